@@ -0,0 +1,158 @@
+// Compares the reroll engine with the previous one, which collected the buffs into hash sets and
+// vectors on every reroll.
+//
+// cargo bench --bench reroll
+
+use std::collections::HashSet;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use strum::IntoEnumIterator;
+
+use nikke_overload_simulation::{Buff, BuffSet, Simulation, SlotState};
+
+// The previous engine, kept here as the baseline.
+mod hash_set {
+    use super::*;
+
+    pub struct Simulation<'a> {
+        pub custom_modules: u32,
+        buffs: Vec<SlotState>,
+        rng: &'a mut StdRng,
+    }
+
+    fn choose(buffs: &[Buff], rng: &mut StdRng) -> Buff {
+        let sum: f64 = buffs.iter().map(|b| b.percent()).sum();
+        let value = rng.gen_range(0.0..sum);
+        let mut accum = 0.0;
+        for b in buffs.iter() {
+            accum += b.percent();
+            if value < accum {
+                return *b;
+            }
+        }
+        unreachable!()
+    }
+
+    impl<'a> Simulation<'a> {
+        pub fn new(rng: &'a mut StdRng) -> Self {
+            Simulation {
+                custom_modules: 0,
+                buffs: vec![SlotState::None, SlotState::None, SlotState::None],
+                rng,
+            }
+        }
+
+        fn locked_count(&self) -> u32 {
+            self.buffs
+                .iter()
+                .filter(|state| matches!(state, SlotState::Locked(_)))
+                .count() as u32
+        }
+
+        pub fn reroll(&mut self) {
+            self.custom_modules += self.locked_count() + 1;
+            let locked_buffs: HashSet<&Buff> = self
+                .buffs
+                .iter()
+                .filter_map(|item| match item {
+                    SlotState::Locked(buff) => Some(buff),
+                    _ => None,
+                })
+                .collect();
+            let mut buffs: Vec<Buff> = Buff::iter().collect();
+            buffs.retain(|item| !locked_buffs.contains(item));
+
+            let second = self.rng.gen_range(0.0..100.0) < 50.0;
+            let third = self.rng.gen_range(0.0..100.0) < 30.0;
+            for (pos, shown) in [true, second, third].into_iter().enumerate() {
+                if shown && !matches!(self.buffs[pos], SlotState::Locked(_)) {
+                    let buff = choose(&buffs, self.rng);
+                    buffs.retain(|b| b != &buff);
+                    self.buffs[pos] = SlotState::Free(buff);
+                }
+            }
+        }
+
+        pub fn reroll_until_all_found_with_locking(&mut self, want: &HashSet<Buff>) {
+            loop {
+                self.reroll();
+                let mut found = HashSet::new();
+                for b in want.iter() {
+                    let pos = self.buffs.iter().position(|state| {
+                        matches!(state, SlotState::Free(x) | SlotState::Locked(x) if x == b)
+                    });
+                    if let Some(pos) = pos {
+                        if let SlotState::Free(buff) = self.buffs[pos] {
+                            if self.locked_count() < 2 {
+                                self.custom_modules += self.locked_count() + 2;
+                                self.buffs[pos] = SlotState::Locked(buff);
+                            }
+                        }
+                        found.insert(*b);
+                    }
+                }
+                if want.eq(&found) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn reroll_until_all_found_with_locking(sim: &mut Simulation<&mut StdRng>, want: BuffSet) {
+    loop {
+        sim.reroll();
+        let found = sim.shown_buffs().intersection(want);
+        for buff in found.iter() {
+            let pos = sim.position_of(&buff).unwrap();
+            sim.lock(pos);
+        }
+        if found == want {
+            break;
+        }
+    }
+}
+
+fn reroll(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reroll");
+    group.bench_function("hash set", |b| {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut sim = hash_set::Simulation::new(&mut rng);
+        b.iter(|| sim.reroll());
+    });
+    group.bench_function("bit mask", |b| {
+        let mut sim = Simulation::with_rng(StdRng::seed_from_u64(1));
+        b.iter(|| sim.reroll());
+    });
+    group.finish();
+}
+
+// A whole sample of a module usage suite: lock the wanted buffs as they appear until all of them
+// are shown.
+fn reroll_until_all_found(c: &mut Criterion) {
+    let want = [Buff::Attack, Buff::Elemental, Buff::CritDamage];
+    let mut group = c.benchmark_group("reroll until three buffs found with locking");
+    group.bench_function("hash set", |b| {
+        let want = HashSet::from_iter(want);
+        let mut rng = StdRng::seed_from_u64(1);
+        b.iter(|| {
+            let mut sim = hash_set::Simulation::new(&mut rng);
+            sim.reroll_until_all_found_with_locking(&want);
+            sim.custom_modules
+        });
+    });
+    group.bench_function("bit mask", |b| {
+        let want = BuffSet::from_iter(want);
+        let mut rng = StdRng::seed_from_u64(1);
+        b.iter(|| {
+            let mut sim = Simulation::with_rng(&mut rng);
+            reroll_until_all_found_with_locking(&mut sim, want);
+            sim.custom_modules()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, reroll, reroll_until_all_found);
+criterion_main!(benches);
@@ -0,0 +1,385 @@
+// Advice for a roll in the game: whether to reroll, lock one of its buffs or stop. Each choice is
+// worked out by the exact solver, which makes the best choice after every roll that follows. When
+// the solver does not apply, each choice is simulated from the roll instead, locking the wanted
+// buffs as soon as they appear afterwards.
+
+use itertools::Itertools;
+
+use crate::report::{ReportRow, RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::simulation::{buffs_to_string, SimulationResult, Strategy, SuiteConfig};
+use crate::solver::{locked_count, locks, Outlook, Solver};
+use crate::{BuffSet, SlotState};
+
+/// What to do with the roll.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Reroll,
+    /// Lock the buff in the slot (0 based).
+    Lock(usize),
+    /// Keep the roll and stop rolling.
+    Stop,
+}
+
+impl Action {
+    pub fn name(&self) -> String {
+        match self {
+            Action::Reroll => "reroll".to_string(),
+            Action::Lock(position) => format!("lock slot {}", position + 1),
+            Action::Stop => "stop".to_string(),
+        }
+    }
+}
+
+fn shown_buffs(slots: &[SlotState; 3]) -> BuffSet {
+    slots
+        .iter()
+        .filter_map(|slot| match slot {
+            SlotState::Free(buff) | SlotState::Locked(buff) => Some(*buff),
+            SlotState::None => None,
+        })
+        .collect()
+}
+
+/// Checks that |slots| is a roll that the game can show with |rules|: no buff is shown twice, the
+/// slots that never get a buff have none, and no more buffs are locked than the rules allow.
+pub fn check_slots(rules: &Rules, slots: &[SlotState; 3]) -> Result<(), String> {
+    let buffs = slots.iter().filter_map(|slot| match slot {
+        SlotState::Free(buff) | SlotState::Locked(buff) => Some(*buff),
+        SlotState::None => None,
+    });
+    if let Some(buff) = buffs.duplicates().next() {
+        return Err(format!("{buff:?} is shown more than once"));
+    }
+    if let Some(pos) =
+        (0..3).find(|pos| slots[*pos] != SlotState::None && rules.slot_percent(*pos) == 0.0)
+    {
+        return Err(format!("slot {} never gets a buff", pos + 1));
+    }
+    if locked_count(locks(slots)) as usize > rules.lock_costs.len() {
+        return Err(format!(
+            "at most {} buffs can be locked",
+            rules.lock_costs.len()
+        ));
+    }
+    Ok(())
+}
+
+/// The actions that can be taken from |slots| with a |stock| of modules, or unlimited modules:
+/// rerolling and locking each buff that is not locked, if the rules allow it and the stock can
+/// pay for it, and stopping.
+pub fn actions(rules: &Rules, slots: &[SlotState; 3], stock: Option<u32>) -> Vec<Action> {
+    let affordable = |cost: u32| stock.is_none_or(|stock| cost <= stock);
+    let locked = locked_count(locks(slots));
+    let mut actions = vec![];
+    if rules.reroll_cost(locked).is_some_and(affordable) {
+        actions.push(Action::Reroll);
+    }
+    if rules.lock_cost(locked).is_some_and(affordable) {
+        actions.extend(
+            slots
+                .iter()
+                .enumerate()
+                .filter(|(_, slot)| matches!(slot, SlotState::Free(_)))
+                .map(|(pos, _)| Action::Lock(pos)),
+        );
+    }
+    actions.push(Action::Stop);
+    actions
+}
+
+// The slots and the stock after |action|, and the modules that it costs.
+fn after(
+    rules: &Rules,
+    slots: [SlotState; 3],
+    stock: Option<u32>,
+    action: Action,
+) -> ([SlotState; 3], Option<u32>, u32) {
+    let Action::Lock(position) = action else {
+        return (slots, stock, 0);
+    };
+    let cost = rules.lock_cost(locked_count(locks(&slots))).unwrap();
+    let mut slots = slots;
+    if let SlotState::Free(buff) = slots[position] {
+        slots[position] = SlotState::Locked(buff);
+    }
+    (slots, stock.map(|stock| stock - cost), cost)
+}
+
+// The outlook of the runs of |result| with a |stock| of modules. The runs that need more modules
+// than the stock fail, and are counted as using all of it.
+fn simulated_outlook(result: &SimulationResult, stock: Option<u32>) -> Outlook {
+    let Some(stock) = stock else {
+        return Outlook {
+            success: 1.0,
+            modules: result.mean(),
+        };
+    };
+    let stock = stock as u64;
+    let mut modules = 0.0;
+    let mut previous = 0.0;
+    for (value, within) in result.cdf() {
+        modules += (within - previous) * value.min(stock) as f64;
+        previous = within;
+    }
+    Outlook {
+        success: result.runs_within(stock) as f64 / result.samples() as f64,
+        modules,
+    }
+}
+
+/// The outlook of each action from a roll, and the recommended one.
+pub struct Advice {
+    pub outlooks: Vec<(Action, Outlook)>,
+    /// The action that is the most likely to get the buffs, and the cheapest of those.
+    pub best: Action,
+    /// Whether the exact solver worked out the outlooks, rather than simulations.
+    pub exact: bool,
+}
+
+/// The chance of getting all of |want| after each action from |slots| with a |stock| of modules,
+/// or unlimited modules, and the modules that each is expected to use, including the cost of
+/// locking.
+pub fn advice(
+    config: &SuiteConfig,
+    slots: [SlotState; 3],
+    want: BuffSet,
+    stock: Option<u32>,
+) -> Advice {
+    let rules = &config.rules;
+    let done = shown_buffs(&slots).is_superset(want);
+    let mut solver = Solver::new(rules.clone(), want, stock);
+    let sampler = config.sampler("advice");
+    let outlooks: Vec<(Action, Outlook)> = actions(rules, &slots, stock)
+        .into_iter()
+        .map(|action| {
+            let (slots, stock, cost) = after(rules, slots, stock, action);
+            let outlook = match (action, &mut solver) {
+                (Action::Stop, _) if done => Outlook::DONE,
+                (Action::Stop, _) => Outlook::STOP,
+                (Action::Reroll, Some(solver)) => solver.reroll(&slots, stock),
+                (Action::Lock(_), Some(solver)) => solver.best(&slots, stock),
+                // The simulation would never finish.
+                _ if rules.check_shown(locks(&slots), want).is_err() => Outlook::STOP,
+                _ => {
+                    let mut result = SimulationResult::new();
+                    result.buffs = want.iter().collect();
+                    sampler.run(&mut result, |sim| {
+                        sim.set_slots(slots);
+                        Strategy::LockImmediately.run(sim, want)
+                    });
+                    simulated_outlook(&result, stock)
+                }
+            };
+            (
+                action,
+                Outlook {
+                    success: outlook.success,
+                    modules: outlook.modules + cost as f64,
+                },
+            )
+        })
+        .collect();
+
+    let best = outlooks
+        .iter()
+        .fold(
+            None,
+            |best: Option<&(Action, Outlook)>, candidate| match best {
+                Some(best) if !candidate.1.is_better_than(&best.1) => Some(best),
+                _ => Some(candidate),
+            },
+        )
+        .map(|(action, _)| *action)
+        .unwrap();
+    Advice {
+        outlooks,
+        best,
+        exact: solver.is_some(),
+    }
+}
+
+/// The advice for |slots| as a report. The description recommends the best action.
+pub fn advise(
+    config: &SuiteConfig,
+    slots: [SlotState; 3],
+    want: BuffSet,
+    stock: Option<u32>,
+) -> SuiteReport {
+    let advice = advice(config, slots, want, stock);
+    let buffs: Vec<_> = want.iter().collect();
+    let within = match stock {
+        Some(stock) => format!("within the stock of {stock} custom modules"),
+        None => "with unlimited custom modules".to_string(),
+    };
+    let method = if advice.exact {
+        "making the best choice after every roll that follows, as calculated by the exact solver"
+            .to_string()
+    } else {
+        format!(
+            "locking the wanted buffs as soon as they appear afterwards, as simulated: {} Runs \
+            that need more modules than the stock fail and count the whole stock",
+            config.modules_stop.describe("modules")
+        )
+    };
+    let mut report = SuiteReport::new(
+        "advice",
+        &format!(
+            "Recommended: {}. The following table shows the chance of getting {} {within} \
+            after each action, and the custom modules expected to be used, including the cost of \
+            locking, when {method}.",
+            advice.best.name(),
+            buffs_to_string(&config.rules, buffs.iter()),
+        ),
+        &["action"],
+    );
+    for (action, outlook) in advice.outlooks {
+        report.rows.push(ReportRow {
+            labels: vec![action.name()],
+            result: RowResult::Outlook(outlook),
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use crate::sampling::StopRule;
+    use crate::solver::MAX_STOCK;
+    use crate::Buff;
+
+    use super::*;
+
+    fn outlook(report: &SuiteReport, row: usize) -> Outlook {
+        match report.rows[row].result {
+            RowResult::Outlook(outlook) => outlook,
+            _ => panic!("not an outlook"),
+        }
+    }
+
+    // Locking Attack makes every reroll cost 2 modules, which costs more than rerolling Attack
+    // away when only Elemental is missing.
+    #[test]
+    fn cheapest_action_is_recommended() {
+        let config = SuiteConfig::default();
+        let slots = [
+            SlotState::Free(Buff::Attack),
+            SlotState::Free(Buff::Defense),
+            SlotState::None,
+        ];
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+
+        assert_eq!(
+            actions(&config.rules, &slots, None),
+            vec![
+                Action::Reroll,
+                Action::Lock(0),
+                Action::Lock(1),
+                Action::Stop
+            ]
+        );
+        let report = advise(&config, slots, want, None);
+        assert!(report.description.starts_with("Recommended: reroll."));
+        assert_eq!(report.rows[1].labels, vec!["lock slot 1"]);
+        assert_eq!(outlook(&report, 0).success, 1.0);
+        assert!(outlook(&report, 0).modules < outlook(&report, 1).modules);
+        assert_eq!(outlook(&report, 3), Outlook::STOP);
+    }
+
+    #[test]
+    fn stock_limits_the_actions() {
+        let config = SuiteConfig::default();
+        let slots = [
+            SlotState::Free(Buff::Attack),
+            SlotState::None,
+            SlotState::None,
+        ];
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        assert_eq!(
+            actions(&config.rules, &slots, Some(1)),
+            vec![Action::Reroll, Action::Stop]
+        );
+        let report = advise(&config, slots, want, Some(0));
+        assert!(report.description.starts_with("Recommended: stop."));
+        assert_eq!(report.rows.len(), 1);
+
+        // With the buffs shown there is nothing left to do.
+        let slots = [
+            SlotState::Free(Buff::Attack),
+            SlotState::Free(Buff::Elemental),
+            SlotState::None,
+        ];
+        let report = advise(&config, slots, want, Some(10));
+        assert!(report.description.starts_with("Recommended: stop."));
+        assert_eq!(outlook(&report, 3), Outlook::DONE);
+    }
+
+    #[test]
+    fn large_stocks_are_simulated() {
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(2000),
+            seed: 7,
+            ..Default::default()
+        };
+        let slots = [
+            SlotState::Free(Buff::Attack),
+            SlotState::Free(Buff::Defense),
+            SlotState::None,
+        ];
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        let report = advise(&config, slots, want, Some(MAX_STOCK + 1));
+        assert!(report.description.starts_with("Recommended: reroll."));
+        assert!(report.description.contains("as simulated"));
+        assert_eq!(outlook(&report, 0).success, 1.0);
+    }
+
+    #[test]
+    fn impossible_rolls_are_rejected() {
+        let rules = Rules::standard();
+        let all_locked = [
+            SlotState::Locked(Buff::Attack),
+            SlotState::Locked(Buff::Defense),
+            SlotState::Locked(Buff::Elemental),
+        ];
+        assert!(check_slots(&rules, &all_locked).is_err());
+        // Nothing is left to reroll or lock.
+        assert_eq!(actions(&rules, &all_locked, None), vec![Action::Stop]);
+        let twice = [
+            SlotState::Free(Buff::Attack),
+            SlotState::Free(Buff::Attack),
+            SlotState::None,
+        ];
+        assert!(check_slots(&rules, &twice).is_err());
+
+        let slots = [
+            SlotState::Locked(Buff::Attack),
+            SlotState::Locked(Buff::Defense),
+            SlotState::Free(Buff::Elemental),
+        ];
+        assert!(check_slots(&rules, &slots).is_ok());
+        let no_third_slot = Rules::from_toml("test", "third_slot_percent = 0\n").unwrap();
+        assert!(check_slots(&no_third_slot, &slots).is_err());
+    }
+
+    // Without a third slot, three buffs can never be shown, and simulating them would never end.
+    #[test]
+    fn buffs_without_enough_slots_are_not_simulated() {
+        let config = SuiteConfig {
+            rules: Arc::new(Rules::from_toml("test", "third_slot_percent = 0\n").unwrap()),
+            ..Default::default()
+        };
+        let slots = [
+            SlotState::Free(Buff::Attack),
+            SlotState::None,
+            SlotState::None,
+        ];
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental, Buff::Defense]);
+        let report = advise(&config, slots, want, Some(MAX_STOCK + 1));
+        for row in 0..report.rows.len() {
+            assert_eq!(outlook(&report, row).success, 0.0);
+        }
+    }
+}
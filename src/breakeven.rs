@@ -0,0 +1,360 @@
+// Break-even points of waiting for a wanted buff in a slot. Waiting rerolls without locking until
+// the buff appears in the slot, locks it, then locks the other wanted buffs as soon as they appear.
+// Plain rerolling locks the wanted buffs as soon as they appear, in any slot. The rolls do not
+// remember, so the modules already spent do not change what either way still needs: going forward,
+// waiting beats plain rerolling always or never. What the spent modules decide is whether waiting
+// was worth it once the buff appeared, i.e. whether the modules spent until then and those still
+// needed after are at most what plain rerolling needs from the start. The expected modules are
+// calculated from the chances of all the rolls, like the solver does, instead of simulated.
+
+use std::collections::HashMap;
+
+use crate::coverage::{self, Coverage};
+use crate::importance;
+use crate::report::{ReportRow, RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::simulation::{buff_to_string, buffs_to_string, lock_cost_note, Strategy, SuiteConfig};
+use crate::solver::{locked_count, Locks};
+use crate::{Buff, BuffSet};
+
+/// The expected custom modules of waiting for a buff in a slot and of plain rerolling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BreakEven {
+    /// Plain rerolling from the start.
+    pub plain: f64,
+    /// Waiting for the buff in the slot from the start.
+    pub waited: f64,
+    /// Once the buff appeared in the slot, locking it included.
+    pub after: f64,
+    /// The chance (0 to 1) that a roll shows the buff in the slot.
+    pub chance: f64,
+    /// The cost of a reroll while waiting, i.e. without locked buffs.
+    pub reroll_cost: u32,
+}
+
+impl BreakEven {
+    /// The most modules spent until the buff appeared for which waiting was worth it. Negative if
+    /// it never was.
+    pub fn modules(&self) -> f64 {
+        self.plain - self.after
+    }
+
+    /// The most rolls until the buff appeared, that one included, for which waiting was worth it.
+    pub fn rolls(&self) -> u32 {
+        (self.modules() / self.reroll_cost as f64).floor().max(0.0) as u32
+    }
+
+    /// The chance (0 to 1) that the buff appears in the slot within |rolls|.
+    pub fn in_time(&self) -> f64 {
+        1.0 - (1.0 - self.chance).powi(self.rolls() as i32)
+    }
+}
+
+// The expected modules of locking the wanted buffs as soon as they appear, after each of the locks
+// that are solved.
+struct LockImmediately<'a> {
+    rules: &'a Rules,
+    want: BuffSet,
+    expected: HashMap<Locks, f64>,
+}
+
+impl LockImmediately<'_> {
+    // The expected modules of rerolling after |locks| until all the wanted buffs are shown.
+    fn expected(&mut self, locks: Locks) -> f64 {
+        if let Some(expected) = self.expected.get(&locks) {
+            return *expected;
+        }
+        let mut rolls = vec![];
+        importance::for_each_roll(self.rules, locks, |slots, probability| {
+            rolls.push((slots, probability))
+        });
+        // The rolls that lock nothing and do not show all the wanted buffs leave the locks as
+        // they are, which makes the expected modules a fixed point.
+        let (mut unchanged, mut modules) = (0.0, 0.0);
+        for (slots, probability) in rolls {
+            match self.step(locks, slots) {
+                Step::Done(cost) => modules += probability * cost,
+                Step::Unchanged => unchanged += probability,
+                Step::Locked(cost, next) => modules += probability * (cost + self.expected(next)),
+            }
+        }
+        let reroll_cost = self.rules.reroll_cost(locked_count(locks)).unwrap() as f64;
+        let expected = (reroll_cost + modules) / (1.0 - unchanged);
+        self.expected.insert(locks, expected);
+        expected
+    }
+
+    // Locks the wanted buffs that |slots| shows outside of |locks|, as many as can be locked. Like
+    // the simulations, the buffs are locked in declaration order, which decides the slots that
+    // stay free when not all of them can be locked.
+    fn step(&self, locks: Locks, slots: Locks) -> Step {
+        let shown: BuffSet = locks.iter().chain(&slots).flatten().copied().collect();
+        let mut next = locks;
+        let mut modules = 0.0;
+        for buff in self.want.iter() {
+            let Some(pos) = (0..3).find(|pos| locks[*pos].is_none() && slots[*pos] == Some(buff))
+            else {
+                continue;
+            };
+            if let Some(cost) = self.rules.lock_cost(locked_count(next)) {
+                modules += cost as f64;
+                next[pos] = Some(buff);
+            }
+        }
+        if shown.is_superset(self.want) {
+            Step::Done(modules)
+        } else if next == locks {
+            Step::Unchanged
+        } else {
+            Step::Locked(modules, next)
+        }
+    }
+
+    // The modules expected after |slots| is shown with |locks|, locking included.
+    fn after(&mut self, locks: Locks, slots: Locks) -> f64 {
+        match self.step(locks, slots) {
+            Step::Done(cost) => cost,
+            Step::Unchanged => self.expected(locks),
+            Step::Locked(cost, next) => cost + self.expected(next),
+        }
+    }
+}
+
+// What a roll does to the locks.
+enum Step {
+    /// All the wanted buffs are shown, after locking for the modules.
+    Done(f64),
+    /// Nothing is locked, and some wanted buffs are still missing.
+    Unchanged,
+    /// The modules locked the wanted buffs that the roll showed, which makes the new locks.
+    Locked(f64, Locks),
+}
+
+/// The break-even point of waiting for |buff|, one of |want|, in the slot at |position| (0 based)
+/// with |rules|, or why the buffs cannot be got that way.
+pub fn break_even(
+    rules: &Rules,
+    want: BuffSet,
+    buff: Buff,
+    position: usize,
+) -> Result<BreakEven, String> {
+    assert!(want.contains(buff), "{buff:?} is not wanted");
+    // Waiting locks the buff, so the others need the slots that are left.
+    Strategy::Waited(buff, position).check(rules, &want.iter().collect::<Vec<_>>())?;
+    let Some(lock_cost) = rules.lock_cost(0) else {
+        return Err(format!(
+            "{buff:?} cannot be locked, the rules allow no locks"
+        ));
+    };
+    let mut chain = LockImmediately {
+        rules,
+        want,
+        expected: HashMap::new(),
+    };
+    let mut shown = vec![];
+    importance::for_each_roll(rules, [None; 3], |slots, probability| {
+        if slots[position] == Some(buff) {
+            shown.push((slots, probability));
+        }
+    });
+    let chance: f64 = shown.iter().map(|(_, probability)| probability).sum();
+    let mut locks = [None; 3];
+    locks[position] = Some(buff);
+    let mut after = 0.0;
+    for (slots, probability) in shown {
+        after += probability / chance * (lock_cost as f64 + chain.after(locks, slots));
+    }
+    let reroll_cost = rules.reroll_cost(0).unwrap();
+    Ok(BreakEven {
+        plain: chain.expected([None; 3]),
+        waited: reroll_cost as f64 / chance + after,
+        after,
+        chance,
+        reroll_cost,
+    })
+}
+
+fn break_even_report(rules: &Rules, name: &str) -> SuiteReport {
+    SuiteReport::new(
+        name,
+        &format!(
+            "The following table shows when waiting for a desired buff in a slot is worth it. \
+        Waiting rerolls without locking until the buff appears in the slot, then locks it and \
+        locks the other desired buffs as soon as they appear. Plain rerolling locks the desired \
+        buffs as soon as they appear, in any slot. {} The rolls do not remember, so the \
+        modules spent so far do not change the modules still needed: waiting from any point costs \
+        what it costs from the start, and beats plain rerolling always or never. Once the buff \
+        appeared in the slot, waiting was worth it if it appeared within the break-even number of \
+        modules, i.e. if those and the modules still needed after it are at most what plain \
+        rerolling needs from the start. The last column is the chance that it appears in time. \
+        The expected modules are calculated exactly from the chances of every roll.",
+            lock_cost_note(rules)
+        ),
+        &["waited for", "slot", "other buffs"],
+    )
+}
+
+// Adds the row of waiting for |buff| in the slot at |position|, or tells why it cannot be got.
+fn push_row(
+    report: &mut SuiteReport,
+    rules: &Rules,
+    want: &[Buff],
+    buff: Buff,
+    position: usize,
+) -> Result<(), String> {
+    let point = break_even(
+        rules,
+        BuffSet::from_iter(want.iter().copied()),
+        buff,
+        position,
+    )?;
+    let others: Vec<Buff> = want.iter().copied().filter(|b| *b != buff).collect();
+    report.rows.push(ReportRow {
+        labels: vec![
+            buff_to_string(rules, buff),
+            (position + 1).to_string(),
+            if others.is_empty() {
+                "-".to_string()
+            } else {
+                buffs_to_string(rules, others.iter())
+            },
+        ],
+        result: RowResult::BreakEven(point),
+    });
+    Ok(())
+}
+
+/// The break-even points of waiting for a buff in the |slots| (0 based), for each pattern of
+/// chances of two and three buffs, the buff waited for first. The goals that the rules do not let
+/// be got that way, e.g. without locks, are left out.
+pub fn suite_break_even(config: &SuiteConfig, coverage: Coverage, slots: &[usize]) -> SuiteReport {
+    let mut report = break_even_report(&config.rules, "Break-even of waiting for a buff in a slot");
+    let mut left_out = false;
+    for size in [2, 3] {
+        for case in coverage::cases(&config.rules, size, true, coverage) {
+            for position in slots {
+                left_out |= push_row(
+                    &mut report,
+                    &config.rules,
+                    &case.want(),
+                    case.locked.unwrap(),
+                    *position,
+                )
+                .is_err();
+            }
+        }
+    }
+    if left_out {
+        report.description += " The buffs that cannot be got this way with the rules, e.g. \
+            because they cannot be locked, are left out.";
+    }
+    report
+}
+
+/// The break-even points of waiting for each of |want| in the |slots| (0 based), e.g. for buffs
+/// picked on the command line, or why they cannot be got that way.
+pub fn suite_want_break_even(
+    config: &SuiteConfig,
+    want: &[Buff],
+    slots: &[usize],
+) -> Result<SuiteReport, String> {
+    let mut report = break_even_report(
+        &config.rules,
+        &format!(
+            "Break-even of waiting for {}",
+            buffs_to_string(&config.rules, want.iter())
+        ),
+    );
+    for buff in want {
+        for position in slots {
+            push_row(&mut report, &config.rules, want, *buff, *position)?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use more_asserts::assert_lt;
+
+    use super::*;
+    use crate::importance::exact_probability;
+    use crate::sampling::StopRule;
+    use crate::simulation::{simulate_strategy, Strategy};
+
+    // A single wanted buff takes a geometric number of rerolls without locking, and only needs to
+    // be locked once it appeared in the slot.
+    #[test]
+    fn single_buff_is_geometric() {
+        let rules = Rules::standard();
+        let want = BuffSet::from_iter([Buff::Attack]);
+        let shown = exact_probability(&rules, |slots| slots.contains(&Some(Buff::Attack)));
+        let in_third = exact_probability(&rules, |slots| slots[2] == Some(Buff::Attack));
+        let point = break_even(&rules, want, Buff::Attack, 2).unwrap();
+        assert_lt!((point.plain - (1.0 / shown + 2.0)).abs(), 1e-9);
+        assert_lt!((point.chance - in_third).abs(), 1e-12);
+        assert_lt!((point.after - 2.0).abs(), 1e-9);
+        assert_lt!((point.waited - (1.0 / in_third + 2.0)).abs(), 1e-9);
+        assert_eq!(point.rolls(), (1.0 / shown).floor() as u32);
+    }
+
+    // With a single lock, the last two wanted buffs must be shown on the same roll.
+    #[test]
+    fn expected_modules_match_the_simulations() {
+        let few_locks = Rules::from_toml("test", "lock_costs = [2]\nreroll_costs = [1, 2]\n");
+        for (rules, position) in [(Rules::standard(), 1), (Arc::new(few_locks.unwrap()), 2)] {
+            let config = SuiteConfig {
+                modules_stop: StopRule::fixed(10000),
+                seed: 7,
+                rules,
+                ..Default::default()
+            };
+            let sampler = config.sampler("test");
+            let want = [Buff::Attack, Buff::Elemental, Buff::MaxAmmo];
+            let point = break_even(
+                &config.rules,
+                BuffSet::from_iter(want),
+                Buff::MaxAmmo,
+                position,
+            )
+            .unwrap();
+            for (strategy, expected) in [
+                (Strategy::LockImmediately, point.plain),
+                (Strategy::Waited(Buff::MaxAmmo, position), point.waited),
+            ] {
+                let result = simulate_strategy(strategy, &want, &sampler);
+                assert_lt!(
+                    (result.mean() - expected).abs(),
+                    4.0 * result.standard_error(),
+                    "{strategy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn goals_that_cannot_be_got_are_rejected() {
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental, Buff::MaxAmmo]);
+        let rules = |text: &str| Rules::from_toml("test", text).unwrap();
+        let no_locks = rules("lock_costs = []\nreroll_costs = [1]\n");
+        assert!(break_even(&no_locks, want, Buff::Attack, 2).is_err());
+        let no_third_slot = rules("third_slot_percent = 0\n");
+        assert!(break_even(&no_third_slot, want, Buff::Attack, 0).is_err());
+        let two = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        assert!(break_even(&no_third_slot, two, Buff::Attack, 2).is_err());
+        assert!(break_even(&no_third_slot, two, Buff::Attack, 1).is_ok());
+        let four = want.iter().chain([Buff::Defense]).collect();
+        assert!(break_even(&Rules::standard(), four, Buff::Attack, 0).is_err());
+
+        let config = SuiteConfig {
+            rules: Arc::new(no_locks),
+            ..Default::default()
+        };
+        assert!(suite_break_even(&config, Coverage::Representative, &[0])
+            .rows
+            .is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+// Parsing buffs from user input: the enum names, the labels in the game and short aliases, in any
+// case and with any spacing or punctuation, e.g. "ChargeSpeed", "Increase Charge Speed",
+// "charge-speed" or "cspd".
+
+use std::{error::Error, fmt, str::FromStr};
+
+use itertools::Itertools;
+use strum::IntoEnumIterator;
+
+use crate::Buff;
+
+// How similar (Jaro-Winkler) an unknown name must be to a name of a buff to suggest it.
+const MIN_SIMILARITY: f64 = 0.8;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+// Lower case letters and digits only, without the "Increase" that starts the labels.
+fn normalize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    match name.strip_prefix("increase") {
+        Some(rest) if !rest.is_empty() => rest.to_string(),
+        _ => name,
+    }
+}
+
+// The normalized names of |buff|.
+fn names(buff: Buff) -> impl Iterator<Item = String> {
+    [format!("{buff:?}"), buff.label().to_string()]
+        .into_iter()
+        .chain(buff.aliases().iter().map(|alias| alias.to_string()))
+        .map(|name| normalize(&name))
+}
+
+/// A name that is not a buff, with the buffs whose names are close to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseBuffError {
+    name: String,
+    suggestions: Vec<Buff>,
+}
+
+impl ParseBuffError {
+    /// The buffs that the name may have meant, most similar first.
+    pub fn suggestions(&self) -> &[Buff] {
+        &self.suggestions
+    }
+}
+
+impl fmt::Display for ParseBuffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown buff '{}'", self.name)?;
+        if self.suggestions.is_empty() {
+            write!(
+                f,
+                ", the buffs are {}",
+                Buff::iter().map(|buff| format!("{buff:?}")).join(", ")
+            )
+        } else {
+            let mut suggestions: Vec<String> = self
+                .suggestions
+                .iter()
+                .map(|buff| format!("{buff:?}"))
+                .collect();
+            let last = suggestions.pop().unwrap();
+            if suggestions.is_empty() {
+                write!(f, ", did you mean {last}?")
+            } else {
+                write!(f, ", did you mean {} or {last}?", suggestions.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for ParseBuffError {}
+
+impl FromStr for Buff {
+    type Err = ParseBuffError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let normalized = normalize(name);
+        if let Some(buff) = Buff::iter().find(|buff| names(*buff).any(|n| n == normalized)) {
+            return Ok(buff);
+        }
+
+        let suggestions = Buff::iter()
+            .map(|buff| {
+                let similarity = names(buff)
+                    .map(|n| strsim::jaro_winkler(&normalized, &n))
+                    .fold(0.0, f64::max);
+                (buff, similarity)
+            })
+            .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+            .sorted_by(|a, b| b.1.total_cmp(&a.1))
+            .take(MAX_SUGGESTIONS)
+            .map(|(buff, _)| buff)
+            .collect();
+        Err(ParseBuffError {
+            name: name.to_string(),
+            suggestions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn parses_names_labels_and_aliases() {
+        for (name, buff) in [
+            ("ChargeSpeed", Buff::ChargeSpeed),
+            ("chargespeed", Buff::ChargeSpeed),
+            ("charge_speed", Buff::ChargeSpeed),
+            ("Increase Charge Speed", Buff::ChargeSpeed),
+            ("Elemental Damage Dealt", Buff::Elemental),
+            ("Increase Max Ammunition Capacity", Buff::MaxAmmo),
+            ("atk", Buff::Attack),
+            ("ATK", Buff::Attack),
+            ("elem", Buff::Elemental),
+            ("cdmg", Buff::CritDamage),
+            ("ammo", Buff::MaxAmmo),
+            ("DEF", Buff::Defense),
+        ] {
+            assert_eq!(name.parse::<Buff>(), Ok(buff), "{name}");
+        }
+    }
+
+    // A name must not be shared, or it would parse as whichever buff comes first.
+    #[test]
+    fn names_are_unique() {
+        let all: Vec<String> = Buff::iter().flat_map(|buff| names(buff).unique()).collect();
+        assert_eq!(all.iter().unique().count(), all.len());
+    }
+
+    #[test]
+    fn unknown_names_get_suggestions() {
+        let error = "Atack".parse::<Buff>().unwrap_err();
+        assert_eq!(error.suggestions(), &[Buff::Attack]);
+        assert_eq!(
+            error.to_string(),
+            "unknown buff 'Atack', did you mean Attack?"
+        );
+
+        let error = "charge".parse::<Buff>().unwrap_err();
+        assert_eq!(error.suggestions().len(), 2);
+
+        let error = "xyz".parse::<Buff>().unwrap_err();
+        assert!(error.suggestions().is_empty());
+        assert!(error
+            .to_string()
+            .contains("the buffs are Elemental, HitRate"));
+    }
+}
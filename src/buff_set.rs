@@ -0,0 +1,241 @@
+// Sets of buffs as bit masks, and the alias tables for drawing from them, so that rerolling does
+// not allocate and every draw takes constant time.
+
+use rand::Rng;
+use strum::IntoEnumIterator;
+
+use crate::rules::Rules;
+use crate::Buff;
+
+pub(crate) const BUFF_COUNT: usize = 9;
+
+/// A set of buffs. Bit i is the i-th buff in declaration order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BuffSet(u16);
+
+impl BuffSet {
+    pub const EMPTY: BuffSet = BuffSet(0);
+    pub const ALL: BuffSet = BuffSet((1 << BUFF_COUNT) - 1);
+
+    fn bit(buff: Buff) -> u16 {
+        1 << buff as u16
+    }
+
+    pub fn contains(self, buff: Buff) -> bool {
+        self.0 & Self::bit(buff) != 0
+    }
+
+    pub fn insert(&mut self, buff: Buff) {
+        self.0 |= Self::bit(buff);
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_superset(self, other: BuffSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersection(self, other: BuffSet) -> BuffSet {
+        BuffSet(self.0 & other.0)
+    }
+
+    /// The buffs that are not in this set.
+    pub fn complement(self) -> BuffSet {
+        BuffSet(!self.0 & Self::ALL.0)
+    }
+
+    /// The buffs in declaration order.
+    pub fn iter(self) -> impl Iterator<Item = Buff> {
+        Buff::iter().filter(move |buff| self.contains(*buff))
+    }
+}
+
+impl FromIterator<Buff> for BuffSet {
+    fn from_iter<I: IntoIterator<Item = Buff>>(buffs: I) -> Self {
+        let mut set = BuffSet::EMPTY;
+        for buff in buffs {
+            set.insert(buff);
+        }
+        set
+    }
+}
+
+// The weight of a buff whose chance is |percent|, in hundredths of a percent, so that the draws
+// are exact integer probabilities.
+fn weight(percent: f64) -> u32 {
+    (percent * 100.0).round() as u32
+}
+
+// A column of an alias table: |buff| is drawn if the draw within the column is below |threshold|,
+// otherwise |alias| is.
+#[derive(Clone, Copy)]
+struct Column {
+    threshold: u32,
+    buff: Buff,
+    alias: Buff,
+}
+
+// Walker's alias method with integers. There is a column for every buff, and every column holds
+// |total| (the sum of the weights of the buffs that are not excluded) out of BUFF_COUNT * |total|.
+// An excluded buff has a weight of 0 so it is always replaced by its alias.
+#[derive(Clone, Copy)]
+struct AliasTable {
+    total: u32,
+    columns: [Column; BUFF_COUNT],
+}
+
+impl AliasTable {
+    // |weights| are the weights of the buffs in declaration order.
+    fn new(weights: &[u32; BUFF_COUNT], excluded: BuffSet) -> Self {
+        let buffs: Vec<Buff> = Buff::iter().collect();
+        // The weight of each buff times BUFF_COUNT, which is its share of all the columns, or 0
+        // if it is excluded.
+        let scaled_weight = |i: usize| {
+            if excluded.contains(buffs[i]) {
+                0
+            } else {
+                weights[i] * BUFF_COUNT as u32
+            }
+        };
+        let total: u32 = excluded
+            .complement()
+            .iter()
+            .map(|buff| weights[buff as usize])
+            .sum();
+        // The share of every column that is still to be placed, in units of 1 / (BUFF_COUNT *
+        // total).
+        let mut scaled: Vec<u32> = (0..BUFF_COUNT).map(scaled_weight).collect();
+        let mut columns: [Column; BUFF_COUNT] = std::array::from_fn(|i| Column {
+            threshold: total,
+            buff: buffs[i],
+            alias: buffs[i],
+        });
+
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..BUFF_COUNT).partition(|i| scaled[*i] < total);
+        while let (Some(less), Some(more)) = (small.pop(), large.pop()) {
+            columns[less].threshold = scaled[less];
+            columns[less].alias = buffs[more];
+            scaled[more] -= total - scaled[less];
+            if scaled[more] < total {
+                small.push(more);
+            } else {
+                large.push(more);
+            }
+        }
+        // The leftovers are exactly full since the shares are integers.
+        AliasTable { total, columns }
+    }
+
+    fn draw<R: Rng>(&self, rng: &mut R) -> Buff {
+        let value = rng.gen_range(0..BUFF_COUNT as u32 * self.total);
+        let column = &self.columns[(value / self.total) as usize];
+        if value % self.total < column.threshold {
+            column.buff
+        } else {
+            column.alias
+        }
+    }
+}
+
+/// The alias tables of all the sets of excluded buffs, for drawing buffs with given chances.
+#[derive(Clone)]
+pub struct AliasTables(Box<[AliasTable; 1 << BUFF_COUNT]>);
+
+impl AliasTables {
+    /// |percents| are the chances of the buffs in declaration order. They are rounded to
+    /// hundredths of a percent and must be positive.
+    pub fn new(percents: &[f64; BUFF_COUNT]) -> Self {
+        let weights = percents.map(weight);
+        assert!(
+            weights.iter().all(|weight| *weight > 0),
+            "the buffs must have a chance"
+        );
+        AliasTables(Box::new(std::array::from_fn(|excluded| {
+            AliasTable::new(&weights, BuffSet(excluded as u16))
+        })))
+    }
+
+    /// Chooses a buff that is not in |excluded|, weighted by the chances.
+    pub fn choose<R: Rng>(&self, excluded: BuffSet, rng: &mut R) -> Buff {
+        assert_ne!(excluded, BuffSet::ALL, "all the buffs are excluded");
+        self.0[excluded.0 as usize].draw(rng)
+    }
+}
+
+/// Chooses a buff that is not in |excluded|, weighted by the standard buff percents.
+pub fn choose<R: Rng>(excluded: BuffSet, rng: &mut R) -> Buff {
+    Rules::standard().choose(excluded, rng)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    // The chance of every buff in every table is exactly its weight out of the total.
+    #[test]
+    fn alias_tables_are_exact() {
+        let percents = Buff::iter()
+            .map(|buff| buff.percent())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        for (excluded, table) in AliasTables::new(&percents).0.iter().enumerate() {
+            let excluded = BuffSet(excluded as u16);
+            if excluded == BuffSet::ALL {
+                continue;
+            }
+            let mut shares = HashMap::new();
+            for column in &table.columns {
+                *shares.entry(column.buff).or_insert(0) += column.threshold;
+                *shares.entry(column.alias).or_insert(0) += table.total - column.threshold;
+            }
+
+            for buff in Buff::iter() {
+                let expected = if excluded.contains(buff) {
+                    0
+                } else {
+                    weight(buff.percent()) * BUFF_COUNT as u32
+                };
+                assert_eq!(shares.get(&buff).copied().unwrap_or(0), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn choose_skips_excluded_buffs() {
+        let excluded = BuffSet::from_iter([Buff::HitRate, Buff::CritRate]);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let chosen: BuffSet = (0..10000).map(|_| choose(excluded, &mut rng)).collect();
+        assert_eq!(chosen, excluded.complement());
+    }
+
+    #[test]
+    fn set_operations() {
+        let set = BuffSet::from_iter([Buff::Defense, Buff::Elemental]);
+
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(Buff::Defense));
+        assert!(!set.contains(Buff::Attack));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![Buff::Elemental, Buff::Defense]
+        );
+        assert!(BuffSet::ALL.is_superset(set));
+        assert!(!set.is_superset(BuffSet::ALL));
+        assert_eq!(set.complement().len(), 7);
+        assert!(set.complement().intersection(set).is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+// SVG charts of the suite results, for the guides.
+
+use std::{error::Error, io, sync::Arc};
+
+use itertools::Itertools;
+use plotters::prelude::*;
+
+use crate::report::{RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::simulation::{buffs_to_string, SimulationResult};
+use crate::Buff;
+
+const SIZE: (u32, u32) = (1024, 640);
+// Roughly the width of a character of the labels, for sizing the label area.
+const LABEL_CHARACTER_WIDTH: u32 = 7;
+
+type ChartResult = Result<String, Box<dyn Error>>;
+
+// The share of the runs that finished within N modules, of a row.
+struct Curve {
+    label: String,
+    // Where the x axis ends so that the long tail does not squash the curve.
+    cutoff: u64,
+    points: Vec<(u64, f64)>,
+}
+
+impl Curve {
+    fn new(label: String, result: &SimulationResult) -> Self {
+        Curve {
+            label,
+            cutoff: result.percentile(99.0),
+            points: result.cdf(),
+        }
+    }
+}
+
+// A row of the suites that measured module usage, kept for comparing the strategies once all the
+// suites finished.
+struct StrategyCurve {
+    // All the buffs that the row wanted, including the locked one.
+    buffs: Vec<Buff>,
+    curve: Curve,
+}
+
+/// A rendered chart.
+pub struct Chart {
+    pub title: String,
+    /// E.g. "first-slot-buff-locked.svg".
+    pub file_name: String,
+    pub svg: String,
+}
+
+/// Renders a chart for every suite, and charts that compare the strategies (i.e. the suites) for
+/// the same wanted buffs once all the suites finished.
+pub struct Charts {
+    // For the chances in the titles.
+    rules: Arc<Rules>,
+    strategies: Vec<StrategyCurve>,
+}
+
+impl Charts {
+    /// The charts of suites that ran with |rules|.
+    pub fn new(rules: Arc<Rules>) -> Self {
+        Charts {
+            rules,
+            strategies: vec![],
+        }
+    }
+
+    /// The chart of |report|. The suites that measured module usage get overlaid CDFs of their
+    /// rows and the others get bar charts of the probabilities.
+    pub fn suite(&mut self, report: &SuiteReport) -> io::Result<Chart> {
+        let mut curves = vec![];
+        let mut bars = vec![];
+        for (i, row) in report.rows.iter().enumerate() {
+            let label = format!("#{} {}", i + 1, row.labels.join(", "));
+            match &row.result {
+                RowResult::Modules(result) => {
+                    let mut buffs = result.buffs.clone();
+                    buffs.extend(result.locked_buff);
+                    buffs.sort();
+                    self.strategies.push(StrategyCurve {
+                        buffs,
+                        curve: Curve::new(report.name.trim_end_matches('.').to_string(), result),
+                    });
+                    curves.push(Curve::new(label, result));
+                }
+                RowResult::Probability(result) => {
+                    let interval = result.confidence_interval();
+                    bars.push((label, result.probability(), interval.lower, interval.upper));
+                }
+                RowResult::Weighted(result) => {
+                    let interval = result.confidence_interval();
+                    bars.push((label, result.probability(), interval.lower, interval.upper));
+                }
+                RowResult::Outlook(outlook) => {
+                    bars.push((label, outlook.success, outlook.success, outlook.success));
+                }
+                RowResult::BreakEven(point) => {
+                    let in_time = point.in_time();
+                    bars.push((label, in_time, in_time, in_time));
+                }
+            }
+        }
+
+        let svg = if bars.is_empty() {
+            draw_cdfs(&report.name, &curves)
+        } else {
+            draw_bars(&report.name, &bars)
+        };
+        Ok(Chart {
+            title: report.name.clone(),
+            file_name: format!("{}.svg", file_stem(&report.name)),
+            svg: svg.map_err(|error| io::Error::other(error.to_string()))?,
+        })
+    }
+
+    /// The charts that compare the strategies. Only the buffs that more than one suite wanted are
+    /// compared.
+    pub fn finish(self) -> io::Result<Vec<Chart>> {
+        let mut charts = vec![];
+        let groups = self
+            .strategies
+            .into_iter()
+            .into_group_map_by(|strategy| strategy.buffs.clone());
+        for (buffs, strategies) in groups.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            if strategies.len() < 2 {
+                continue;
+            }
+            let names = buffs.iter().map(|buff| format!("{buff:?}")).join(" ");
+            let curves: Vec<Curve> = strategies
+                .into_iter()
+                .map(|strategy| strategy.curve)
+                .collect();
+            let title = format!(
+                "Strategies for {}",
+                buffs_to_string(&self.rules, buffs.iter())
+            );
+            let svg =
+                draw_cdfs(&title, &curves).map_err(|error| io::Error::other(error.to_string()))?;
+            charts.push(Chart {
+                title,
+                file_name: format!("strategies-{}.svg", file_stem(&names)),
+                svg,
+            });
+        }
+        Ok(charts)
+    }
+}
+
+// Lower case words separated by dashes, e.g. "first-slot-buff-locked".
+fn file_stem(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .join("-")
+}
+
+fn draw_cdfs(title: &str, curves: &[Curve]) -> ChartResult {
+    let mut svg = String::new();
+    let root = SVGBackend::with_string(&mut svg, SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let cutoff = curves.iter().map(|curve| curve.cutoff).max().unwrap_or(0) + 1;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(64)
+        .build_cartesian_2d(0..cutoff, 0.0..1.0)?;
+    chart
+        .configure_mesh()
+        .x_desc("custom modules used")
+        .y_desc("runs finished within")
+        .y_label_formatter(&|share| format!("{:.0}%", share * 100.0))
+        .draw()?;
+
+    for (i, curve) in curves.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let points = curve
+            .points
+            .iter()
+            .copied()
+            .take_while(|(modules, _)| *modules <= cutoff);
+        chart
+            .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+            .label(&curve.label)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::LowerRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    // The backend borrows |svg| until it is dropped.
+    drop(chart);
+    drop(root);
+    Ok(svg)
+}
+
+// Horizontal bars of the probabilities, with their 95% confidence intervals as error bars. The
+// bars are (label, probability, lower, upper).
+fn draw_bars(title: &str, bars: &[(String, f64, f64, f64)]) -> ChartResult {
+    let mut svg = String::new();
+    let root = SVGBackend::with_string(&mut svg, SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let highest = bars.iter().map(|bar| bar.3).fold(0.0, f64::max);
+    let label_width = bars.iter().map(|bar| bar.0.len()).max().unwrap_or(0) as u32;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(label_width * LABEL_CHARACTER_WIDTH)
+        .build_cartesian_2d(0.0..highest * 1.1, (0..bars.len()).into_segmented())?;
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .x_desc("probability")
+        .x_label_formatter(&|probability| format!("{:.2}%", probability * 100.0))
+        .y_label_formatter(&|row| match row {
+            SegmentValue::CenterOf(i) if *i < bars.len() => bars[*i].0.clone(),
+            _ => String::new(),
+        })
+        .draw()?;
+
+    let color = Palette99::pick(0).to_rgba();
+    chart.draw_series(bars.iter().enumerate().map(|(i, bar)| {
+        let mut rectangle = Rectangle::new(
+            [
+                (0.0, SegmentValue::Exact(i)),
+                (bar.1, SegmentValue::Exact(i + 1)),
+            ],
+            color.filled(),
+        );
+        rectangle.set_margin(6, 6, 0, 0);
+        rectangle
+    }))?;
+    chart.draw_series(bars.iter().enumerate().map(|(i, bar)| {
+        PathElement::new(
+            [
+                (bar.2, SegmentValue::CenterOf(i)),
+                (bar.3, SegmentValue::CenterOf(i)),
+            ],
+            BLACK.stroke_width(2),
+        )
+    }))?;
+
+    root.present()?;
+    // The backend borrows |svg| until it is dropped.
+    drop(chart);
+    drop(root);
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::report::ReportRow;
+
+    #[test]
+    fn file_stem_from_suite_name() {
+        assert_eq!(
+            file_stem("With locking: two desired buffs custom mod usage."),
+            "with-locking-two-desired-buffs-custom-mod-usage"
+        );
+    }
+
+    #[test]
+    fn strategies_compared_by_wanted_buffs() {
+        let mut charts = Charts::new(Rules::standard());
+        for (name, locked_buff, buffs) in [
+            ("no lock", None, vec![Buff::Attack, Buff::Elemental]),
+            ("slot 1 locked", Some(Buff::Attack), vec![Buff::Elemental]),
+            ("other", None, vec![Buff::Attack, Buff::MaxAmmo]),
+        ] {
+            let mut result = SimulationResult::new();
+            result.locked_buff = locked_buff;
+            result.buffs = buffs;
+            for modules in 1..100 {
+                result.record(modules);
+            }
+            let mut report = SuiteReport::new(name, "", &["buffs"]);
+            report.rows.push(ReportRow {
+                labels: vec!["buffs".to_string()],
+                result: RowResult::Modules(result),
+            });
+            assert!(charts.suite(&report).unwrap().svg.starts_with("<svg"));
+        }
+
+        let comparisons = charts.finish().unwrap();
+        assert_eq!(comparisons.len(), 1);
+        assert_eq!(comparisons[0].file_name, "strategies-elemental-attack.svg");
+        assert!(comparisons[0].svg.contains("slot 1 locked"));
+    }
+}
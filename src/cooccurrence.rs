@@ -0,0 +1,245 @@
+// How likely the buffs appear together on a roll. A roll that shows three buffs shows three pairs
+// and a triple of them at once, so a single pass over the rolls counts every pair and triple
+// instead of sampling each of them separately. The exact probabilities come from going through all
+// the rolls. The pairs make a matrix, so that the chance of any two wanted buffs can be looked up.
+
+use std::{collections::HashMap, sync::Arc};
+
+use itertools::Itertools;
+use strum::IntoEnumIterator;
+
+use crate::importance;
+use crate::report::{Heatmap, ReportRow, RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::sampling::{self, Estimate, StopRule};
+use crate::simulation::{buffs_to_string, note_left_out, ProportionResult, SuiteConfig};
+use crate::stats::Interval;
+use crate::{Buff, BuffSet, Simulation};
+
+// Every set of one, two and three buffs, in declaration order.
+fn buff_sets(sizes: std::ops::RangeInclusive<usize>) -> impl Iterator<Item = BuffSet> {
+    sizes.flat_map(|size| Buff::iter().combinations(size).map(BuffSet::from_iter))
+}
+
+/// The rolls and how many of them showed each set of one, two and three buffs, up to as many as
+/// a roll can show.
+#[derive(Clone, Debug)]
+pub struct Cooccurrences {
+    pub rolls: u64,
+    pub hits: HashMap<BuffSet, u64>,
+}
+
+impl Cooccurrences {
+    /// Counts the sets of up to |largest| buffs. The larger sets are never shown, so that they
+    /// would never be precise.
+    pub fn new(largest: usize) -> Self {
+        Cooccurrences {
+            rolls: 0,
+            hits: buff_sets(1..=largest).map(|buffs| (buffs, 0)).collect(),
+        }
+    }
+
+    // Records a roll that showed |shown|.
+    fn record(&mut self, shown: BuffSet) {
+        self.rolls += 1;
+        for buffs in (1..=shown.len()).flat_map(|size| shown.iter().combinations(size)) {
+            *self.hits.get_mut(&BuffSet::from_iter(buffs)).unwrap() += 1;
+        }
+    }
+
+    /// Adds the rolls of |other|, e.g. a part that ran on another thread.
+    pub fn merge(&mut self, other: &Cooccurrences) {
+        self.rolls += other.rolls;
+        for (buffs, hits) in &other.hits {
+            *self.hits.get_mut(buffs).unwrap() += hits;
+        }
+    }
+
+    /// How many of the rolls showed all of |buffs|, one to three of them.
+    pub fn result(&self, buffs: BuffSet) -> ProportionResult {
+        ProportionResult {
+            hits: self.hits[&buffs],
+            attempts: self.rolls,
+        }
+    }
+
+    // The least likely set of buffs, whose estimate is the least precise.
+    fn rarest(&self) -> ProportionResult {
+        let hits = *self.hits.values().min().unwrap();
+        ProportionResult {
+            hits,
+            attempts: self.rolls,
+        }
+    }
+}
+
+impl Default for Cooccurrences {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+// The sampling stops once the rarest set of buffs is precise enough, and all the others with it.
+impl Estimate for Cooccurrences {
+    fn samples(&self) -> u64 {
+        self.rolls
+    }
+
+    fn estimate(&self) -> f64 {
+        self.rarest().probability()
+    }
+
+    fn confidence_interval(&self) -> Interval {
+        self.rarest().confidence_interval()
+    }
+}
+
+/// The exact probability that a roll with |rules| shows each set of one, two and three buffs.
+pub fn exact_cooccurrences(rules: &Rules) -> HashMap<BuffSet, f64> {
+    let mut exact: HashMap<BuffSet, f64> = buff_sets(1..=3).map(|buffs| (buffs, 0.0)).collect();
+    importance::for_each_roll(rules, [None; 3], |slots, probability| {
+        let shown: Vec<Buff> = slots.into_iter().flatten().collect();
+        for buffs in (1..=shown.len()).flat_map(|size| shown.iter().copied().combinations(size)) {
+            *exact.get_mut(&BuffSet::from_iter(buffs)).unwrap() += probability;
+        }
+    });
+    exact
+}
+
+// The most buffs that a roll shows with |rules|.
+fn largest_shown(rules: &Rules) -> usize {
+    (0..3).filter(|pos| rules.slot_percent(*pos) > 0.0).count()
+}
+
+fn sim_cooccurrences(rules: &Arc<Rules>, rule: &StopRule, seed: u64) -> Cooccurrences {
+    let largest = largest_shown(rules);
+    let mut result = Cooccurrences::new(largest);
+
+    rule.run(
+        &mut result,
+        |chunk, samples| {
+            let mut rng = sampling::chunk_rng(seed, chunk);
+            let mut part = Cooccurrences::new(largest);
+            for _ in 0..samples {
+                let mut sim = Simulation::with_rules(rules.clone(), &mut rng);
+                sim.reroll();
+                part.record(sim.shown_buffs());
+            }
+            part
+        },
+        |result, part| result.merge(&part),
+    );
+
+    result
+}
+
+/// The exact probability of each pair of buffs on a roll, and of each buff on the diagonal.
+pub fn pair_heatmap(rules: &Rules) -> Heatmap {
+    let exact = exact_cooccurrences(rules);
+    Heatmap {
+        title: "Exact probability of each pair of buffs on a roll, of each buff alone on the \
+            diagonal"
+            .to_string(),
+        labels: Buff::iter().map(|buff| format!("{buff:?}")).collect(),
+        cells: Buff::iter()
+            .map(|row| {
+                Buff::iter()
+                    .map(|column| exact[&BuffSet::from_iter([row, column])])
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+// A suite of simulations.
+// Every pair and triple of buffs, counted on the same rolls, next to their exact probabilities.
+pub fn suite_cooccurrence(config: &SuiteConfig) -> SuiteReport {
+    let rule = &config.probability_stop;
+    let mut report = SuiteReport::new(
+        "buffs together",
+        &format!(
+            "The following tests report how likely (probability) a roll shows each pair and each \
+        triple of buffs, in any slots. All the rows count the same rolls, each of which counts \
+        for every pair and triple of the buffs that it shows, so the sampling stops by the \
+        rarest of them. The confidence intervals are Wilson score intervals. The exact \
+        probabilities are computed by going through all the rolls. The matrix of the pairs \
+        looks up any two buffs. {}",
+            rule.describe("")
+        ),
+        &["buffs", "exact"],
+    );
+
+    let exact = exact_cooccurrences(&config.rules);
+    let result = sim_cooccurrences(&config.rules, rule, config.seed("buffs together"));
+    let largest = largest_shown(&config.rules);
+    for buffs in buff_sets(2..=largest) {
+        let list: Vec<Buff> = buffs.iter().collect();
+        report.rows.push(ReportRow {
+            labels: vec![
+                buffs_to_string(&config.rules, list.iter()),
+                format!("{:.4}%", exact[&buffs] * 100.0),
+            ],
+            result: RowResult::Probability(result.result(buffs)),
+        });
+    }
+    note_left_out(&mut report, largest < 3);
+    report.heatmap = Some(pair_heatmap(&config.rules));
+    report
+}
+
+#[cfg(test)]
+mod test {
+
+    use more_asserts::assert_lt;
+
+    use super::*;
+    use crate::importance::exact_probability;
+
+    #[test]
+    fn exact_matches_each_set_of_buffs() {
+        let rules = Rules::standard();
+        let exact = exact_cooccurrences(&rules);
+        assert_eq!(exact.len(), 9 + 36 + 84);
+        for buffs in [
+            vec![Buff::Attack],
+            vec![Buff::Attack, Buff::Elemental],
+            vec![Buff::Attack, Buff::Elemental, Buff::MaxAmmo],
+        ] {
+            let want = BuffSet::from_iter(buffs);
+            let probability = exact_probability(&rules, |slots| {
+                slots
+                    .into_iter()
+                    .flatten()
+                    .collect::<BuffSet>()
+                    .is_superset(want)
+            });
+            assert_lt!((exact[&want] - probability).abs(), 1e-12);
+        }
+
+        let heatmap = pair_heatmap(&rules);
+        let attack = Buff::iter().position(|buff| buff == Buff::Attack).unwrap();
+        assert_eq!(heatmap.cells.len(), 9);
+        assert_eq!(heatmap.cells[0][attack], heatmap.cells[attack][0]);
+        assert_eq!(
+            heatmap.cells[attack][attack],
+            exact[&BuffSet::from_iter([Buff::Attack])]
+        );
+    }
+
+    #[test]
+    fn simulation_matches_the_exact_probabilities() {
+        let rule = StopRule::fixed(200000);
+        let rules = Rules::standard();
+        let result = sim_cooccurrences(&rules, &rule, 7);
+        assert_eq!(result.rolls, 200000);
+        for (buffs, exact) in exact_cooccurrences(&rules) {
+            let interval = result.result(buffs).confidence_interval();
+            // Wider than the 95% interval, so that none of the 129 sets fails by chance.
+            let slack = interval.half_width();
+            assert!(
+                interval.lower - slack <= exact && exact <= interval.upper + slack,
+                "{buffs:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// The cases that the suites cover, generated from the chances of the buffs rather than picked by
+// hand. The buffs with the same chance are interchangeable, so a goal is covered by the pattern of
+// its chances, e.g. two 10% buffs and a 12% buff, and every pattern gets a case. With a locked
+// buff, the chance of the locked buff is part of the pattern. Since the patterns come from the
+// rules, the cases stay complete when the chances change.
+
+use std::str::FromStr;
+
+use itertools::Itertools;
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::rules::Rules;
+use crate::Buff;
+
+/// The buffs that have the same chance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightClass {
+    pub percent: f64,
+    /// In declaration order.
+    pub buffs: Vec<Buff>,
+}
+
+/// The buffs of |rules| grouped by their chances, from the least likely.
+pub fn weight_classes(rules: &Rules) -> Vec<WeightClass> {
+    let mut classes: Vec<WeightClass> = vec![];
+    for buff in Buff::iter() {
+        let percent = rules.percent(buff);
+        match classes.iter_mut().find(|class| class.percent == percent) {
+            Some(class) => class.buffs.push(buff),
+            None => classes.push(WeightClass {
+                percent,
+                buffs: vec![buff],
+            }),
+        }
+    }
+    classes.sort_by(|a, b| a.percent.total_cmp(&b.percent));
+    classes
+}
+
+/// Which buffs stand for a pattern.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Coverage {
+    /// The first buffs of each weight class, in declaration order.
+    #[default]
+    Representative,
+    /// All the buffs of each weight class.
+    All,
+}
+
+impl Coverage {
+    pub const NAMES: [&'static str; 2] = ["representative", "all"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Coverage::Representative => Coverage::NAMES[0],
+            Coverage::All => Coverage::NAMES[1],
+        }
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "representative" => Ok(Coverage::Representative),
+            "all" => Ok(Coverage::All),
+            _ => Err(format!(
+                "'{name}' is not a coverage, they are {}",
+                Coverage::NAMES.join(" and ")
+            )),
+        }
+    }
+}
+
+/// A goal to cover: the buff that is locked at the start, if any, and the other wanted buffs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub locked: Option<Buff>,
+    pub rest: Vec<Buff>,
+}
+
+impl Case {
+    /// All the wanted buffs, the locked one first.
+    pub fn want(&self) -> Vec<Buff> {
+        self.locked.iter().chain(&self.rest).copied().collect()
+    }
+}
+
+// The ways of taking |size| buffs from |classes|, as the number of buffs taken from each class, in
+// the order of the classes. |taken| buffs of each class are already taken.
+fn patterns(classes: &[WeightClass], taken: &[usize], size: usize) -> Vec<Vec<usize>> {
+    (0..classes.len())
+        .combinations_with_replacement(size)
+        .map(|indices| {
+            let mut counts = vec![0; classes.len()];
+            for index in indices {
+                counts[index] += 1;
+            }
+            counts
+        })
+        .filter(|counts| {
+            counts
+                .iter()
+                .zip(taken)
+                .zip(classes)
+                .all(|((count, taken), class)| count + taken <= class.buffs.len())
+        })
+        .collect()
+}
+
+// The buffs that stand for |counts| buffs of each class, without |excluded|.
+fn buffs_for(
+    classes: &[WeightClass],
+    counts: &[usize],
+    excluded: Option<Buff>,
+    coverage: Coverage,
+) -> Vec<Vec<Buff>> {
+    counts
+        .iter()
+        .zip(classes)
+        .map(|(count, class)| {
+            let buffs: Vec<Buff> = class
+                .buffs
+                .iter()
+                .copied()
+                .filter(|buff| Some(*buff) != excluded)
+                .collect();
+            match coverage {
+                Coverage::Representative => vec![buffs[..*count].to_vec()],
+                Coverage::All => buffs.into_iter().combinations(*count).collect(),
+            }
+        })
+        .multi_cartesian_product()
+        .map(|parts| parts.concat())
+        .collect()
+}
+
+/// The cases of |size| wanted buffs with the chances of |rules|, from the least likely patterns.
+/// With |locked|, one of the buffs is locked at the start, and each of its chances makes other
+/// patterns.
+pub fn cases(rules: &Rules, size: usize, locked: bool, coverage: Coverage) -> Vec<Case> {
+    let classes = weight_classes(rules);
+    let none = vec![0; classes.len()];
+    if !locked {
+        return patterns(&classes, &none, size)
+            .iter()
+            .flat_map(|counts| buffs_for(&classes, counts, None, coverage))
+            .map(|rest| Case { locked: None, rest })
+            .collect();
+    }
+    let mut cases = vec![];
+    for (index, class) in classes.iter().enumerate() {
+        let mut taken = none.clone();
+        taken[index] = 1;
+        let lockable = match coverage {
+            Coverage::Representative => &class.buffs[..1],
+            Coverage::All => &class.buffs[..],
+        };
+        for counts in patterns(&classes, &taken, size - 1) {
+            for locked in lockable {
+                // The representative of the rest are the buffs after the locked one.
+                let rest = match coverage {
+                    Coverage::Representative => {
+                        let mut shifted = counts.clone();
+                        shifted[index] += 1;
+                        let mut buffs = buffs_for(&classes, &shifted, None, coverage).remove(0);
+                        buffs.retain(|buff| buff != locked);
+                        vec![buffs]
+                    }
+                    Coverage::All => buffs_for(&classes, &counts, Some(*locked), coverage),
+                };
+                cases.extend(rest.into_iter().map(|rest| Case {
+                    locked: Some(*locked),
+                    rest,
+                }));
+            }
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn percents(rules: &Rules, buffs: &[Buff]) -> Vec<f64> {
+        buffs.iter().map(|buff| rules.percent(*buff)).collect()
+    }
+
+    #[test]
+    fn buffs_are_grouped_by_chance() {
+        let classes = weight_classes(&Rules::standard());
+        assert_eq!(classes.len(), 2);
+        assert_eq!(classes[0].percent, 10.0);
+        assert_eq!(
+            classes[0].buffs,
+            vec![
+                Buff::Elemental,
+                Buff::Attack,
+                Buff::CritDamage,
+                Buff::Defense
+            ]
+        );
+        assert_eq!(classes[1].buffs.len(), 5);
+    }
+
+    #[test]
+    fn every_pattern_is_covered() {
+        let rules = Rules::standard();
+        let patterns = |cases: Vec<Case>| -> Vec<(Option<f64>, Vec<f64>)> {
+            cases
+                .iter()
+                .map(|case| {
+                    (
+                        case.locked.map(|buff| rules.percent(buff)),
+                        percents(&rules, &case.rest),
+                    )
+                })
+                .collect()
+        };
+
+        let shown = cases(&rules, 3, false, Coverage::Representative);
+        assert_eq!(
+            patterns(shown),
+            vec![
+                (None, vec![10.0, 10.0, 10.0]),
+                (None, vec![10.0, 10.0, 12.0]),
+                (None, vec![10.0, 12.0, 12.0]),
+                (None, vec![12.0, 12.0, 12.0]),
+            ]
+        );
+
+        let locked = cases(&rules, 3, true, Coverage::Representative);
+        assert_eq!(
+            patterns(locked.clone()),
+            vec![
+                (Some(10.0), vec![10.0, 10.0]),
+                (Some(10.0), vec![10.0, 12.0]),
+                (Some(10.0), vec![12.0, 12.0]),
+                (Some(12.0), vec![10.0, 10.0]),
+                (Some(12.0), vec![10.0, 12.0]),
+                (Some(12.0), vec![12.0, 12.0]),
+            ]
+        );
+        assert_eq!(
+            locked[0],
+            Case {
+                locked: Some(Buff::Elemental),
+                rest: vec![Buff::Attack, Buff::CritDamage],
+            }
+        );
+        for case in locked {
+            assert!(case.want().iter().all_unique());
+        }
+    }
+
+    #[test]
+    fn all_buffs_make_every_combination() {
+        let rules = Rules::standard();
+        // 9 choose 3.
+        assert_eq!(cases(&rules, 3, false, Coverage::All).len(), 84);
+        // 9 locked buffs, and 8 choose 2 others each.
+        let locked = cases(&rules, 3, true, Coverage::All);
+        assert_eq!(locked.len(), 9 * 28);
+        assert!(locked.iter().all(|case| case.want().iter().all_unique()));
+    }
+
+    // With three chances there are more patterns, and the classes that are too small for a pattern
+    // are left out.
+    #[test]
+    fn patterns_follow_the_rules() {
+        let rules = Rules::from_toml(
+            "test",
+            "[buff_percents]\nAttack = 5\nHitRate = 12\nMaxAmmo = 12\nChargeDamage = 12\n\
+            ChargeSpeed = 12\nCritRate = 12\n",
+        )
+        .unwrap();
+        let cases = cases(&rules, 2, false, Coverage::Representative);
+        assert_eq!(
+            cases
+                .iter()
+                .map(|case| percents(&rules, &case.rest))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![5.0, 10.0],
+                vec![5.0, 12.0],
+                vec![10.0, 10.0],
+                vec![10.0, 12.0],
+                vec![12.0, 12.0],
+            ]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+// HTML rendering of the suite reports: a single page with no external resources, with sortable
+// tables and the charts embedded, e.g. for hosting on a wiki.
+
+use strum::IntoEnumIterator;
+
+use crate::chart::Chart;
+use crate::report::{Heatmap, SuiteReport};
+use crate::rules::Rules;
+use crate::Buff;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em auto; max-width: 1400px; padding: 0 1em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.5em; text-align: left; }
+th { background: #f0f0f0; }
+table.sortable th { cursor: pointer; user-select: none; }
+table.sortable th[data-order=ascending]::after { content: " \25B2"; }
+table.sortable th[data-order=descending]::after { content: " \25BC"; }
+tbody tr:nth-child(even) { background: #fafafa; }
+table.heatmap td { text-align: right; }
+details { margin: 0.5em 0; }
+summary { cursor: pointer; }
+figure { margin: 1em 0; }
+figure svg { max-width: 100%; height: auto; }
+"#;
+
+// Sorts the rows of a table by the clicked column. Cells that start with a number, e.g. "23.5%"
+// or "[1.2, 3.4]", are compared as numbers.
+const SCRIPT: &str = r#"
+for (const header of document.querySelectorAll("table.sortable th")) {
+  header.addEventListener("click", () => {
+    const body = header.closest("table").tBodies[0];
+    const ascending = header.dataset.order !== "ascending";
+    for (const other of header.parentNode.children) {
+      delete other.dataset.order;
+    }
+    header.dataset.order = ascending ? "ascending" : "descending";
+    const cell = (row) => row.cells[header.cellIndex].textContent;
+    const number = (text) => parseFloat(text.replace(/^[\[#]/, ""));
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const [x, y] = [number(cell(a)), number(cell(b))];
+      const order = isNaN(x) || isNaN(y) ? cell(a).localeCompare(cell(b)) : x - y;
+      return ascending ? order : -order;
+    });
+    body.append(...rows);
+  });
+}
+"#;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// |data| is the header row followed by the rows.
+fn table(data: &[Vec<String>], sortable: bool) -> String {
+    let mut html = if sortable {
+        "<table class=\"sortable\">\n".to_string()
+    } else {
+        "<table>\n".to_string()
+    };
+    if let Some((header, rows)) = data.split_first() {
+        html += "<thead><tr>";
+        for cell in header {
+            html += &format!("<th>{}</th>", escape(cell));
+        }
+        html += "</tr></thead>\n<tbody>\n";
+        for row in rows {
+            html += "<tr>";
+            for cell in row {
+                html += &format!("<td>{}</td>", escape(cell));
+            }
+            html += "</tr>\n";
+        }
+        html += "</tbody>\n";
+    }
+    html + "</table>\n"
+}
+
+// The cells of |heatmap| are shaded from white to blue by their probability, the largest the
+// darkest.
+fn heatmap(heatmap: &Heatmap) -> String {
+    let data = heatmap.table_data();
+    let max = heatmap.max();
+    let mut html = format!(
+        "<p>{}:</p>\n<table class=\"heatmap\">\n<thead><tr>",
+        escape(&heatmap.title)
+    );
+    for cell in &data[0] {
+        html += &format!("<th>{}</th>", escape(cell));
+    }
+    html += "</tr></thead>\n<tbody>\n";
+    for (row, probabilities) in data[1..].iter().zip(&heatmap.cells) {
+        html += &format!("<tr><th>{}</th>", escape(&row[0]));
+        for (cell, probability) in row[1..].iter().zip(probabilities) {
+            let shade = if max > 0.0 { probability / max } else { 0.0 };
+            html += &format!(
+                "<td style=\"background: rgba(31, 119, 180, {shade:.2})\">{}</td>",
+                escape(cell)
+            );
+        }
+        html += "</tr>\n";
+    }
+    html + "</tbody>\n</table>\n"
+}
+
+fn figure(chart: &Chart) -> String {
+    format!(
+        "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n",
+        chart.svg,
+        escape(&chart.title)
+    )
+}
+
+/// The start of the page, with the |parameters| of the run and the buff table of |rules|.
+pub fn header(parameters: &[(&str, String)], rules: &Rules) -> String {
+    let title = "Overload gear simulation results";
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+        <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+
+    html += "<h2>Parameters</h2>\n";
+    let mut data = vec![vec!["parameter".to_string(), "value".to_string()]];
+    data.extend(
+        parameters
+            .iter()
+            .map(|(name, value)| vec![name.to_string(), value.clone()]),
+    );
+    html += &table(&data, false);
+
+    html += "<h2>Buffs</h2>\n";
+    let mut data = vec![vec!["buff".to_string(), "chance".to_string()]];
+    data.extend(
+        Buff::iter().map(|buff| vec![format!("{buff:?}"), format!("{}%", rules.percent(buff))]),
+    );
+    html += &table(&data, true);
+    html
+}
+
+/// A section with the suite's name, its description (collapsed), |data| as a sortable table, the
+/// heatmap of the suite, if any, and the |chart| of the suite.
+pub fn suite_section(report: &SuiteReport, data: &[Vec<String>], chart: Option<&Chart>) -> String {
+    let mut html = format!(
+        "<section>\n<h2>{}</h2>\n<details>\n<summary>Description</summary>\n<p>{}</p>\n\
+        </details>\n",
+        escape(&report.name),
+        escape(&report.plain_description())
+    );
+    html += &table(data, true);
+    if let Some(map) = &report.heatmap {
+        html += &heatmap(map);
+    }
+    if let Some(chart) = chart {
+        html += &figure(chart);
+    }
+    html + "</section>\n"
+}
+
+/// The end of the page, with the charts that compare the strategies.
+pub fn footer(comparisons: &[Chart]) -> String {
+    let mut html = String::new();
+    if !comparisons.is_empty() {
+        html += "<section>\n<h2>Strategy comparison</h2>\n<p>The share of the runs that \
+            finished within N custom modules, for each suite that wanted the same buffs.</p>\n";
+        for chart in comparisons {
+            html += &figure(chart);
+        }
+        html += "</section>\n";
+    }
+    html + &format!("<script>{SCRIPT}</script>\n</body>\n</html>\n")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn table_escapes_cells() {
+        let data = vec![
+            vec!["a".to_string(), "b".to_string()],
+            vec!["<1>".to_string(), "x & y".to_string()],
+        ];
+
+        assert_eq!(
+            table(&data, true),
+            "<table class=\"sortable\">\n<thead><tr><th>a</th><th>b</th></tr></thead>\n\
+            <tbody>\n<tr><td>&lt;1&gt;</td><td>x &amp; y</td></tr>\n</tbody>\n</table>\n"
+        );
+    }
+}
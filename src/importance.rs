@@ -0,0 +1,215 @@
+// Importance sampling of rare outcomes of a roll. The rolls are drawn from a proposal that favours
+// the wanted buffs and the extra slots, and every roll is weighted by its likelihood ratio, i.e.
+// how much more likely its draws are with the real chances than with the proposal. The mean of the
+// weights of the rolls with the outcome is an unbiased estimate of its real probability.
+
+use rand::Rng;
+use strum::IntoEnumIterator;
+
+use crate::rules::Rules;
+use crate::{AdditionalSlots, Buff, BuffSet};
+
+// The chance of drawing one of the wanted buffs that are left, when other buffs are left too.
+const WANTED_SHARE: f64 = 0.8;
+
+// The proposed chance of an extra slot getting a buff when fewer than 3 buffs are wanted, if the
+// real chance is lower.
+const MIN_SLOT_PERCENT: f64 = 80.0;
+
+/// The chances that the rolls are drawn with instead of the real ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Proposal {
+    /// The buffs that are drawn more often.
+    pub want: BuffSet,
+    /// The chance of drawing one of the wanted buffs that are left, when other buffs are left
+    /// too. The buffs within the wanted and the other buffs keep their relative chances.
+    pub wanted_share: f64,
+    /// The chances of the second and third slots getting a buff, in percent.
+    pub second_slot_percent: f64,
+    pub third_slot_percent: f64,
+}
+
+impl Proposal {
+    /// A proposal for the rolls that show all of |want| with |rules|. All the slots get a buff if
+    /// 3 buffs are wanted, since the others cannot have the outcome.
+    pub fn new(rules: &Rules, want: BuffSet) -> Self {
+        let slot_percent = |percent: f64| {
+            if want.len() >= 3 {
+                100.0
+            } else {
+                percent.max(MIN_SLOT_PERCENT)
+            }
+        };
+        Proposal {
+            want,
+            wanted_share: WANTED_SHARE,
+            second_slot_percent: slot_percent(rules.second_slot_percent),
+            third_slot_percent: slot_percent(rules.third_slot_percent),
+        }
+    }
+
+    /// Chooses a buff that is not in |excluded|, and returns it with its likelihood ratio with
+    /// |rules|.
+    pub(crate) fn choose<R: Rng>(
+        &self,
+        rules: &Rules,
+        excluded: BuffSet,
+        rng: &mut R,
+    ) -> (Buff, f64) {
+        let available = excluded.complement();
+        let wanted = available.intersection(self.want);
+        let others = available.intersection(self.want.complement());
+        if wanted.is_empty() || others.is_empty() {
+            return (rules.choose(excluded, rng), 1.0);
+        }
+
+        // Within a group the proposal has the real relative chances, so the ratio is the same for
+        // all of its buffs.
+        let total = total_percent(rules, available);
+        if rng.gen_bool(self.wanted_share) {
+            let ratio = total_percent(rules, wanted) / (self.wanted_share * total);
+            (rules.choose(wanted.complement(), rng), ratio)
+        } else {
+            let ratio = total_percent(rules, others) / ((1.0 - self.wanted_share) * total);
+            (rules.choose(others.complement(), rng), ratio)
+        }
+    }
+
+    /// Draws which extra slots get a buff, and returns them with their likelihood ratio with
+    /// |rules|.
+    pub(crate) fn additional_slots<R: Rng>(
+        &self,
+        rules: &Rules,
+        rng: &mut R,
+    ) -> (AdditionalSlots, f64) {
+        let (second, second_ratio) = slot(rng, rules.second_slot_percent, self.second_slot_percent);
+        let (third, third_ratio) = slot(rng, rules.third_slot_percent, self.third_slot_percent);
+        (
+            AdditionalSlots::new(second, third),
+            second_ratio * third_ratio,
+        )
+    }
+}
+
+fn total_percent(rules: &Rules, buffs: BuffSet) -> f64 {
+    buffs.iter().map(|buff| rules.percent(buff)).sum()
+}
+
+// Draws whether a slot gets a buff with the |proposed| chance instead of the |real| one.
+fn slot<R: Rng>(rng: &mut R, real: f64, proposed: f64) -> (bool, f64) {
+    if rng.gen_range(0.0..100.0) < proposed {
+        (true, real / proposed)
+    } else {
+        (false, (100.0 - real) / (100.0 - proposed))
+    }
+}
+
+/// The exact probability of |outcome| on a roll with no locked buffs with |rules|, by going through
+/// all the rolls. |outcome| gets the buff of each slot, if it has one.
+pub fn exact_probability(rules: &Rules, outcome: impl Fn([Option<Buff>; 3]) -> bool) -> f64 {
+    let mut probability = 0.0;
+    for_each_roll(rules, [None; 3], |slots, chance| {
+        if outcome(slots) {
+            probability += chance;
+        }
+    });
+    probability
+}
+
+/// Calls |visit| with every roll that can follow the |locked| buffs, by slot, with |rules| and its
+/// probability. The rolls have the buff of each slot, if it has one, the locked buffs included.
+pub fn for_each_roll(
+    rules: &Rules,
+    locked: [Option<Buff>; 3],
+    mut visit: impl FnMut([Option<Buff>; 3], f64),
+) {
+    let excluded: BuffSet = locked.iter().flatten().copied().collect();
+    for (second, third) in [(false, false), (true, false), (false, true), (true, true)] {
+        let chance = |shown: bool, percent: f64| {
+            if shown {
+                percent / 100.0
+            } else {
+                1.0 - percent / 100.0
+            }
+        };
+        let probability =
+            chance(second, rules.second_slot_percent) * chance(third, rules.third_slot_percent);
+        if probability > 0.0 {
+            let shown = [true, second, third];
+            draws(rules, &shown, locked, excluded, probability, &mut visit);
+        }
+    }
+}
+
+// Visits the rolls that follow the buffs drawn so far into |slots|, which are |drawn|, with the
+// |probability| of the draws so far. The slots that are |shown| and still empty are drawn in
+// order.
+fn draws(
+    rules: &Rules,
+    shown: &[bool; 3],
+    slots: [Option<Buff>; 3],
+    drawn: BuffSet,
+    probability: f64,
+    visit: &mut impl FnMut([Option<Buff>; 3], f64),
+) {
+    let Some(pos) = (0..3).find(|pos| shown[*pos] && slots[*pos].is_none()) else {
+        visit(slots, probability);
+        return;
+    };
+    let total = total_percent(rules, drawn.complement());
+    for buff in Buff::iter().filter(|buff| !drawn.contains(*buff)) {
+        let mut slots = slots;
+        slots[pos] = Some(buff);
+        let mut drawn = drawn;
+        drawn.insert(buff);
+        let chance = rules.percent(buff) / total;
+        draws(rules, shown, slots, drawn, probability * chance, visit);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use more_asserts::assert_lt;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Simulation;
+
+    #[test]
+    fn exact_probabilities_add_up() {
+        let rules = Rules::standard();
+        let slots = |count: usize| {
+            exact_probability(&rules, move |slots| slots.iter().flatten().count() == count)
+        };
+        assert_lt!((slots(1) - 0.35).abs(), 1e-12);
+        assert_lt!((slots(2) - 0.5).abs(), 1e-12);
+        assert_lt!((slots(3) - 0.15).abs(), 1e-12);
+        // Each buff is in the first slot as often as its chance.
+        let attack = exact_probability(&rules, |slots| slots[0] == Some(Buff::Attack));
+        assert_lt!((attack - 0.1).abs(), 1e-12);
+    }
+
+    // The mean likelihood ratio of the rolls with an outcome is its exact probability.
+    #[test]
+    fn weighted_rolls_are_unbiased() {
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental, Buff::CritDamage]);
+        let rules = Rules::standard();
+        let exact = exact_probability(&rules, |slots| {
+            slots.iter().flatten().copied().collect::<BuffSet>() == want
+        });
+        let mut rng = StdRng::seed_from_u64(1);
+        let rolls = 100000;
+        let mut hits = 0.0;
+        for _ in 0..rolls {
+            let mut sim = Simulation::with_rng(&mut rng);
+            sim.sample_from(Proposal::new(&rules, want));
+            sim.reroll();
+            if sim.shown_buffs().is_superset(want) {
+                hits += sim.likelihood_ratio();
+            }
+        }
+
+        assert_lt!((hits / rolls as f64 / exact - 1.0).abs(), 0.02);
+    }
+}
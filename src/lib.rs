@@ -0,0 +1,628 @@
+// The overload gear simulation: the buffs, and rolling and locking them.
+
+use enum_assoc::Assoc;
+use more_asserts::assert_lt;
+use std::sync::Arc;
+
+use rand::{rngs::ThreadRng, Rng};
+use strum_macros::EnumIter;
+
+use importance::Proposal;
+use rules::Rules;
+
+pub use buff_name::ParseBuffError;
+pub use buff_set::{choose, BuffSet};
+
+pub mod advisor;
+pub mod breakeven;
+mod buff_name;
+pub mod buff_set;
+pub mod chart;
+pub mod cooccurrence;
+pub mod coverage;
+pub mod html;
+pub mod importance;
+pub mod markdown;
+pub mod output;
+pub mod plot;
+pub mod registry;
+pub mod report;
+pub mod rules;
+pub mod sampling;
+pub mod saved;
+pub mod scenario;
+pub mod session;
+pub mod simulation;
+pub mod solver;
+pub mod stats;
+pub mod variance;
+
+#[derive(Assoc, EnumIter, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+// The standard chances of the buffs, see Rules for others.
+#[func(pub fn percent(&self) -> f64)]
+// The name of the buff in the game, see buff_name.rs for the other names that are parsed.
+#[func(pub fn label(&self) -> &'static str)]
+#[func(fn aliases(&self) -> &'static [&'static str])]
+pub enum Buff {
+    #[assoc(percent = 10.0)]
+    #[assoc(label = "Increase Elemental Damage Dealt")]
+    #[assoc(aliases = &["elem", "ele", "element", "elemental damage"])]
+    Elemental,
+    #[assoc(percent = 12.0)]
+    #[assoc(label = "Increase Hit Rate")]
+    #[assoc(aliases = &["hit", "hr", "accuracy"])]
+    HitRate,
+    #[assoc(percent = 12.0)]
+    #[assoc(label = "Increase Max Ammunition Capacity")]
+    #[assoc(aliases = &["ammo", "max ammunition", "ammunition"])]
+    MaxAmmo,
+    #[assoc(percent = 10.0)]
+    #[assoc(label = "Increase ATK")]
+    #[assoc(aliases = &["atk"])]
+    Attack,
+    #[assoc(percent = 12.0)]
+    #[assoc(label = "Increase Charge Damage")]
+    #[assoc(aliases = &["charge dmg", "chg dmg"])]
+    ChargeDamage,
+    #[assoc(percent = 12.0)]
+    #[assoc(label = "Increase Charge Speed")]
+    #[assoc(aliases = &["charge spd", "chg spd", "cspd"])]
+    ChargeSpeed,
+    #[assoc(percent = 12.0)]
+    #[assoc(label = "Increase Critical Rate")]
+    #[assoc(aliases = &["crit", "crate", "crit rate", "cr"])]
+    CritRate,
+    #[assoc(percent = 10.0)]
+    #[assoc(label = "Increase Critical Damage")]
+    #[assoc(aliases = &["cdmg", "crit dmg", "crit damage"])]
+    CritDamage,
+    #[assoc(percent = 10.0)]
+    #[assoc(label = "Increase DEF")]
+    #[assoc(aliases = &["def"])]
+    Defense,
+}
+
+#[derive(PartialEq)]
+enum AdditionalSlots {
+    None,
+    SecondOnly,
+    ThirdOnly,
+    SecondAndThird,
+}
+
+impl AdditionalSlots {
+    fn new(gets_second_slot: bool, gets_third_slot: bool) -> Self {
+        if gets_second_slot && gets_third_slot {
+            return AdditionalSlots::SecondAndThird;
+        } else if gets_second_slot {
+            return AdditionalSlots::SecondOnly;
+        } else if gets_third_slot {
+            return AdditionalSlots::ThirdOnly;
+        }
+        AdditionalSlots::None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotState {
+    // There are no values in the slot.
+    None,
+    // The buff may change on re-roll.
+    Free(Buff),
+    // The buff will not change on reroll.
+    Locked(Buff),
+}
+
+pub struct Simulation<R: Rng = ThreadRng> {
+    custom_modules: u32,
+    attempts: u32,
+    locks: u32,
+    // The parts of |custom_modules| that the rerolls and the locks used.
+    reroll_modules: u32,
+    lock_modules: u32,
+    buffs: [SlotState; 3],
+    rules: Arc<Rules>,
+    rng: R,
+    // Draw for every slot on every reroll, see |synchronize_draws|.
+    synchronized_draws: bool,
+    // Draw from these chances instead of the real ones, see |sample_from|.
+    proposal: Option<Proposal>,
+    likelihood_ratio: f64,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Simulation::with_rng(rand::thread_rng())
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new()
+    }
+}
+
+impl<R: Rng> Simulation<R> {
+    /// Draws the buffs with |rng|. Use a seeded generator for reproducible simulations.
+    pub fn with_rng(rng: R) -> Self {
+        Simulation::with_rules(Rules::standard(), rng)
+    }
+
+    /// Draws the buffs with |rng|, with |rules| instead of the standard ones.
+    pub fn with_rules(rules: Arc<Rules>, rng: R) -> Self {
+        Simulation {
+            custom_modules: 0,
+            attempts: 0,
+            locks: 0,
+            reroll_modules: 0,
+            lock_modules: 0,
+            buffs: [SlotState::None, SlotState::None, SlotState::None],
+            rules,
+            rng,
+            synchronized_draws: false,
+            proposal: None,
+            likelihood_ratio: 1.0,
+        }
+    }
+
+    /// Makes every reroll draw a buff for every slot, and throw away the draws of the slots that
+    /// are locked or do not get a buff. The rerolls then use the same random numbers no matter
+    /// which slots are locked, so that simulations of different strategies from the same seed stay
+    /// in step (common random numbers).
+    pub fn synchronize_draws(&mut self) {
+        self.synchronized_draws = true;
+    }
+
+    /// Draws the rolls from |proposal| instead of the real chances, for importance sampling.
+    /// |likelihood_ratio| tells how to weight the outcome.
+    pub fn sample_from(&mut self, proposal: Proposal) {
+        self.proposal = Some(proposal);
+    }
+
+    /// How much more likely the draws so far are with the real chances than with the proposal,
+    /// or 1 without a proposal.
+    pub fn likelihood_ratio(&self) -> f64 {
+        self.likelihood_ratio
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn buffs(&self) -> &[SlotState] {
+        &self.buffs
+    }
+
+    /// The rerolls so far.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The custom modules used by the rerolls and locks so far.
+    pub fn custom_modules(&self) -> u32 {
+        self.custom_modules
+    }
+
+    /// The locks so far. Unlocking does not take them back.
+    pub fn locks(&self) -> u32 {
+        self.locks
+    }
+
+    /// The custom modules used by the rerolls so far.
+    pub fn reroll_modules(&self) -> u32 {
+        self.reroll_modules
+    }
+
+    /// The custom modules used by the locks so far.
+    pub fn lock_modules(&self) -> u32 {
+        self.lock_modules
+    }
+
+    /// Forgets the rerolls, the locks and the modules so far, and counts |rerolls| without locks
+    /// instead, e.g. to count from a roll that took some to get to as if it took |rerolls|. The
+    /// slots stay as they are.
+    pub fn restart_counts(&mut self, rerolls: u32) {
+        self.custom_modules = rerolls * self.rules.reroll_cost(0).unwrap();
+        self.attempts = rerolls;
+        self.reroll_modules = self.custom_modules;
+        self.locks = 0;
+        self.lock_modules = 0;
+    }
+
+    /// Reroll the buffs. Locked buffs will not change, and will use more custom modules accordingly.
+    pub fn reroll(&mut self) {
+        let lock_count = self.locked_count();
+
+        let cus_mod_usage = self
+            .rules
+            .reroll_cost(lock_count)
+            .expect("more buffs are locked than the rules allow");
+
+        self.custom_modules += cus_mod_usage;
+        self.attempts += 1;
+        self.reroll_modules += cus_mod_usage;
+        self.reroll_buffs();
+    }
+
+    // The locked buffs are excluded from the draws so that they do not appear twice.
+    //
+    // The rest is the same as initial roll. Except that the locked ones are skipped.
+    fn reroll_buffs(&mut self) {
+        let mut excluded = self.locked_buffs();
+
+        self.reroll_slot(0, &mut excluded, true);
+        let extra_slots = match self.proposal {
+            Some(proposal) => {
+                let (extra_slots, ratio) = proposal.additional_slots(&self.rules, &mut self.rng);
+                self.likelihood_ratio *= ratio;
+                extra_slots
+            }
+            None => additional_slots(&self.rules, &mut self.rng),
+        };
+        self.reroll_slot(
+            1,
+            &mut excluded,
+            matches!(
+                extra_slots,
+                AdditionalSlots::SecondOnly | AdditionalSlots::SecondAndThird
+            ),
+        );
+        self.reroll_slot(
+            2,
+            &mut excluded,
+            matches!(
+                extra_slots,
+                AdditionalSlots::ThirdOnly | AdditionalSlots::SecondAndThird
+            ),
+        );
+    }
+
+    // Draws a buff that is not in |excluded| into the slot at |pos| if it |gets_buff| and is not
+    // locked, and adds it to |excluded|. A slot that is not locked and does not get a buff is
+    // emptied, as the buff of the previous roll is rerolled away.
+    fn reroll_slot(&mut self, pos: usize, excluded: &mut BuffSet, gets_buff: bool) {
+        let locked = matches!(self.buffs[pos], SlotState::Locked(_));
+        if !gets_buff || locked {
+            if self.synchronized_draws {
+                self.rules.choose(*excluded, &mut self.rng);
+            }
+            if !locked {
+                self.buffs[pos] = SlotState::None;
+            }
+            return;
+        }
+        let buff = match self.proposal {
+            Some(proposal) => {
+                let (buff, ratio) = proposal.choose(&self.rules, *excluded, &mut self.rng);
+                self.likelihood_ratio *= ratio;
+                buff
+            }
+            None => self.rules.choose(*excluded, &mut self.rng),
+        };
+        excluded.insert(buff);
+        self.buffs[pos] = SlotState::Free(buff);
+    }
+
+    pub fn has_buff(&self, buff: &Buff) -> bool {
+        self.shown_buffs().contains(*buff)
+    }
+
+    /// The buffs in the slots, locked or not.
+    pub fn shown_buffs(&self) -> BuffSet {
+        self.buffs
+            .iter()
+            .filter_map(|state| match state {
+                SlotState::Free(buff) | SlotState::Locked(buff) => Some(*buff),
+                SlotState::None => None,
+            })
+            .collect()
+    }
+
+    pub fn locked_buffs(&self) -> BuffSet {
+        self.buffs
+            .iter()
+            .filter_map(|state| match state {
+                SlotState::Locked(buff) => Some(*buff),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn position_of(&self, buff: &Buff) -> Option<usize> {
+        self.buffs.iter().position(|state| match state {
+            SlotState::Free(b) | SlotState::Locked(b) => b.eq(buff),
+            _ => false,
+        })
+    }
+
+    // Locks the buff if there is a buf and is not locked already. This uses
+    // custom modules on lock.
+    pub fn lock(&mut self, pos: usize) {
+        assert_lt!(pos, self.buffs.len());
+        let locked_count = self.locked_count();
+        // It does not make sense to lock the third slot. Just don't reroll.
+        // Note that this could happen in simluation code, just ignore it as it should have reached
+        // a terminating condition.
+        let Some(cost) = self.rules.lock_cost(locked_count) else {
+            return;
+        };
+
+        if let SlotState::Free(buff) = &self.buffs[pos] {
+            self.buffs[pos] = SlotState::Locked(*buff);
+            // Note that the cost is of the locked count before locking.
+            self.custom_modules += cost;
+            self.locks += 1;
+            self.lock_modules += cost;
+        }
+    }
+
+    /// Unlocks the buff at |pos| if it is locked. Unlocking is free, and the modules that the
+    /// lock cost are not given back.
+    pub fn unlock(&mut self, pos: usize) {
+        assert_lt!(pos, self.buffs.len());
+        if let SlotState::Locked(buff) = self.buffs[pos] {
+            self.buffs[pos] = SlotState::Free(buff);
+        }
+    }
+
+    fn locked_count(&self) -> u32 {
+        self.locked_buffs().len() as u32
+    }
+
+    /// Starts from |slots|, e.g. a roll in the game, without using any modules.
+    pub fn set_slots(&mut self, slots: [SlotState; 3]) {
+        self.buffs = slots;
+    }
+
+    // Force sets the buff at position as non-locked buff.
+    pub fn set_buff(&mut self, pos: usize, buff: &Buff) {
+        self.buffs[pos] = SlotState::Free(*buff);
+    }
+
+    pub fn lock_first(&mut self) {
+        self.lock(0);
+    }
+
+    pub fn lock_second(&mut self) {
+        self.lock(1);
+    }
+
+    pub fn lock_third(&mut self) {
+        self.lock(2);
+    }
+}
+
+fn additional_slots<R: Rng>(rules: &Rules, rng: &mut R) -> AdditionalSlots {
+    // Draw for second slot.
+    let value = rng.gen_range(0.0..100.0);
+    let mut gets_second_slot = false;
+    if value < rules.second_slot_percent {
+        gets_second_slot = true;
+    }
+
+    // Draw for third slot.
+    let value = rng.gen_range(0.0..100.0);
+    let mut gets_third_slot = false;
+    if value < rules.third_slot_percent {
+        gets_third_slot = true;
+    }
+
+    AdditionalSlots::new(gets_second_slot, gets_third_slot)
+}
+
+#[cfg(test)]
+mod test {
+
+    use itertools::Itertools;
+    use more_asserts::{assert_ge, assert_le};
+    use std::{collections::HashMap, vec};
+
+    use super::*;
+
+    #[test]
+    fn check_distribution_10000() {
+        let mut samples = vec![];
+        let mut rng = rand::thread_rng();
+        for _ in 0..10000 {
+            let buff = choose(BuffSet::EMPTY, &mut rng);
+            samples.push(buff);
+        }
+
+        let counts: HashMap<Buff, usize> = samples.into_iter().counts();
+        let range_for_10_percent = 500..1500;
+
+        assert!(range_for_10_percent.contains(&counts[&Buff::Elemental]));
+        assert!(range_for_10_percent.contains(&counts[&Buff::Attack]));
+        assert!(range_for_10_percent.contains(&counts[&Buff::CritDamage]));
+        assert!(range_for_10_percent.contains(&counts[&Buff::Defense]));
+
+        let range_for_12_percent = 600..2000;
+        assert!(range_for_12_percent.contains(&counts[&Buff::HitRate]));
+        assert!(range_for_12_percent.contains(&counts[&Buff::MaxAmmo]));
+        assert!(range_for_12_percent.contains(&counts[&Buff::ChargeDamage]));
+        assert!(range_for_12_percent.contains(&counts[&Buff::ChargeSpeed]));
+        assert!(range_for_12_percent.contains(&counts[&Buff::CritRate]));
+    }
+
+    #[test]
+    fn check_partial_choices() {
+        let excluded = BuffSet::from_iter([Buff::Attack, Buff::Elemental]).complement();
+        let mut rng = rand::thread_rng();
+        let samples: Vec<Buff> = (0..10000).map(|_| choose(excluded, &mut rng)).collect();
+
+        let counts: HashMap<Buff, usize> = samples.into_iter().counts();
+        assert_eq!(counts.len(), 2);
+        assert!(counts.contains_key(&Buff::Attack));
+        assert!(counts.contains_key(&Buff::Elemental));
+    }
+
+    #[test]
+    fn simulation_reroll_init() {
+        let mut sim = Simulation::new();
+        sim.reroll();
+
+        assert_eq!(sim.attempts, 1);
+        assert_eq!(sim.custom_modules, 1);
+        assert_eq!(sim.buffs().len(), 3);
+
+        assert!(matches!(
+            sim.buffs[0],
+            SlotState::Locked(_) | SlotState::Free(_)
+        ));
+    }
+
+    // Verify that the distribution of the buffs are OK.
+    #[test]
+    fn simulation_reroll_multiiple() {
+        let mut sim = Simulation::new();
+        let mut all_buffs = vec![];
+        for _ in 0..10000 {
+            sim.reroll();
+            let buffs: Vec<_> = sim
+                .buffs()
+                .iter()
+                .filter_map(|item| match item {
+                    SlotState::Free(b) => Some(*b),
+                    SlotState::Locked(b) => Some(*b),
+                    SlotState::None => None,
+                })
+                .collect();
+
+            all_buffs.extend(buffs);
+        }
+
+        let all_buffs_len = all_buffs.len();
+
+        let counts: HashMap<Buff, usize> = all_buffs.into_iter().counts();
+        println!("counts: {:?}", counts);
+
+        // For 10% buffs, assume they occur about 7%-13% of all buffs.
+        let seven_percent = (0.07 * all_buffs_len as f64) as usize;
+        let thirteen_percent = (0.13 * all_buffs_len as f64) as usize;
+
+        assert_ge!(counts[&Buff::Elemental], seven_percent);
+        assert_le!(counts[&Buff::Elemental], thirteen_percent);
+        assert_ge!(counts[&Buff::Attack], seven_percent);
+        assert_le!(counts[&Buff::Attack], thirteen_percent);
+        assert_ge!(counts[&Buff::CritDamage], seven_percent);
+        assert_le!(counts[&Buff::CritDamage], thirteen_percent);
+        assert_ge!(counts[&Buff::Defense], seven_percent);
+        assert_le!(counts[&Buff::Defense], thirteen_percent);
+
+        // For 12% buffs, assume they occur about 9%-15% of all buffs.
+        let nine_percent = (0.09 * all_buffs_len as f64) as usize;
+        let fifteen_percent = (0.15 * all_buffs_len as f64) as usize;
+
+        assert_ge!(counts[&Buff::HitRate], nine_percent);
+        assert_le!(counts[&Buff::HitRate], fifteen_percent);
+        assert_ge!(counts[&Buff::MaxAmmo], nine_percent);
+        assert_le!(counts[&Buff::MaxAmmo], fifteen_percent);
+        assert_ge!(counts[&Buff::ChargeDamage], nine_percent);
+        assert_le!(counts[&Buff::ChargeDamage], fifteen_percent);
+        assert_ge!(counts[&Buff::ChargeSpeed], nine_percent);
+        assert_le!(counts[&Buff::ChargeSpeed], fifteen_percent);
+        assert_ge!(counts[&Buff::CritRate], nine_percent);
+        assert_le!(counts[&Buff::CritRate], fifteen_percent);
+    }
+
+    #[test]
+    fn simulation_reroll_custom_module_count() {
+        let mut sim = Simulation::new();
+        for _ in 0..1000 {
+            sim.reroll();
+        }
+
+        assert_eq!(sim.attempts, 1000);
+        assert_eq!(sim.custom_modules, 1000);
+    }
+
+    // The extra slots that do not get a buff on a reroll must not keep the buffs of earlier rolls,
+    // which could also show a buff twice.
+    #[test]
+    fn reroll_empties_slots_without_buff() {
+        let mut sim = Simulation::new();
+        sim.set_slots([
+            SlotState::Locked(Buff::Attack),
+            SlotState::Free(Buff::Defense),
+            SlotState::Free(Buff::Elemental),
+        ]);
+        let mut emptied = false;
+        for _ in 0..100 {
+            sim.reroll();
+            let slots = sim.buffs().iter();
+            let filled = slots.filter(|slot| **slot != SlotState::None).count();
+            assert_eq!(sim.shown_buffs().len(), filled);
+            emptied |= sim.buffs()[2] == SlotState::None;
+        }
+        assert_eq!(sim.buffs()[0], SlotState::Locked(Buff::Attack));
+        assert!(emptied);
+    }
+
+    #[test]
+    fn has_buff() {
+        let mut sim = Simulation::new();
+        sim.reroll();
+
+        let SlotState::Free(buff) = &sim.buffs()[0] else {
+            panic!("First slot on first roll must be free state.");
+        };
+
+        assert!(sim.has_buff(buff));
+    }
+
+    // Verify that locking the first slot and rerolling should consume more custom modules.
+    #[test]
+    fn locking_should_use_more_custom_modules() {
+        let mut sim = Simulation::new();
+        sim.reroll();
+
+        assert_eq!(sim.custom_modules, 1);
+
+        sim.lock_first();
+        assert_eq!(sim.custom_modules, 3);
+        sim.reroll();
+
+        assert_eq!(sim.custom_modules, 5);
+    }
+
+    // Verify that locking the first slot and rerolling should consume more custom modules.
+    #[test]
+    fn locking_should_use_more_custom_modules_locking_two_slots() {
+        let mut sim = Simulation::new();
+        sim.reroll();
+
+        assert_eq!(sim.custom_modules, 1);
+        sim.lock_first();
+        assert_eq!(sim.custom_modules, 3);
+
+        // Modify the buffs (internal state) so the second buff can be locked.
+        // Making sure that the second buff does not collide with the first buff.
+        if let SlotState::Locked(Buff::Attack) = sim.buffs[0] {
+            sim.set_buff(1, &Buff::MaxAmmo);
+        } else {
+            sim.set_buff(1, &Buff::Attack);
+        }
+        sim.lock_second();
+        assert_eq!(sim.custom_modules, 6);
+
+        sim.reroll();
+
+        assert_eq!(sim.custom_modules, 9);
+        assert_eq!((sim.locks(), sim.lock_modules()), (2, 5));
+        // Locking a locked buff again is free.
+        sim.lock_second();
+        assert_eq!((sim.locks(), sim.lock_modules()), (2, 5));
+
+        sim.restart_counts(0);
+        assert_eq!(
+            (sim.custom_modules(), sim.attempts(), sim.locks()),
+            (0, 0, 0)
+        );
+        sim.restart_counts(4);
+        assert_eq!(
+            (sim.custom_modules(), sim.attempts(), sim.locks()),
+            (4, 4, 0)
+        );
+        assert_eq!(sim.locked_buffs().len(), 2);
+    }
+}
@@ -1,441 +1,600 @@
-use std::{collections::HashSet, mem::ManuallyDrop};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::Duration,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rand::{rngs::StdRng, SeedableRng};
+
+use nikke_overload_simulation::rules::Rules;
+use nikke_overload_simulation::sampling::StopRule;
+use nikke_overload_simulation::simulation::{Column, Strategy, SuiteConfig};
+use nikke_overload_simulation::{
+    advisor, breakeven, markdown, output, registry, sampling, saved, scenario, session, simulation,
+    variance, Buff, BuffSet, SlotState,
+};
+use registry::{ParamValue, Registry, Suite};
+
+/// Simulates rerolling and locking the buffs of NIKKE's overload gear. Without a command, runs
+/// all the suites.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    options: Options,
+}
 
-use enum_assoc::Assoc;
-use more_asserts::assert_lt;
-use rand::Rng;
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
+#[derive(Args)]
+struct Options {
+    /// Runs (or rolls) per row, instead of sampling until the estimates are precise.
+    #[arg(long, global = true, value_parser = positive::<u64>)]
+    attempts: Option<u64>,
+    /// Master seed. The same seed gives the same results. Random by default.
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// Threads that run the simulations. One per CPU by default. The results for a seed are the
+    /// same with any number of threads, unless --max-duration stops the sampling.
+    #[arg(long, global = true, value_parser = positive::<usize>)]
+    threads: Option<usize>,
+    /// Stop sampling a row after this many seconds even if it is not precise yet. The results
+    /// then depend on the speed of the machine, not only on the seed.
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = positive::<u64>)]
+    max_duration: Option<u64>,
+    /// table, json, csv, ndjson, markdown or html.
+    #[arg(long, global = true, default_value = "table")]
+    format: output::Format,
+    /// The statistics of the custom module usage rows, e.g. mean,p90,rerolls,locks,slots, instead
+    /// of all the statistics of the modules.
+    #[arg(long, global = true, value_delimiter = ',')]
+    columns: Vec<Column>,
+    /// A TOML file with the buff chances, slot chances and costs, instead of the game's.
+    #[arg(long, global = true, value_name = "FILE")]
+    rules: Option<PathBuf>,
+    /// Regenerate the results section of this README instead of printing.
+    #[arg(long, global = true, value_name = "PATH")]
+    readme: Option<PathBuf>,
+    /// Draw the module usage plots under the tables, with bars up to this many characters.
+    #[arg(long, global = true, value_name = "WIDTH", value_parser = positive::<usize>)]
+    plot: Option<usize>,
+    /// Write SVG charts of the suites into this directory.
+    #[arg(long, global = true, value_name = "DIR")]
+    charts: Option<PathBuf>,
+    /// Save the reports with all their samples to this file, for merging.
+    #[arg(long, global = true, value_name = "FILE")]
+    save: Option<PathBuf>,
+    /// Scenario files (TOML or YAML), or directories of them, to add to the suites.
+    #[arg(long, global = true, value_name = "PATH")]
+    scenarios: Vec<PathBuf>,
+    /// none, crn (common random numbers), antithetic or both.
+    #[arg(long, global = true)]
+    variance_reduction: Option<sampling::VarianceReduction>,
+}
 
-mod simulation;
+#[derive(Subcommand)]
+enum Command {
+    /// List or run the suites.
+    Suites {
+        #[command(subcommand)]
+        command: SuitesCommand,
+    },
+    /// How likely a roll shows all the wanted buffs.
+    Prob {
+        /// The wanted buffs, e.g. Attack,Elemental or atk,elem.
+        #[arg(long, required = true, value_delimiter = ',')]
+        want: Vec<Buff>,
+    },
+    /// The custom modules that a strategy uses to get the wanted buffs.
+    Cost {
+        /// The wanted buffs, e.g. Attack,Elemental. The locked and waited strategies lock the
+        /// first one.
+        #[arg(long, required = true, value_delimiter = ',')]
+        want: Vec<Buff>,
+        #[arg(long, default_value = "lock-immediately")]
+        strategy: StrategyArg,
+        /// The slot (1 to 3) that the locked and waited strategies lock the first wanted buff in.
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        slot: Option<u8>,
+        /// With the waited strategy, the roll on which the first wanted buff appeared in --slot:
+        /// the modules still needed from then on.
+        #[arg(long, value_name = "ROLL", value_parser = positive::<u32>)]
+        shown_on: Option<u32>,
+    },
+    /// Within how many modules a wanted buff must appear in a slot for waiting for it to be worth
+    /// it, compared to locking the wanted buffs as soon as they appear.
+    BreakEven {
+        /// The wanted buffs, e.g. Attack,Elemental. Each of them is waited for in turn.
+        #[arg(long, required = true, value_delimiter = ',')]
+        want: Vec<Buff>,
+        /// Only this slot (1 to 3), instead of every slot.
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        slot: Option<u8>,
+    },
+    /// Whether to reroll a roll, lock one of its buffs or stop, and how likely each gets the
+    /// wanted buffs at what cost.
+    Advise {
+        /// The wanted buffs, e.g. Attack,Elemental or atk,elem.
+        #[arg(long, required = true, value_delimiter = ',')]
+        want: Vec<Buff>,
+        /// The buffs of the roll by slot, with - for a slot without a buff, e.g. Attack,-,Defense.
+        #[arg(long, required = true, value_delimiter = ',')]
+        slots: Vec<String>,
+        /// The slots (1 to 3) that are locked.
+        #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(1..=3))]
+        locked: Vec<u8>,
+        /// The custom modules left. Unlimited by default.
+        #[arg(long)]
+        stock: Option<u32>,
+    },
+    /// Practice rerolling and locking on a simulated gear, with the advisor's suggestions.
+    Play {
+        /// The wanted buffs, e.g. Attack,Elemental or atk,elem.
+        #[arg(long, required = true, value_delimiter = ',')]
+        want: Vec<Buff>,
+        /// The custom modules to start with. Unlimited by default.
+        #[arg(long)]
+        stock: Option<u32>,
+        /// Record the session to this file.
+        #[arg(long, value_name = "FILE")]
+        transcript: Option<PathBuf>,
+    },
+    /// Merge saved runs and print the merged results.
+    Merge {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Measure how much the variance reduction options reduce the variance.
+    VarianceReduction,
+}
 
-const MAX_LOCK_COUNT: u32 = 2;
+#[derive(Subcommand)]
+enum SuitesCommand {
+    /// List the suites with their tags and parameters.
+    List {
+        /// Only the suites with all these tags.
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+    },
+    /// Run the named suites, or all of them.
+    Run {
+        names: Vec<String>,
+        /// Only the suites with all these tags.
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+        /// A parameter of the suites that take it, e.g. rolls=5000. See 'suites list'.
+        #[arg(long, value_name = "NAME=VALUE", value_parser = registry::parse_param)]
+        param: Vec<ParamValue>,
+    },
+}
 
-#[derive(Assoc, EnumIter, Clone, Copy, Debug, PartialEq, Eq, Hash)]
-#[func(pub fn percent(&self) -> f64)]
-enum Buff {
-    #[assoc(percent = 10.0)]
-    Elemental,
-    #[assoc(percent = 12.0)]
-    HitRate,
-    #[assoc(percent = 12.0)]
-    MaxAmmo,
-    #[assoc(percent = 10.0)]
-    Attack,
-    #[assoc(percent = 12.0)]
-    ChargeDamage,
-    #[assoc(percent = 12.0)]
-    ChargeSpeed,
-    #[assoc(percent = 12.0)]
-    CritRate,
-    #[assoc(percent = 10.0)]
-    CritDamage,
-    #[assoc(percent = 10.0)]
-    Defense,
+#[derive(Clone, Copy, ValueEnum)]
+enum StrategyArg {
+    /// Never lock.
+    NoLocking,
+    /// Lock every wanted buff as soon as it appears.
+    LockImmediately,
+    /// Lock the first wanted buff in --slot after the first roll, then lock the others as soon
+    /// as they appear.
+    Locked,
+    /// Reroll without locking until the first wanted buff appears in --slot and lock it, then
+    /// lock the others as soon as they appear.
+    Waited,
 }
 
-#[derive(PartialEq)]
-enum AdditionalSlots {
-    None,
-    SecondOnly,
-    ThirdOnly,
-    SecondAndThird,
+fn positive<T: std::str::FromStr + PartialOrd + Default>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|value| *value > T::default())
+        .ok_or(format!("'{value}' is not a positive number"))
 }
 
-enum SlotState {
-    // There are no values in the slot.
-    None,
-    // The buff may change on re-roll.
-    Free(Buff),
-    // The buff will not change on reroll.
-    Locked(Buff),
+fn parse_slots(rules: &Rules, slots: &[String], locked: &[u8]) -> Result<[SlotState; 3], String> {
+    if slots.len() != 3 {
+        return Err("--slots needs the three slots, e.g. Attack,-,Defense".to_string());
+    }
+    let mut states = [SlotState::None; 3];
+    for (pos, slot) in slots.iter().enumerate() {
+        let is_locked = locked.contains(&(pos as u8 + 1));
+        states[pos] = match (slot.as_str(), is_locked) {
+            ("-", false) => SlotState::None,
+            ("-", true) => return Err(format!("slot {} has no buff to lock", pos + 1)),
+            (name, false) => SlotState::Free(name.parse().map_err(|error| format!("{error}"))?),
+            (name, true) => SlotState::Locked(name.parse().map_err(|error| format!("{error}"))?),
+        };
+    }
+    advisor::check_slots(rules, &states).map_err(|error| format!("--slots: {error}"))?;
+    Ok(states)
 }
 
-struct Simulation {
-    custom_modules: u32,
-    attempts: u32,
-    buffs: Vec<SlotState>,
+// The strategy of the cost command. The locked and waited strategies start from the first of
+// |want| in the slot (1 to 3).
+fn cost_strategy(strategy: StrategyArg, want: &[Buff], slot: Option<u8>) -> Strategy {
+    let position = slot.map(|slot| slot as usize - 1);
+    match strategy {
+        StrategyArg::NoLocking => Strategy::NoLocking,
+        StrategyArg::LockImmediately => Strategy::LockImmediately,
+        StrategyArg::Locked => Strategy::Locked(want[0], position.unwrap()),
+        StrategyArg::Waited => Strategy::Waited(want[0], position.unwrap()),
+    }
 }
 
-impl Simulation {
-    pub fn new() -> Self {
-        Simulation {
-            custom_modules: 0,
-            attempts: 0,
-            buffs: vec![SlotState::None, SlotState::None, SlotState::None],
+// The suites that the command runs with their parameters, or an error for the commands that do
+// not run suites.
+fn selected_suites<'a>(
+    registry: &'a Registry,
+    command: &'a Option<Command>,
+) -> Result<(Vec<&'a Suite>, &'a [ParamValue]), String> {
+    match command {
+        None => Ok((registry.select(&[], &[])?, &[])),
+        Some(Command::Suites {
+            command: SuitesCommand::Run { names, tag, param },
+        }) => {
+            let suites = registry.select(names, tag)?;
+            if suites.is_empty() {
+                return Err("no suite has all the tags".to_string());
+            }
+            Registry::check_params(&suites, param)?;
+            Ok((suites, param))
         }
+        _ => Err("not a suite command".to_string()),
     }
+}
 
-    pub fn buffs(&self) -> &Vec<SlotState> {
-        &self.buffs
+fn check_options(cli: &Cli, registry: &Registry, rules: &Rules) -> Result<(), String> {
+    let options = &cli.options;
+    if options.plot.is_some()
+        && (options.format != output::Format::Table || options.readme.is_some())
+    {
+        return Err("--plot is only supported by the table format".to_string());
     }
-
-    /// Reroll the buffs. Locked buffs will not change, and will use more custom modules accordingly.
-    pub fn reroll(&mut self) {
-        let lock_count = self.locked_count();
-
-        let cus_mod_usage = lock_count + 1;
-
-        self.custom_modules += cus_mod_usage;
-        self.attempts += 1;
-        self.reroll_buffs();
+    if options.attempts.is_some() && options.max_duration.is_some() {
+        return Err("--max-duration does not go with --attempts".to_string());
     }
-
-    // First this collects all locked buffs so that it does not appear in the next draw.
-    //
-    // The rest is the same as initial roll. Except that the locked ones are skipped.
-    fn reroll_buffs(&mut self) {
-        let locked_buffs: HashSet<&Buff> = self
-            .buffs
-            .iter()
-            .filter_map(|item| match item {
-                SlotState::Locked(buff) => Some(buff),
-                _ => None,
-            })
-            .collect();
-
-        let mut buffs: Vec<Buff> = Buff::iter().collect();
-        buffs.retain(|item| !locked_buffs.contains(item));
-
-        match self.buffs[0] {
-            SlotState::None | SlotState::Free(_) => {
-                let first_buff = choose(&buffs);
-                buffs.retain(|b| b != &first_buff);
-                self.buffs[0] = SlotState::Free(first_buff);
-            }
-            SlotState::Locked(_) => {
-                // do nothing when locked.
-            }
+    if matches!(cli.command, Some(Command::VarianceReduction))
+        && (options.format != output::Format::Table
+            || options.readme.is_some()
+            || options.plot.is_some()
+            || options.charts.is_some()
+            || options.save.is_some()
+            || options.attempts.is_some()
+            || options.max_duration.is_some()
+            || !options.columns.is_empty()
+            || options.variance_reduction.is_some())
+    {
+        return Err("variance-reduction only supports --seed, --threads and --rules".to_string());
+    }
+    if matches!(cli.command, Some(Command::Play { .. }))
+        && (options.format != output::Format::Table
+            || options.readme.is_some()
+            || options.plot.is_some()
+            || options.charts.is_some()
+            || options.save.is_some()
+            || options.attempts.is_some()
+            || options.max_duration.is_some()
+            || !options.columns.is_empty()
+            || options.variance_reduction.is_some())
+    {
+        return Err("play only supports --seed, --threads and --rules".to_string());
+    }
+    if let Some(
+        Command::Prob { want }
+        | Command::Cost { want, .. }
+        | Command::BreakEven { want, .. }
+        | Command::Advise { want, .. }
+        | Command::Play { want, .. },
+    ) = &cli.command
+    {
+        scenario::check_want(want).map_err(|error| format!("--want: {error}"))?;
+    }
+    match &cli.command {
+        Some(Command::Cost { strategy, slot, .. })
+            if matches!(strategy, StrategyArg::Locked | StrategyArg::Waited) != slot.is_some() =>
+        {
+            return Err("--slot goes with --strategy locked or waited".to_string());
         }
-        let extra_slots = additional_slots();
-        if extra_slots == AdditionalSlots::None {
-            return;
+        Some(Command::Cost {
+            strategy,
+            shown_on: Some(_),
+            ..
+        }) if !matches!(strategy, StrategyArg::Waited) => {
+            return Err("--shown-on goes with --strategy waited".to_string());
         }
-
-        match self.buffs[1] {
-            SlotState::None | SlotState::Free(_) => {
-                // Note that the match is for the second buff slot. So this should not affect
-                // the third buff slot.
-                if extra_slots == AdditionalSlots::SecondOnly
-                    || extra_slots == AdditionalSlots::SecondAndThird
-                {
-                    let another_buff = choose(&buffs);
-                    buffs.retain(|b| b != &another_buff);
-                    self.buffs[1] = SlotState::Free(another_buff);
-                }
-            }
-            SlotState::Locked(_) => {}
+        Some(Command::Prob { want }) => {
+            Strategy::NoLocking.check(rules, want)?;
         }
-
-        if extra_slots == AdditionalSlots::SecondOnly {
-            return;
+        Some(Command::Cost {
+            want,
+            strategy,
+            slot,
+            ..
+        }) => {
+            cost_strategy(*strategy, want, *slot).check(rules, want)?;
         }
-
-        match self.buffs[2] {
-            SlotState::None | SlotState::Free(_) => {
-                if extra_slots == AdditionalSlots::ThirdOnly
-                    || extra_slots == AdditionalSlots::SecondAndThird
-                {
-                    let another_buff = choose(&buffs);
-                    buffs.retain(|b| b != &another_buff);
-                    self.buffs[2] = SlotState::Free(another_buff);
-                }
-            }
-            SlotState::Locked(_) => {}
+        Some(Command::Advise { slots, locked, .. }) => {
+            parse_slots(rules, slots, locked)?;
         }
-    }
-
-    pub fn has_buff(&self, buff: &Buff) -> bool {
-        self.buffs.iter().any(|state| match state {
-            SlotState::Free(b) | SlotState::Locked(b) => buff == b,
-            _ => false,
-        })
-    }
-
-    pub fn position_of(&self, buff: &Buff) -> Option<usize> {
-        self.buffs.iter().position(|state| match state {
-            SlotState::Free(b) | SlotState::Locked(b) => b.eq(buff),
-            _ => false,
-        })
-    }
-
-    // Locks the buff if there is a buf and is not locked already. This uses
-    // custom modules on lock.
-    pub fn lock(&mut self, pos: usize) {
-        assert_lt!(pos, self.buffs.len());
-        let locked_count = self.locked_count();
-        // It does not make sense to lock the third slot. Just don't reroll.
-        // Note that this could happen in simluation code, just ignore it as it should have reached
-        // a terminating condition.
-        if locked_count >= MAX_LOCK_COUNT {
-            return;
+        Some(Command::Suites {
+            command: SuitesCommand::Run { .. },
+        }) => {
+            selected_suites(registry, &cli.command)?;
         }
-
-        if let SlotState::Free(buff) = &self.buffs[pos] {
-            self.buffs[pos] = SlotState::Locked(*buff);
-            // Note that the locked count was calculated before locking with the statement above,
-            // so +2 here.
-            self.custom_modules += locked_count + 2;
+        Some(Command::Suites {
+            command: SuitesCommand::List { tag },
+        }) => {
+            registry.select(&[], tag)?;
         }
+        _ => {}
     }
+    Ok(())
+}
 
-    fn locked_count(&self) -> u32 {
-        self.buffs
-            .iter()
-            .map(|state| match state {
-                SlotState::Locked(_) => 1,
-                _ => 0,
-            })
-            .sum()
+// The standard suites and the scenarios of --scenarios.
+fn registry(options: &Options) -> Result<Registry, String> {
+    let mut registry = Registry::standard();
+    for path in &options.scenarios {
+        for scenario in scenario::load(path)? {
+            registry.add(scenario.suite());
+        }
     }
+    Ok(registry)
+}
 
-    // Force sets the buff at position as non-locked buff.
-    pub fn set_buff(&mut self, pos: usize, buff: &Buff) {
-        self.buffs[pos] = SlotState::Free(*buff);
+fn suite_config(options: &Options) -> Result<SuiteConfig, String> {
+    let mut config = SuiteConfig::default();
+    if let Some(seed) = options.seed {
+        config.seed = seed;
     }
-
-    pub fn lock_first(&mut self) {
-        self.lock(0);
+    if let Some(variance_reduction) = options.variance_reduction {
+        config.variance_reduction = variance_reduction;
     }
-
-    pub fn lock_second(&mut self) {
-        self.lock(1);
+    if !options.columns.is_empty() {
+        config.columns = options.columns.clone();
     }
-
-    pub fn lock_third(&mut self) {
-        self.lock(2);
+    if let Some(path) = &options.rules {
+        config.rules = Arc::new(Rules::load(path)?);
     }
+    if let Some(attempts) = options.attempts {
+        let fixed = StopRule::fixed(attempts);
+        config.modules_stop = fixed.clone();
+        config.probability_stop = fixed;
+    }
+    if let Some(seconds) = options.max_duration {
+        let duration = Some(Duration::from_secs(seconds));
+        config.modules_stop.max_duration = duration;
+        config.probability_stop.max_duration = duration;
+    }
+    Ok(config)
 }
 
-// Choose a buff not specified in |buffs|.
-fn choose(buffs: &[Buff]) -> Buff {
-    let sum: f64 = buffs.iter().map(|b| b.percent()).sum();
-
-    let mut rng = rand::thread_rng();
-    let value = rng.gen_range(0.0..sum);
-
-    let mut accum = 0.0;
-    for b in buffs.iter() {
-        let next_threshold = accum + b.percent();
-        if value < next_threshold {
-            return *b;
+// Writes the reports of the saved runs that were merged, or runs the command's suites.
+fn write_reports<W: Write>(
+    output: &mut output::Output<W>,
+    registry: &Registry,
+    config: &SuiteConfig,
+    command: &Option<Command>,
+    merged: Option<&saved::SavedRun>,
+) -> io::Result<()> {
+    if let Some(merged) = merged {
+        return merged
+            .reports
+            .iter()
+            .try_for_each(|report| output.suite(report));
+    }
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidInput, error);
+    match command {
+        Some(Command::Prob { want }) => {
+            output.suite(&simulation::suite_want_probability(config, want))
+        }
+        Some(Command::Cost {
+            want,
+            strategy,
+            slot,
+            shown_on,
+        }) => {
+            if let Some(roll) = shown_on {
+                return output.suite(&simulation::suite_shown_in_slot(
+                    config,
+                    want,
+                    want[0],
+                    slot.unwrap() as usize - 1,
+                    *roll,
+                ));
+            }
+            let strategy = cost_strategy(*strategy, want, *slot);
+            output.suite(&simulation::suite_strategy_cost(config, want, strategy))
+        }
+        Some(Command::BreakEven { want, slot }) => {
+            let slots: Vec<usize> = match slot {
+                Some(slot) => vec![*slot as usize - 1],
+                None => (0..3).collect(),
+            };
+            output.suite(&breakeven::suite_want_break_even(config, want, &slots).map_err(invalid)?)
+        }
+        Some(Command::Advise {
+            want,
+            slots,
+            locked,
+            stock,
+        }) => {
+            let slots = parse_slots(&config.rules, slots, locked).map_err(invalid)?;
+            let want = BuffSet::from_iter(want.iter().copied());
+            output.suite(&advisor::advise(config, slots, want, *stock))
+        }
+        command => {
+            let (suites, params) = selected_suites(registry, command).map_err(invalid)?;
+            suites.into_iter().try_for_each(|suite| {
+                let report = suite.run(config, params).map_err(invalid)?;
+                output.suite(&report)
+            })
         }
-        accum = next_threshold;
     }
-
-    panic!("With correct percentage calculation, it should not reach here!");
 }
 
-fn additional_slots() -> AdditionalSlots {
-    let mut rng = rand::thread_rng();
-
-    // Draw for second slot.
-    let value = rng.gen_range(0.0..100.0);
-    let mut gets_second_slot = false;
-    if value < 50.0 {
-        gets_second_slot = true;
+// Applies the options that all the outputs share.
+fn configure_output<W: Write>(
+    mut output: output::Output<W>,
+    options: &Options,
+    seeds: &[u64],
+) -> io::Result<output::Output<W>> {
+    if let Some(width) = options.plot {
+        output = output.with_plots(width);
     }
-
-    // Draw for third slot.
-    let value = rng.gen_range(0.0..100.0);
-    let mut gets_third_slot = false;
-    if value < 30.0 {
-        gets_third_slot = true;
+    if let Some(dir) = &options.charts {
+        output = output.with_charts(dir)?;
     }
-
-    if gets_second_slot && gets_third_slot {
-        return AdditionalSlots::SecondAndThird;
-    } else if gets_second_slot {
-        return AdditionalSlots::SecondOnly;
-    } else if gets_third_slot {
-        return AdditionalSlots::ThirdOnly;
+    if let Some(path) = &options.save {
+        output = output.with_save(path, seeds);
     }
-    AdditionalSlots::None
+    Ok(output)
 }
 
-fn main() {
-    simulation::simulation_slots_shown_distribution();
-    simulation::suite_desired_buff_percent();
-    simulation::suite_two_desired_buffs_custom_mod_usage();
-    simulation::suite_two_desired_buffs_custom_mod_usage_with_locking();
-    simulation::suite_simulation_first_slot_buff_locked();
-    simulation::suite_simulation_second_slot_buff_locked();
-    simulation::suite_simulation_third_slot_buff_locked();
-}
-
-#[cfg(test)]
-mod test {
-
-    use itertools::Itertools;
-    use more_asserts::{assert_ge, assert_le};
-    use std::{collections::HashMap, vec};
-
-    use super::*;
-
-    #[test]
-    fn check_distribution_10000() {
-        let mut samples = vec![];
-        let buffs: Vec<Buff> = Buff::iter().collect();
-        for _ in 0..10000 {
-            let buff = choose(&buffs);
-            samples.push(buff);
-        }
-
-        let counts: HashMap<Buff, usize> = samples.into_iter().counts();
-        let range_for_10_percent = 500..1500;
-
-        assert!(range_for_10_percent.contains(&counts[&Buff::Elemental]));
-        assert!(range_for_10_percent.contains(&counts[&Buff::Attack]));
-        assert!(range_for_10_percent.contains(&counts[&Buff::CritDamage]));
-        assert!(range_for_10_percent.contains(&counts[&Buff::Defense]));
-
-        let range_for_12_percent = 600..2000;
-        assert!(range_for_12_percent.contains(&counts[&Buff::HitRate]));
-        assert!(range_for_12_percent.contains(&counts[&Buff::MaxAmmo]));
-        assert!(range_for_12_percent.contains(&counts[&Buff::ChargeDamage]));
-        assert!(range_for_12_percent.contains(&counts[&Buff::ChargeSpeed]));
-        assert!(range_for_12_percent.contains(&counts[&Buff::CritRate]));
+fn run(cli: Cli, registry: &Registry, mut config: SuiteConfig) -> io::Result<()> {
+    let options = &cli.options;
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(io::Error::other)?;
     }
 
-    #[test]
-    fn check_partial_choices() {
-        let choices = [Buff::Attack, Buff::Elemental];
-        let samples: Vec<Buff> = (0..10000).map(|_| choose(&choices)).collect();
-
-        let counts: HashMap<Buff, usize> = samples.into_iter().counts();
-        assert_eq!(counts.len(), 2);
-        assert!(counts.contains_key(&Buff::Attack));
-        assert!(counts.contains_key(&Buff::Elemental));
-    }
-
-    #[test]
-    fn simulation_reroll_init() {
-        let mut sim = Simulation::new();
-        sim.reroll();
-
-        assert_eq!(sim.attempts, 1);
-        assert_eq!(sim.custom_modules, 1);
-        assert_eq!(sim.buffs().len(), 3);
-
-        assert!(matches!(
-            sim.buffs[0],
-            SlotState::Locked(_) | SlotState::Free(_)
-        ));
-    }
-
-    // Verify that the distribution of the buffs are OK.
-    #[test]
-    fn simulation_reroll_multiiple() {
-        let mut sim = Simulation::new();
-        let mut all_buffs = vec![];
-        for _ in 0..10000 {
-            sim.reroll();
-            let buffs: Vec<_> = sim
-                .buffs()
-                .iter()
-                .filter_map(|item| match item {
-                    SlotState::Free(b) => Some(b.clone()),
-                    SlotState::Locked(b) => Some(b.clone()),
-                    SlotState::None => None,
-                })
-                .collect();
-
-            all_buffs.extend(buffs);
+    let merge = match &cli.command {
+        Some(Command::Suites {
+            command: SuitesCommand::List { tag },
+        }) => {
+            let suites = registry
+                .select(&[], tag)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+            let mut out = io::stdout().lock();
+            for suite in suites {
+                writeln!(out, "{:20} {}", suite.name, suite.description)?;
+                writeln!(out, "{:20} tags: {}", "", suite.tags.join(", "))?;
+                for param in &suite.params {
+                    let default = match param.default {
+                        Some(default) => format!(" (default {default})"),
+                        None => String::new(),
+                    };
+                    writeln!(
+                        out,
+                        "{:20} {}={}: {}{default}",
+                        "",
+                        param.name,
+                        param.kind.name(),
+                        param.description
+                    )?;
+                }
+            }
+            return Ok(());
         }
-
-        let all_buffs_len = all_buffs.len();
-
-        let counts: HashMap<Buff, usize> = all_buffs.into_iter().counts();
-        println!("counts: {:?}", counts);
-
-        // For 10% buffs, assume they occur about 7%-13% of all buffs.
-        let seven_percent = (0.07 * all_buffs_len as f64) as usize;
-        let thirteen_percent = (0.13 * all_buffs_len as f64) as usize;
-
-        assert_ge!(counts[&Buff::Elemental], seven_percent);
-        assert_le!(counts[&Buff::Elemental], thirteen_percent);
-        assert_ge!(counts[&Buff::Attack], seven_percent);
-        assert_le!(counts[&Buff::Attack], thirteen_percent);
-        assert_ge!(counts[&Buff::CritDamage], seven_percent);
-        assert_le!(counts[&Buff::CritDamage], thirteen_percent);
-        assert_ge!(counts[&Buff::Defense], seven_percent);
-        assert_le!(counts[&Buff::Defense], thirteen_percent);
-
-        // For 12% buffs, assume they occur about 9%-15% of all buffs.
-        let nine_percent = (0.09 * all_buffs_len as f64) as usize;
-        let fifteen_percent = (0.15 * all_buffs_len as f64) as usize;
-
-        assert_ge!(counts[&Buff::HitRate], nine_percent);
-        assert_le!(counts[&Buff::HitRate], fifteen_percent);
-        assert_ge!(counts[&Buff::MaxAmmo], nine_percent);
-        assert_le!(counts[&Buff::MaxAmmo], fifteen_percent);
-        assert_ge!(counts[&Buff::ChargeDamage], nine_percent);
-        assert_le!(counts[&Buff::ChargeDamage], fifteen_percent);
-        assert_ge!(counts[&Buff::ChargeSpeed], nine_percent);
-        assert_le!(counts[&Buff::ChargeSpeed], fifteen_percent);
-        assert_ge!(counts[&Buff::CritRate], nine_percent);
-        assert_le!(counts[&Buff::CritRate], fifteen_percent);
-    }
-
-    #[test]
-    fn simulation_reroll_custom_module_count() {
-        let mut sim = Simulation::new();
-        for _ in 0..1000 {
-            sim.reroll();
+        Some(Command::VarianceReduction) => {
+            let measurements = variance::measure(&config.rules, config.seed);
+            let mut out = io::stdout().lock();
+            writeln!(out, "seed: {}", config.seed)?;
+            return write!(out, "{}", variance::table(&config.rules, &measurements));
         }
-
-        assert_eq!(sim.attempts, 1000);
-        assert_eq!(sim.custom_modules, 1000);
-    }
-
-    #[test]
-    fn has_buff() {
-        let mut sim = Simulation::new();
-        sim.reroll();
-
-        let SlotState::Free(buff) = &sim.buffs()[0] else {
-            panic!("First slot on first roll must be free state.");
-        };
-
-        assert!(sim.has_buff(buff));
+        Some(Command::Play {
+            want,
+            stock,
+            transcript,
+        }) => {
+            let want = BuffSet::from_iter(want.iter().copied());
+            let rng = StdRng::seed_from_u64(sampling::derive_seed(config.seed, "play"));
+            let mut session = session::Session::new(&config, want, *stock, rng);
+            let mut transcript = match transcript {
+                Some(path) => Some(BufWriter::new(File::create(path)?)),
+                None => None,
+            };
+            let seed = format!("seed: {}\n", config.seed);
+            let mut out = io::stdout().lock();
+            out.write_all(seed.as_bytes())?;
+            if let Some(transcript) = transcript.as_mut() {
+                transcript.write_all(seed.as_bytes())?;
+            }
+            return session::run(
+                &mut session,
+                io::stdin().lock(),
+                &mut out,
+                transcript.as_mut().map(|file| file as &mut dyn Write),
+            );
+        }
+        Some(Command::Merge { files }) => files.as_slice(),
+        _ => &[],
+    };
+
+    let merged = if merge.is_empty() {
+        None
+    } else {
+        let runs = merge
+            .iter()
+            .map(|path| saved::read(path))
+            .collect::<io::Result<Vec<_>>>()?;
+        Some(
+            saved::merge(runs)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+        )
+    };
+    let (parameters, seeds) = match &merged {
+        Some(merged) => (merged.parameters(&config), merged.seeds.clone()),
+        None => (config.parameters(), vec![config.seed]),
+    };
+
+    if let Some(readme) = &options.readme {
+        let output = output::Output::readme_section(&config, &parameters, vec![])?;
+        let mut output = configure_output(output, options, &seeds)?;
+        write_reports(
+            &mut output,
+            registry,
+            &config,
+            &cli.command,
+            merged.as_ref(),
+        )?;
+        let generated = output.finish()?;
+        return markdown::update_readme(readme, &String::from_utf8_lossy(&generated));
     }
 
-    // Verify that locking the first slot and rerolling should consume more custom modules.
-    #[test]
-    fn locking_should_use_more_custom_modules() {
-        let mut sim = Simulation::new();
-        sim.reroll();
-
-        assert_eq!(sim.custom_modules, 1);
-
-        sim.lock_first();
-        assert_eq!(sim.custom_modules, 3);
-        sim.reroll();
-
-        assert_eq!(sim.custom_modules, 5);
+    if options.format == output::Format::Ndjson {
+        config.runs = Some(Arc::new(output::NdjsonRuns));
     }
+    let output = output::Output::new(options.format, &config, &parameters, io::stdout().lock())?;
+    let mut output = configure_output(output, options, &seeds)?;
+    write_reports(
+        &mut output,
+        registry,
+        &config,
+        &cli.command,
+        merged.as_ref(),
+    )?;
+    output.finish()?.flush()
+}
 
-    // Verify that locking the first slot and rerolling should consume more custom modules.
-    #[test]
-    fn locking_should_use_more_custom_modules_locking_two_slots() {
-        let mut sim = Simulation::new();
-        sim.reroll();
-
-        assert_eq!(sim.custom_modules, 1);
-        sim.lock_first();
-        assert_eq!(sim.custom_modules, 3);
-
-        // Modify the buffs (internal state) so the second buff can be locked.
-        // Making sure that the second buff does not collide with the first buff.
-        if let SlotState::Locked(Buff::Attack) = sim.buffs[0] {
-            sim.set_buff(1, &Buff::MaxAmmo);
-        } else {
-            sim.set_buff(1, &Buff::Attack);
+fn main() {
+    let cli = Cli::parse();
+    let registry = match registry(&cli.options) {
+        Ok(registry) => registry,
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(2);
         }
-        sim.lock_second();
-        assert_eq!(sim.custom_modules, 6);
-
-        sim.reroll();
+    };
+    let config = match suite_config(&cli.options) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(2);
+        }
+    };
+    if let Err(error) = check_options(&cli, &registry, &config.rules) {
+        eprintln!("{error}");
+        process::exit(2);
+    }
 
-        assert_eq!(sim.custom_modules, 9);
+    if let Err(error) = run(cli, &registry, config) {
+        eprintln!("{error}");
+        process::exit(1);
     }
 }
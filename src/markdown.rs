@@ -0,0 +1,141 @@
+// Markdown rendering of the suite reports, e.g. for the result section of README.md.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use strum::IntoEnumIterator;
+use tabled::{builder::Builder, settings::style::Style};
+
+use crate::report::SuiteReport;
+use crate::rules::Rules;
+use crate::Buff;
+
+// The generated results in README.md are between these lines.
+const README_BEGIN_MARKER: &str = "<!-- BEGIN GENERATED RESULTS -->";
+const README_END_MARKER: &str = "<!-- END GENERATED RESULTS -->";
+
+const REGENERATE_COMMAND: &str = "cargo run --release -- --readme README.md";
+
+fn table(data: Vec<Vec<String>>) -> String {
+    let mut table = Builder::from(data).build();
+    table.with(Style::markdown());
+    format!("{table}\n")
+}
+
+/// The |parameters| of the run and the buff table of |rules|. |title| adds a top level heading, for a
+/// standalone document.
+pub fn header(parameters: &[(&str, String)], rules: &Rules, title: bool) -> String {
+    let mut markdown = String::new();
+    if title {
+        markdown += "# Overload gear simulation results\n\n";
+    }
+
+    markdown += "## Parameters\n\n";
+    let mut data = vec![vec!["parameter".to_string(), "value".to_string()]];
+    data.extend(
+        parameters
+            .iter()
+            .map(|(name, value)| vec![name.to_string(), value.clone()]),
+    );
+    markdown += &table(data);
+    markdown += &format!(
+        "\nRegenerate with `{REGENERATE_COMMAND}`. Runs with the same seed give the same \
+        results, unless a time budget stopped the sampling.\n\n"
+    );
+
+    markdown += "## Buffs\n\n";
+    let mut data = vec![vec!["buff".to_string(), "chance".to_string()]];
+    data.extend(
+        Buff::iter().map(|buff| vec![format!("{buff:?}"), format!("{}%", rules.percent(buff))]),
+    );
+    markdown += &table(data);
+    markdown += "\n";
+    markdown
+}
+
+/// A section with the suite's name, description and |data| as a table. |data| is the header row
+/// followed by the rows.
+pub fn suite_section(report: &SuiteReport, data: &[Vec<String>]) -> String {
+    let mut markdown = format!(
+        "## {}\n\n{}\n\n{}\n",
+        report.name,
+        report.plain_description(),
+        table(data.to_vec())
+    );
+    if let Some(heatmap) = &report.heatmap {
+        markdown += &format!("{}:\n\n{}\n", heatmap.title, table(heatmap.table_data()));
+    }
+    markdown
+}
+
+/// An image of |path| with |title| as the alternative text.
+pub fn image(title: &str, path: &Path) -> String {
+    format!("![{title}]({})\n\n", path.display())
+}
+
+/// A section with the charts that compare the strategies for the same wanted buffs.
+pub fn strategy_charts(charts: &[(String, PathBuf)]) -> String {
+    let mut markdown = "## Strategy comparison\n\n\
+        The share of the runs that finished within N custom modules, for each suite that \
+        wanted the same buffs.\n\n"
+        .to_string();
+    for (title, path) in charts {
+        markdown += &image(title, path);
+    }
+    markdown
+}
+
+// Replaces the lines between the markers in |readme| with |generated|.
+fn splice_readme(readme: &str, generated: &str) -> Result<String, String> {
+    let begin = readme
+        .find(README_BEGIN_MARKER)
+        .ok_or(format!("missing '{README_BEGIN_MARKER}'"))?
+        + README_BEGIN_MARKER.len();
+    let end = readme
+        .find(README_END_MARKER)
+        .filter(|end| *end >= begin)
+        .ok_or(format!(
+            "missing '{README_END_MARKER}' after the begin marker"
+        ))?;
+    Ok(format!(
+        "{}\n\n{}\n{}",
+        &readme[..begin],
+        generated.trim(),
+        &readme[end..]
+    ))
+}
+
+/// Rewrites the generated results section of the README at |path|.
+pub fn update_readme(path: &Path, generated: &str) -> io::Result<()> {
+    let readme = fs::read_to_string(path)?;
+    let updated = splice_readme(&readme, generated)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    fs::write(path, updated)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn splice_readme_replaces_between_markers() {
+        let readme =
+            format!("# Result\nintro\n{README_BEGIN_MARKER}\nold\n{README_END_MARKER}\n# Next\n");
+        let updated = splice_readme(&readme, "new\n").unwrap();
+
+        assert_eq!(
+            updated,
+            format!("# Result\nintro\n{README_BEGIN_MARKER}\n\nnew\n{README_END_MARKER}\n# Next\n")
+        );
+        // Splicing again is stable.
+        assert_eq!(splice_readme(&updated, "new\n").unwrap(), updated);
+    }
+
+    #[test]
+    fn splice_readme_requires_markers() {
+        assert!(splice_readme("# Result\n", "new").is_err());
+    }
+}
@@ -0,0 +1,661 @@
+// Output backends for the suite reports.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use itertools::Itertools;
+use serde_json::{json, Map, Value};
+use tabled::{builder::Builder, settings::style::Style};
+
+use crate::chart::{Chart, Charts};
+use crate::html;
+use crate::markdown;
+use crate::plot;
+use crate::report::{RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::saved;
+use crate::simulation::{
+    Column, ProportionResult, RunOutcome, RunSink, SimulationResult, SuiteConfig, WeightedResult,
+};
+use crate::stats::Interval;
+
+const START_SUITE_MARKER: &str = "===== SUITE START =====";
+const END_SUITE_MARKER: &str = "=====  SUITE END  =====";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable tables.
+    Table,
+    /// A single JSON document with all the suites, written at the end.
+    Json,
+    /// One CSV row per suite row, written at the end.
+    Csv,
+    /// Newline delimited JSON. Individual runs and rows are written as they finish.
+    Ndjson,
+    /// A complete Markdown document with the parameters and GitHub flavored tables.
+    Markdown,
+    /// A single self-contained HTML page with sortable tables and the charts embedded.
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "ndjson" => Ok(Format::Ndjson),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!(
+                "unknown format '{s}', expected one of: table, json, csv, ndjson, markdown, html"
+            )),
+        }
+    }
+}
+
+/// Writes the suite reports in the chosen format to |out|.
+pub struct Output<W: Write> {
+    format: Format,
+    columns: Vec<Column>,
+    // The rules that the suites ran with, for the charts.
+    rules: Arc<Rules>,
+    // Width of the bars of the module usage plots under the tables. None for no plots.
+    plot_width: Option<usize>,
+    charts: Option<Charts>,
+    // Where the charts are written to as SVG files, if at all.
+    chart_dir: Option<PathBuf>,
+    // Where the reports are saved with all their samples, for merging, and the seeds of the runs.
+    save_path: Option<PathBuf>,
+    seeds: Vec<u64>,
+    saved: Vec<Value>,
+    out: W,
+    // Reports that are written together by finish(), for formats that cannot be streamed.
+    pending: Vec<Value>,
+}
+
+impl<W: Write> Output<W> {
+    /// |parameters| are shown in the header of the Markdown and HTML formats, see
+    /// SuiteConfig::parameters().
+    pub fn new(
+        format: Format,
+        config: &SuiteConfig,
+        parameters: &[(&str, String)],
+        mut out: W,
+    ) -> io::Result<Self> {
+        match format {
+            Format::Markdown => {
+                write!(out, "{}", markdown::header(parameters, &config.rules, true))?
+            }
+            Format::Html => write!(out, "{}", html::header(parameters, &config.rules))?,
+            _ => {}
+        }
+        let mut output = Self::without_header(format, config, out);
+        if format == Format::Html {
+            output.charts = Some(Charts::new(config.rules.clone()));
+        }
+        Ok(output)
+    }
+
+    /// Markdown for the results section of a README, i.e. without the document title.
+    pub fn readme_section(
+        config: &SuiteConfig,
+        parameters: &[(&str, String)],
+        mut out: W,
+    ) -> io::Result<Self> {
+        write!(
+            out,
+            "{}",
+            markdown::header(parameters, &config.rules, false)
+        )?;
+        Ok(Self::without_header(Format::Markdown, config, out))
+    }
+
+    fn without_header(format: Format, config: &SuiteConfig, out: W) -> Self {
+        Output {
+            format,
+            columns: config.columns.clone(),
+            rules: config.rules.clone(),
+            plot_width: None,
+            charts: None,
+            chart_dir: None,
+            save_path: None,
+            seeds: vec![],
+            saved: vec![],
+            out,
+            pending: vec![],
+        }
+    }
+
+    /// Draws the histogram and the CDF of the module usage under each table, with bars up to
+    /// |width| characters. Only the table format has plots.
+    pub fn with_plots(mut self, width: usize) -> Self {
+        self.plot_width = Some(width);
+        self
+    }
+
+    /// Also writes SVG charts of the suites into |dir|. The Markdown format links to them.
+    pub fn with_charts(mut self, dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        self.chart_dir = Some(dir.to_path_buf());
+        let rules = self.rules.clone();
+        self.charts.get_or_insert_with(|| Charts::new(rules));
+        Ok(self)
+    }
+
+    /// Also saves the reports with all their samples to |path|, so that they can be merged with
+    /// other runs later. |seeds| are the master seeds of the runs of the reports.
+    pub fn with_save(mut self, path: &Path, seeds: &[u64]) -> Self {
+        self.save_path = Some(path.to_path_buf());
+        self.seeds = seeds.to_vec();
+        self
+    }
+
+    // Writes |chart| into the chart directory, if there is one, and returns its path.
+    fn write_chart(&self, chart: &Chart) -> io::Result<Option<PathBuf>> {
+        let Some(dir) = &self.chart_dir else {
+            return Ok(None);
+        };
+        let path = dir.join(&chart.file_name);
+        fs::write(&path, &chart.svg)?;
+        Ok(Some(path))
+    }
+
+    pub fn suite(&mut self, report: &SuiteReport) -> io::Result<()> {
+        let chart = match &mut self.charts {
+            Some(charts) => Some(charts.suite(report)?),
+            None => None,
+        };
+        let chart_path = match &chart {
+            Some(chart) => self.write_chart(chart)?,
+            None => None,
+        };
+        if self.save_path.is_some() {
+            self.saved.push(report.saved_json());
+        }
+        match self.format {
+            Format::Table => {
+                print_suite_table(&mut self.out, report, &self.columns, self.plot_width)
+            }
+            Format::Json | Format::Csv => {
+                self.pending.push(report_json(report, &self.columns));
+                Ok(())
+            }
+            Format::Ndjson => {
+                let report = report_json(report, &self.columns);
+                for row in report["rows"].as_array().unwrap() {
+                    let mut line = json!({
+                        "type": "row",
+                        "suite": report["name"],
+                    });
+                    line.as_object_mut()
+                        .unwrap()
+                        .extend(row.as_object().unwrap().clone());
+                    writeln!(self.out, "{line}")?;
+                }
+                if let Some(heatmap) = report.get("heatmap") {
+                    let mut line = json!({
+                        "type": "heatmap",
+                        "suite": report["name"],
+                    });
+                    line.as_object_mut()
+                        .unwrap()
+                        .extend(heatmap.as_object().unwrap().clone());
+                    writeln!(self.out, "{line}")?;
+                }
+                Ok(())
+            }
+            Format::Markdown => {
+                write!(
+                    self.out,
+                    "{}",
+                    markdown::suite_section(report, &suite_table_data(report, &self.columns))
+                )?;
+                if let Some(path) = chart_path {
+                    write!(self.out, "{}", markdown::image(&report.name, &path))?;
+                }
+                Ok(())
+            }
+            Format::Html => write!(
+                self.out,
+                "{}",
+                html::suite_section(
+                    report,
+                    &suite_table_data(report, &self.columns),
+                    chart.as_ref()
+                )
+            ),
+        }
+    }
+
+    /// Writes what has not been written yet and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(path) = &self.save_path {
+            saved::write(path, &self.seeds, std::mem::take(&mut self.saved))?;
+        }
+        let comparisons = match self.charts.take() {
+            Some(charts) => charts.finish()?,
+            None => vec![],
+        };
+        let mut comparison_paths = vec![];
+        for chart in &comparisons {
+            if let Some(path) = self.write_chart(chart)? {
+                comparison_paths.push((chart.title.clone(), path));
+            }
+        }
+        match self.format {
+            Format::Table | Format::Ndjson => {}
+            Format::Markdown => {
+                if !comparison_paths.is_empty() {
+                    write!(self.out, "{}", markdown::strategy_charts(&comparison_paths))?;
+                }
+            }
+            Format::Html => write!(self.out, "{}", html::footer(&comparisons))?,
+            Format::Json => writeln!(
+                self.out,
+                "{}",
+                serde_json::to_string_pretty(&Value::Array(self.pending)).unwrap()
+            )?,
+            Format::Csv => write_csv(&self.pending, &mut self.out)?,
+        }
+        Ok(self.out)
+    }
+}
+
+/// Streams the individual runs as NDJSON lines to stdout.
+pub struct NdjsonRuns;
+
+impl RunSink for NdjsonRuns {
+    fn run(&self, suite: &str, result: &SimulationResult, run: &RunOutcome) {
+        let line = json!({
+            "type": "run",
+            "suite": suite,
+            "locked_buff": result.locked_buff.map(|buff| format!("{buff:?}")),
+            "buffs": result.buffs.iter().map(|buff| format!("{buff:?}")).collect::<Vec<_>>(),
+            "modules": run.modules,
+            "rerolls": run.rerolls,
+            "locks": run.locks,
+            "reroll_modules": run.reroll_modules,
+            "lock_modules": run.lock_modules,
+            "slots": run.slots.map(|slot| slot.map(|buff| format!("{buff:?}"))),
+        });
+        writeln!(io::stdout().lock(), "{line}").unwrap();
+    }
+}
+
+fn probability_headers() -> Vec<String> {
+    ["hits", "rolls", "probability", "std error", "95% CI"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect()
+}
+
+// The hits, rolls, probability, standard error and 95% confidence interval of a probability.
+struct ProbabilityStatistics {
+    hits: u64,
+    rolls: u64,
+    probability: f64,
+    std_error: f64,
+    interval: Interval,
+}
+
+impl From<&ProportionResult> for ProbabilityStatistics {
+    fn from(result: &ProportionResult) -> Self {
+        ProbabilityStatistics {
+            hits: result.hits,
+            rolls: result.attempts,
+            probability: result.probability(),
+            std_error: result.standard_error(),
+            interval: result.confidence_interval(),
+        }
+    }
+}
+
+impl From<&WeightedResult> for ProbabilityStatistics {
+    fn from(result: &WeightedResult) -> Self {
+        ProbabilityStatistics {
+            hits: result.hits,
+            rolls: result.attempts,
+            probability: result.probability(),
+            std_error: result.standard_error(),
+            interval: result.confidence_interval(),
+        }
+    }
+}
+
+fn probability_cells(statistics: ProbabilityStatistics) -> Vec<String> {
+    let interval = statistics.interval;
+    vec![
+        statistics.hits.to_string(),
+        statistics.rolls.to_string(),
+        format!("{:.4}%", statistics.probability * 100.0),
+        format!("{:.4}%", statistics.std_error * 100.0),
+        format!(
+            "[{:.4}%, {:.4}%]",
+            interval.lower * 100.0,
+            interval.upper * 100.0
+        ),
+    ]
+}
+
+// The statistics of the module usage rows of |report|: its own, or else |columns|.
+fn report_columns<'a>(report: &'a SuiteReport, columns: &'a [Column]) -> &'a [Column] {
+    report.columns.as_deref().unwrap_or(columns)
+}
+
+// The share of the runs of |result| that used at most |budget| modules.
+fn within_budget(result: &SimulationResult, budget: u32) -> f64 {
+    result.runs_within(budget as u64) as f64 / result.samples() as f64
+}
+
+// The statistic headers of a report. All the rows of a suite measure the same kind of thing, so
+// the first row decides.
+fn statistic_headers(report: &SuiteReport, columns: &[Column]) -> Vec<String> {
+    match report.rows.first().map(|row| &row.result) {
+        Some(RowResult::Probability(_) | RowResult::Weighted(_)) => probability_headers(),
+        Some(RowResult::Outlook(_)) => vec!["success".to_string(), "expected modules".to_string()],
+        Some(RowResult::BreakEven(_)) => [
+            "plain rerolling (modules)",
+            "waiting (modules)",
+            "after it appears (modules)",
+            "break-even (modules)",
+            "break-even (rolls)",
+            "appears in time",
+        ]
+        .iter()
+        .map(|header| header.to_string())
+        .collect(),
+        _ => report_columns(report, columns)
+            .iter()
+            .map(|column| column.header().to_string())
+            .chain(
+                report
+                    .budgets
+                    .iter()
+                    .map(|budget| format!("within {budget} modules")),
+            )
+            .collect(),
+    }
+}
+
+fn statistic_cells(report: &SuiteReport, result: &RowResult, columns: &[Column]) -> Vec<String> {
+    match result {
+        RowResult::Modules(result) => report_columns(report, columns)
+            .iter()
+            .map(|column| column.value(result))
+            .chain(
+                report
+                    .budgets
+                    .iter()
+                    .map(|budget| format!("{:.2}%", within_budget(result, *budget) * 100.0)),
+            )
+            .collect(),
+        RowResult::Probability(result) => probability_cells(result.into()),
+        RowResult::Weighted(result) => probability_cells(result.into()),
+        RowResult::Outlook(outlook) => vec![
+            format!("{:.2}%", outlook.success * 100.0),
+            format!("{:.2}", outlook.modules),
+        ],
+        RowResult::BreakEven(point) => vec![
+            format!("{:.2}", point.plain),
+            format!("{:.2}", point.waited),
+            format!("{:.2}", point.after),
+            format!("{:.2}", point.modules()),
+            point.rolls().to_string(),
+            format!("{:.2}%", point.in_time() * 100.0),
+        ],
+    }
+}
+
+fn indistinguishable_cell(rows: &[usize]) -> String {
+    if rows.is_empty() {
+        "-".to_string()
+    } else {
+        rows.iter().map(|row| format!("#{row}")).join(", ")
+    }
+}
+
+// The rows are compared against each other. The row number column is prepended and a column
+// flagging the rows whose 95% confidence intervals overlap is appended.
+fn suite_table_data(report: &SuiteReport, columns: &[Column]) -> Vec<Vec<String>> {
+    let mut header = vec!["#".to_string()];
+    header.extend(report.labels.iter().cloned());
+    header.extend(statistic_headers(report, columns));
+    header.push("indistinguishable from (95% CI)".to_string());
+
+    let mut data = vec![header];
+    for (i, (row, overlaps)) in report
+        .rows
+        .iter()
+        .zip(report.indistinguishable_rows())
+        .enumerate()
+    {
+        let mut cells = vec![(i + 1).to_string()];
+        cells.extend(row.labels.iter().cloned());
+        cells.extend(statistic_cells(report, &row.result, columns));
+        cells.push(indistinguishable_cell(&overlaps));
+        data.push(cells);
+    }
+    data
+}
+
+fn print_suite_table<W: Write>(
+    out: &mut W,
+    report: &SuiteReport,
+    columns: &[Column],
+    plot_width: Option<usize>,
+) -> io::Result<()> {
+    writeln!(out, "{}", START_SUITE_MARKER)?;
+    writeln!(out, "Name: {}", report.name)?;
+    writeln!(out, "Description: {}", report.description)?;
+
+    let mut table = Builder::from(suite_table_data(report, columns)).build();
+    table.with(Style::modern());
+    writeln!(out, "{table}")?;
+    if let Some(heatmap) = &report.heatmap {
+        let mut table = Builder::from(heatmap.table_data()).build();
+        table.with(Style::modern());
+        writeln!(out, "{}:\n{table}", heatmap.title)?;
+    }
+    if let Some(width) = plot_width {
+        write!(out, "{}", plot::suite_plots(report, width))?;
+    }
+
+    writeln!(out, "{}", END_SUITE_MARKER)
+}
+
+fn statistics_json(
+    report: &SuiteReport,
+    result: &RowResult,
+    columns: &[Column],
+) -> Map<String, Value> {
+    let mut statistics = Map::new();
+    match result {
+        RowResult::Modules(result) => {
+            statistics.extend(
+                report_columns(report, columns)
+                    .iter()
+                    .flat_map(|column| column.json(result)),
+            );
+            for budget in &report.budgets {
+                statistics.insert(
+                    format!("within_{budget}"),
+                    json!(within_budget(result, *budget)),
+                );
+            }
+        }
+        RowResult::Probability(result) => probability_json(&mut statistics, result.into()),
+        RowResult::Weighted(result) => probability_json(&mut statistics, result.into()),
+        RowResult::Outlook(outlook) => {
+            statistics.insert("success".to_string(), json!(outlook.success));
+            statistics.insert("expected_modules".to_string(), json!(outlook.modules));
+        }
+        RowResult::BreakEven(point) => {
+            statistics.insert("plain_modules".to_string(), json!(point.plain));
+            statistics.insert("waited_modules".to_string(), json!(point.waited));
+            statistics.insert("after_modules".to_string(), json!(point.after));
+            statistics.insert("break_even_modules".to_string(), json!(point.modules()));
+            statistics.insert("break_even_rolls".to_string(), json!(point.rolls()));
+            statistics.insert("in_time".to_string(), json!(point.in_time()));
+        }
+    }
+    statistics
+}
+
+fn probability_json(json: &mut Map<String, Value>, statistics: ProbabilityStatistics) {
+    json.insert("hits".to_string(), json!(statistics.hits));
+    json.insert("rolls".to_string(), json!(statistics.rolls));
+    json.insert("probability".to_string(), json!(statistics.probability));
+    json.insert("std_error".to_string(), json!(statistics.std_error));
+    json.insert("ci95_lower".to_string(), json!(statistics.interval.lower));
+    json.insert("ci95_upper".to_string(), json!(statistics.interval.upper));
+}
+
+/// The report as a JSON object. This is also the intermediate form of the CSV and NDJSON
+/// outputs.
+pub fn report_json(report: &SuiteReport, columns: &[Column]) -> Value {
+    let rows: Vec<Value> = report
+        .rows
+        .iter()
+        .zip(report.indistinguishable_rows())
+        .enumerate()
+        .map(|(i, (row, overlaps))| {
+            let labels: Map<String, Value> = report
+                .labels
+                .iter()
+                .cloned()
+                .zip(row.labels.iter().map(|label| json!(label)))
+                .collect();
+            json!({
+                "row": i + 1,
+                "labels": labels,
+                "statistics": statistics_json(report, &row.result, columns),
+                "indistinguishable_from": overlaps,
+            })
+        })
+        .collect();
+
+    let mut json = json!({
+        "name": report.name,
+        "description": report.description,
+        "rows": rows,
+    });
+    if let Some(heatmap) = &report.heatmap {
+        json["heatmap"] = json!({
+            "title": heatmap.title,
+            "labels": heatmap.labels,
+            "cells": heatmap.cells,
+        });
+    }
+    json
+}
+
+// Flattens the reports into a single CSV table: the suite, the row number, the row labels and the
+// statistics. Columns that do not apply to a row are left empty.
+fn write_csv<W: Write>(reports: &[Value], writer: W) -> io::Result<()> {
+    let rows: Vec<(&Value, &Value)> = reports
+        .iter()
+        .flat_map(|report| {
+            report["rows"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(move |row| (report, row))
+        })
+        .collect();
+
+    let label_count = rows
+        .iter()
+        .map(|(_, row)| row["labels"].as_object().unwrap().len())
+        .max()
+        .unwrap_or(0);
+    let statistic_keys: Vec<String> = rows
+        .iter()
+        .flat_map(|(_, row)| row["statistics"].as_object().unwrap().keys().cloned())
+        .unique()
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header = vec!["suite".to_string(), "row".to_string()];
+    header.extend((1..=label_count).map(|i| format!("label_{i}")));
+    header.extend(statistic_keys.iter().cloned());
+    writer.write_record(&header)?;
+
+    for (report, row) in rows {
+        let mut record = vec![
+            report["name"].as_str().unwrap().to_string(),
+            row["row"].to_string(),
+        ];
+        let labels: Vec<String> = row["labels"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|label| label.as_str().unwrap().to_string())
+            .collect();
+        record.extend((0..label_count).map(|i| labels.get(i).cloned().unwrap_or_default()));
+        let statistics = row["statistics"].as_object().unwrap();
+        record.extend(statistic_keys.iter().map(|key| {
+            statistics
+                .get(key)
+                .map(|value| value.to_string())
+                .unwrap_or_default()
+        }));
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::report::ReportRow;
+
+    fn probability_report() -> SuiteReport {
+        let mut report = SuiteReport::new("probabilities", "A description.", &["buffs"]);
+        for (label, hits) in [("a", 10), ("b", 11), ("c", 500)] {
+            report.rows.push(ReportRow {
+                labels: vec![label.to_string()],
+                result: RowResult::Probability(ProportionResult {
+                    hits,
+                    attempts: 1000,
+                }),
+            });
+        }
+        report
+    }
+
+    #[test]
+    fn json_flags_indistinguishable_rows() {
+        let json = report_json(&probability_report(), &[]);
+
+        assert_eq!(json["name"], "probabilities");
+        assert_eq!(json["rows"][0]["labels"]["buffs"], "a");
+        assert_eq!(json["rows"][0]["statistics"]["hits"], 10);
+        assert_eq!(json["rows"][0]["indistinguishable_from"], json!([2]));
+        assert_eq!(json["rows"][2]["indistinguishable_from"], json!([]));
+    }
+
+    #[test]
+    fn csv_has_one_line_per_row() {
+        let mut buffer = vec![];
+        write_csv(&[report_json(&probability_report(), &[])], &mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "suite,row,label_1,hits,rolls,probability,std_error,ci95_lower,ci95_upper"
+        );
+        assert!(lines[1].starts_with("probabilities,1,a,10,1000,0.01,"));
+    }
+}
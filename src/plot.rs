@@ -0,0 +1,143 @@
+// Terminal plots of the module usage distributions, drawn under the suite tables.
+
+use std::fmt::Write;
+
+use crate::report::{RowResult, SuiteReport};
+use crate::simulation::SimulationResult;
+
+// The number of bars up to the 99th percentile. The runs beyond that are put together in one bar
+// so that the long tail does not squash the rest of the plot.
+const BARS: u64 = 20;
+
+// A bar is drawn in eighths of a character.
+const PARTIAL_BLOCKS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+// A horizontal bar |fraction| (0 to 1) of |width| characters long, padded to |width|.
+fn bar(fraction: f64, width: usize) -> String {
+    let eighths = (fraction.clamp(0.0, 1.0) * (width * 8) as f64).round() as usize;
+    let bar = "█".repeat(eighths / 8) + PARTIAL_BLOCKS[eighths % 8];
+    let length = eighths.div_ceil(8);
+    bar + &" ".repeat(width - length)
+}
+
+// The (inclusive) module ranges of the bars. The last range is the tail beyond the 99th
+// percentile, if there is one.
+fn ranges(result: &SimulationResult) -> Vec<(u64, u64)> {
+    let min = result.min();
+    let cutoff = result.percentile(99.0).max(min);
+    let step = (cutoff - min + 1).div_ceil(BARS);
+
+    let mut ranges: Vec<(u64, u64)> = (min..=cutoff)
+        .step_by(step as usize)
+        .map(|low| (low, (low + step - 1).min(result.max())))
+        .collect();
+    let last = ranges.last().unwrap().1;
+    if last < result.max() {
+        ranges.push((last + 1, result.max()));
+    }
+    ranges
+}
+
+fn range_label(low: u64, high: u64) -> String {
+    if low == high {
+        low.to_string()
+    } else {
+        format!("{low}-{high}")
+    }
+}
+
+/// The histogram and the CDF of the modules used by the runs of |result|, with bars up to |width|
+/// characters.
+pub fn modules_plot(result: &SimulationResult, width: usize) -> String {
+    let samples = result.samples();
+    if samples == 0 {
+        return "no runs\n".to_string();
+    }
+    let ranges = ranges(result);
+    let counts: Vec<u64> = ranges
+        .iter()
+        .map(|&(low, high)| {
+            let before = if low == 0 {
+                0
+            } else {
+                result.runs_within(low - 1)
+            };
+            result.runs_within(high) - before
+        })
+        .collect();
+    let highest = *counts.iter().max().unwrap();
+    let label_width = ranges
+        .iter()
+        .map(|&(low, high)| range_label(low, high).len())
+        .max()
+        .unwrap();
+
+    let mut plot = String::new();
+    writeln!(plot, "modules used (share of runs)").unwrap();
+    for (&(low, high), &count) in ranges.iter().zip(&counts) {
+        writeln!(
+            plot,
+            "{:>label_width$} │{} {:5.1}%",
+            range_label(low, high),
+            bar(count as f64 / highest as f64, width),
+            count as f64 * 100.0 / samples as f64
+        )
+        .unwrap();
+    }
+
+    writeln!(plot, "runs finished within N modules (CDF)").unwrap();
+    let label_width = ranges.last().unwrap().1.to_string().len();
+    for &(_, high) in &ranges {
+        let fraction = result.runs_within(high) as f64 / samples as f64;
+        writeln!(
+            plot,
+            "{high:>label_width$} │{} {:5.1}%",
+            bar(fraction, width),
+            fraction * 100.0
+        )
+        .unwrap();
+    }
+    plot
+}
+
+/// The plots of all the rows of |report| that measured module usage. Empty for the other suites.
+pub fn suite_plots(report: &SuiteReport, width: usize) -> String {
+    let mut plots = String::new();
+    for (i, row) in report.rows.iter().enumerate() {
+        if let RowResult::Modules(result) = &row.result {
+            writeln!(plots, "#{} {}", i + 1, row.labels.join(", ")).unwrap();
+            plots += &modules_plot(result, width);
+        }
+    }
+    plots
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn bar_uses_partial_blocks() {
+        assert_eq!(bar(1.0, 4), "████");
+        assert_eq!(bar(0.5, 4), "██  ");
+        assert_eq!(bar(0.25 + 1.0 / 32.0, 4), "█▏  ");
+        assert_eq!(bar(0.0, 4), "    ");
+    }
+
+    #[test]
+    fn cdf_reaches_all_runs() {
+        let mut result = SimulationResult::new();
+        for modules in 1..=1000 {
+            result.record(modules % 50 + 1);
+        }
+        let plot = modules_plot(&result, 10);
+        let lines: Vec<&str> = plot.lines().collect();
+
+        assert_eq!(lines[0], "modules used (share of runs)");
+        // 50 values in ranges of 3 modules, so 17 bars for each of the plots.
+        assert_eq!(lines.len(), 2 * (1 + 17));
+        assert_eq!(lines[1], "  1-3 │██████████   6.0%");
+        assert_eq!(lines.last().unwrap(), &"50 │██████████ 100.0%");
+    }
+}
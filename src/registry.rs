@@ -0,0 +1,476 @@
+// The suites that the command line can list and run. Each suite declares its name, what it
+// measures, tags for selecting related suites and the parameters that it takes, so that the runner
+// does not need to know about any of them.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use itertools::Itertools;
+
+use crate::breakeven;
+use crate::cooccurrence;
+use crate::coverage::Coverage;
+use crate::report::SuiteReport;
+use crate::scenario;
+use crate::simulation::{self, SuiteConfig};
+use crate::Buff;
+
+/// The kind of value that a parameter takes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamKind {
+    /// A positive whole number.
+    Count,
+    /// 1 to 3 different buffs separated by commas, e.g. Attack,Elemental or atk,elem.
+    Buffs,
+    /// One of the names.
+    Choice(&'static [&'static str]),
+}
+
+impl ParamKind {
+    pub fn name(&self) -> String {
+        match self {
+            ParamKind::Count => "count".to_string(),
+            ParamKind::Buffs => "buffs".to_string(),
+            ParamKind::Choice(names) => names.join("|"),
+        }
+    }
+
+    // Checks that |value| is of the kind.
+    fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            ParamKind::Count => match value.parse::<u64>() {
+                Ok(count) if count > 0 => Ok(()),
+                _ => Err(format!("'{value}' is not a positive number")),
+            },
+            ParamKind::Buffs => scenario::check_want(&parse_buffs(value)?),
+            ParamKind::Choice(names) if names.contains(&value) => Ok(()),
+            ParamKind::Choice(names) => {
+                Err(format!("'{value}' is not one of {}", names.join(", ")))
+            }
+        }
+    }
+}
+
+fn parse_buffs(value: &str) -> Result<Vec<Buff>, String> {
+    value
+        .split(',')
+        .map(|name| name.trim().parse().map_err(|error| format!("{error}")))
+        .collect()
+}
+
+/// A parameter that a suite takes.
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub description: &'static str,
+    /// The value when it is not given, or None if the suite has its own behaviour without it.
+    pub default: Option<&'static str>,
+}
+
+/// The values of the parameters of a suite, checked against its parameters.
+#[derive(Clone, Debug, Default)]
+pub struct Params {
+    values: BTreeMap<&'static str, String>,
+}
+
+impl Params {
+    /// The count of the parameter |name|, or its default.
+    pub fn count(&self, name: &str) -> Option<u64> {
+        self.values.get(name).map(|value| value.parse().unwrap())
+    }
+
+    /// The buffs of the parameter |name|, or its default.
+    pub fn buffs(&self, name: &str) -> Option<Vec<Buff>> {
+        self.values
+            .get(name)
+            .map(|value| parse_buffs(value).unwrap())
+    }
+
+    /// The value of the parameter |name|, or its default.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// The coverage of the cases, if the parameter is given or has a default.
+    pub fn coverage(&self) -> Option<Coverage> {
+        self.get(COVERAGE).map(|value| value.parse().unwrap())
+    }
+}
+
+/// A parameter given by name, e.g. ("rolls", "5000").
+pub type ParamValue = (String, String);
+
+/// The name of the parameter that picks which buffs stand for each pattern of chances.
+pub const COVERAGE: &str = "buffs";
+
+/// The parameter of the suites whose cases are generated, see coverage.rs.
+pub fn coverage_param(default: Option<&'static str>) -> Param {
+    Param {
+        name: COVERAGE,
+        kind: ParamKind::Choice(&Coverage::NAMES),
+        description: "the first buffs of each chance for each pattern of chances, or all the buffs",
+        default,
+    }
+}
+
+pub type RunSuite = dyn Fn(&SuiteConfig, &Params) -> SuiteReport + Send + Sync;
+
+/// A suite that can be run by name.
+#[derive(Clone)]
+pub struct Suite {
+    /// The name that the command line uses.
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub params: Vec<Param>,
+    run: Arc<RunSuite>,
+}
+
+impl Suite {
+    pub fn new(
+        name: &str,
+        description: &str,
+        tags: &[&str],
+        params: Vec<Param>,
+        run: impl Fn(&SuiteConfig, &Params) -> SuiteReport + Send + Sync + 'static,
+    ) -> Self {
+        Suite {
+            name: name.to_string(),
+            description: description.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            params,
+            run: Arc::new(run),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    /// The parameters of the suite from the (name, value) pairs of |values|, with the defaults
+    /// of the others. The pairs of parameters that the suite does not take are ignored.
+    pub fn params(&self, values: &[ParamValue]) -> Result<Params, String> {
+        let mut params = Params::default();
+        for param in &self.params {
+            let value = values
+                .iter()
+                .rev()
+                .find(|(name, _)| name == param.name)
+                .map(|(_, value)| value.as_str())
+                .or(param.default);
+            if let Some(value) = value {
+                param
+                    .kind
+                    .check(value)
+                    .map_err(|error| format!("{} {}: {error}", self.name, param.name))?;
+                params.values.insert(param.name, value.to_string());
+            }
+        }
+        Ok(params)
+    }
+
+    /// Runs the suite with the parameters from |values|.
+    pub fn run(&self, config: &SuiteConfig, values: &[ParamValue]) -> Result<SuiteReport, String> {
+        let params = self.params(values)?;
+        Ok((self.run)(config, &params))
+    }
+}
+
+/// The suites, in the order that they run.
+#[derive(Clone, Default)]
+pub struct Registry {
+    suites: Vec<Suite>,
+}
+
+impl Registry {
+    /// The suites of the simulation, then the standard scenarios.
+    pub fn standard() -> Self {
+        let mut registry = Registry::default();
+        registry.add(Suite::new(
+            "slots-shown",
+            "how many slots get a buff on a roll",
+            &["probability", "check"],
+            vec![Param {
+                name: "rolls",
+                kind: ParamKind::Count,
+                description: "the rolls to count",
+                default: Some("100000"),
+            }],
+            |config, params| {
+                simulation::simulation_slots_shown_distribution(
+                    config,
+                    params.count("rolls").unwrap(),
+                )
+            },
+        ));
+        registry.add(Suite::new(
+            "desired-buffs",
+            "how likely a roll shows two or three buffs",
+            &["probability"],
+            vec![
+                Param {
+                    name: "want",
+                    kind: ParamKind::Buffs,
+                    description: "only these buffs, instead of two and three buffs of each \
+                        pattern of chances",
+                    default: None,
+                },
+                coverage_param(Some("representative")),
+            ],
+            |config, params| match params.buffs("want") {
+                Some(want) => simulation::suite_want_probability(config, &want),
+                None => simulation::suite_desired_buff_percent(config, params.coverage().unwrap()),
+            },
+        ));
+        registry.add(Suite::new(
+            "rare-buffs",
+            "how likely rare rolls are, by importance sampling",
+            &["probability", "importance-sampling"],
+            vec![],
+            |config, _| simulation::suite_rare_buffs(config),
+        ));
+        registry.add(Suite::new(
+            "buffs-together",
+            "how likely a roll shows each pair and triple of buffs, with a matrix of the pairs",
+            &["probability", "exact"],
+            vec![],
+            |config, _| cooccurrence::suite_cooccurrence(config),
+        ));
+        registry.add(Suite::new(
+            "two-buffs",
+            "custom modules to get two buffs without locking",
+            &["modules", "no-locking"],
+            vec![coverage_param(Some("representative"))],
+            |config, params| {
+                simulation::suite_two_desired_buffs_custom_mod_usage(
+                    config,
+                    params.coverage().unwrap(),
+                )
+            },
+        ));
+        registry.add(Suite::new(
+            "two-buffs-locking",
+            "custom modules to get two buffs, locking them as they appear",
+            &["modules", "locking"],
+            vec![coverage_param(Some("representative"))],
+            |config, params| {
+                simulation::suite_two_desired_buffs_custom_mod_usage_with_locking(
+                    config,
+                    params.coverage().unwrap(),
+                )
+            },
+        ));
+        for scenario in scenario::standard() {
+            registry.add(scenario.suite());
+        }
+        registry.add(Suite::new(
+            "break-even",
+            "when waiting for a buff in a slot beats locking the buffs as they appear",
+            &["locking", "exact"],
+            vec![
+                Param {
+                    name: "slot",
+                    kind: ParamKind::Choice(&["1", "2", "3"]),
+                    description: "only this slot, instead of every slot",
+                    default: None,
+                },
+                coverage_param(Some("representative")),
+            ],
+            |config, params| {
+                let slots: Vec<usize> = match params.get("slot") {
+                    Some(slot) => vec![slot.parse::<usize>().unwrap() - 1],
+                    None => (0..3).collect(),
+                };
+                breakeven::suite_break_even(config, params.coverage().unwrap(), &slots)
+            },
+        ));
+        registry
+    }
+
+    /// Adds |suite| after the others, replacing the suite with the same name in place.
+    pub fn add(&mut self, suite: Suite) {
+        match self.suites.iter_mut().find(|s| s.name == suite.name) {
+            Some(existing) => *existing = suite,
+            None => self.suites.push(suite),
+        }
+    }
+
+    pub fn suites(&self) -> &[Suite] {
+        &self.suites
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Suite> {
+        self.suites.iter().find(|suite| suite.name == name)
+    }
+
+    /// The suites named in |names|, in that order, or all the suites without names, keeping those
+    /// that have all of |tags|.
+    pub fn select(&self, names: &[String], tags: &[String]) -> Result<Vec<&Suite>, String> {
+        let suites: Vec<&Suite> = if names.is_empty() {
+            self.suites.iter().collect()
+        } else {
+            names
+                .iter()
+                .map(|name| {
+                    self.get(name).ok_or(format!(
+                        "unknown suite '{name}', see 'suites list' for the suites"
+                    ))
+                })
+                .try_collect()?
+        };
+        if let Some(tag) = tags
+            .iter()
+            .find(|tag| !self.suites.iter().any(|suite| suite.has_tag(tag)))
+        {
+            return Err(format!(
+                "unknown tag '{tag}', the tags are {}",
+                self.tags().join(", ")
+            ));
+        }
+        Ok(suites
+            .into_iter()
+            .filter(|suite| tags.iter().all(|tag| suite.has_tag(tag)))
+            .collect())
+    }
+
+    /// The tags of all the suites, sorted.
+    pub fn tags(&self) -> Vec<&str> {
+        self.suites
+            .iter()
+            .flat_map(|suite| suite.tags.iter().map(String::as_str))
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Checks that each of the (name, value) pairs of |values| is a parameter of one of |suites|
+    /// and has a valid value for them.
+    pub fn check_params(suites: &[&Suite], values: &[ParamValue]) -> Result<(), String> {
+        if let Some((name, _)) = values
+            .iter()
+            .find(|(name, _)| !suites.iter().any(|suite| suite.param(name).is_some()))
+        {
+            return Err(format!(
+                "none of the suites takes the parameter '{name}', see 'suites list' for the \
+                parameters"
+            ));
+        }
+        suites
+            .iter()
+            .try_for_each(|suite| suite.params(values).map(|_| ()))
+    }
+}
+
+/// Parses a NAME=VALUE parameter.
+pub fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("'{param}' is not NAME=VALUE")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::report::RowResult;
+    use crate::sampling::StopRule;
+
+    #[test]
+    fn suites_are_selected_by_name_and_tag() {
+        let registry = Registry::standard();
+        let names = |suites: Vec<&Suite>| -> Vec<String> {
+            suites.iter().map(|suite| suite.name.clone()).collect()
+        };
+        assert_eq!(
+            registry.select(&[], &[]).unwrap().len(),
+            registry.suites().len()
+        );
+        assert_eq!(
+            names(
+                registry
+                    .select(&["rare-buffs".to_string(), "slots-shown".to_string()], &[])
+                    .unwrap()
+            ),
+            vec!["rare-buffs", "slots-shown"]
+        );
+        assert_eq!(
+            names(registry.select(&[], &["slot-locked".to_string()]).unwrap()),
+            vec![
+                "first-slot-locked",
+                "second-slot-locked",
+                "third-slot-locked"
+            ]
+        );
+        assert_eq!(
+            names(
+                registry
+                    .select(&[], &["probability".to_string(), "check".to_string()])
+                    .unwrap()
+            ),
+            vec!["slots-shown"]
+        );
+        assert!(registry.select(&["nope".to_string()], &[]).is_err());
+        assert!(registry.select(&[], &["nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn params_are_checked_and_passed_to_the_suite() {
+        let registry = Registry::standard();
+        let suite = registry.get("slots-shown").unwrap();
+        let param = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+
+        assert!(suite
+            .run(&SuiteConfig::default(), &param("rolls", "0"))
+            .is_err());
+        let report = suite
+            .run(&SuiteConfig::default(), &param("rolls", "1000"))
+            .unwrap();
+        let attempts: u64 = report
+            .rows
+            .iter()
+            .map(|row| match &row.result {
+                RowResult::Probability(result) => result.attempts,
+                _ => panic!("not a probability"),
+            })
+            .sum();
+        assert_eq!(attempts, 3000);
+
+        let suites = registry.select(&[], &[]).unwrap();
+        assert!(Registry::check_params(&suites, &param("want", "atk,elem")).is_ok());
+        assert!(Registry::check_params(&suites, &param("want", "atk,nope")).is_err());
+        assert!(Registry::check_params(&suites, &param("want", "atk,atk")).is_err());
+        assert!(Registry::check_params(&suites, &param("want", "atk,elem,ammo,def")).is_err());
+        assert!(Registry::check_params(&suites, &param("size", "2")).is_err());
+        assert_eq!(
+            parse_param("want = atk"),
+            Ok(param("want", "atk").remove(0))
+        );
+        assert!(parse_param("want").is_err());
+    }
+
+    #[test]
+    fn added_suites_run_generically() {
+        let mut registry = Registry::standard();
+        registry.add(Suite::new(
+            "attack",
+            "how likely a roll shows Attack",
+            &["probability"],
+            vec![],
+            |config, _| simulation::suite_want_probability(config, &[Buff::Attack]),
+        ));
+        let config = SuiteConfig {
+            probability_stop: StopRule::fixed(100),
+            ..Default::default()
+        };
+        let suite = registry.suites().last().unwrap();
+        assert_eq!(suite.name, "attack");
+        assert_eq!(suite.run(&config, &[]).unwrap().rows.len(), 1);
+    }
+}
@@ -0,0 +1,424 @@
+// Structured results of the suites. The backends in output.rs turn these into tables, JSON, CSV,
+// etc.
+
+use serde_json::{json, Value};
+
+use crate::breakeven::BreakEven;
+use crate::simulation::{Column, Outcomes, ProportionResult, SimulationResult, WeightedResult};
+use crate::solver::Outlook;
+use crate::stats::{self, Interval};
+use crate::Buff;
+
+/// What a row of a suite measured.
+pub enum RowResult {
+    /// The number of custom modules used to get the wanted buffs.
+    Modules(SimulationResult),
+    /// How likely an outcome is on a roll.
+    Probability(ProportionResult),
+    /// How likely an outcome is on a roll, by importance sampling.
+    Weighted(WeightedResult),
+    /// How likely the wanted buffs are to be got and the custom modules expected to be used,
+    /// calculated by the solver or from simulations.
+    Outlook(Outlook),
+    /// When waiting for a buff in a slot is worth it, calculated exactly.
+    BreakEven(BreakEven),
+}
+
+impl RowResult {
+    pub fn confidence_interval(&self) -> Interval {
+        match self {
+            RowResult::Modules(result) => result.confidence_interval(),
+            RowResult::Probability(result) => result.confidence_interval(),
+            RowResult::Weighted(result) => result.confidence_interval(),
+            RowResult::Outlook(outlook) => Interval {
+                lower: outlook.modules,
+                upper: outlook.modules,
+            },
+            RowResult::BreakEven(point) => Interval {
+                lower: point.modules(),
+                upper: point.modules(),
+            },
+        }
+    }
+
+    /// Adds the samples of |other|, which must measure the same thing.
+    pub fn merge(&mut self, other: &RowResult) -> Result<(), String> {
+        match (self, other) {
+            (RowResult::Modules(result), RowResult::Modules(other)) => result.merge(other),
+            (RowResult::Probability(result), RowResult::Probability(other)) => {
+                result.merge(other);
+                Ok(())
+            }
+            (RowResult::Weighted(result), RowResult::Weighted(other)) => {
+                result.merge(other);
+                Ok(())
+            }
+            // There are no samples to add, but the same outlooks can be merged.
+            (RowResult::Outlook(outlook), RowResult::Outlook(other)) if outlook == other => Ok(()),
+            (RowResult::Outlook(_), RowResult::Outlook(_)) => {
+                Err("cannot merge different outlooks".to_string())
+            }
+            (RowResult::BreakEven(point), RowResult::BreakEven(other)) if point == other => Ok(()),
+            (RowResult::BreakEven(_), RowResult::BreakEven(_)) => {
+                Err("cannot merge different break-even points".to_string())
+            }
+            _ => Err("cannot merge different kinds of results".to_string()),
+        }
+    }
+
+    // Everything that is needed to merge the result later, i.e. the whole histogram for module
+    // usage.
+    fn saved_json(&self) -> Value {
+        match self {
+            RowResult::Modules(result) => json!({
+                "locked_buff": result.locked_buff.map(|buff| format!("{buff:?}")),
+                "buffs": result.buffs.iter().map(|buff| format!("{buff:?}")).collect::<Vec<_>>(),
+                "histogram": result.histogram_base64(),
+                "outcomes": outcomes_json(&result.outcomes),
+            }),
+            RowResult::Probability(result) => json!({
+                "hits": result.hits,
+                "attempts": result.attempts,
+            }),
+            RowResult::Weighted(result) => json!({
+                "hits": result.hits,
+                "attempts": result.attempts,
+                "weight_sum": result.weight_sum,
+                "weight_sum_of_squares": result.weight_sum_of_squares,
+            }),
+            RowResult::Outlook(outlook) => json!({
+                "success": outlook.success,
+                "expected_modules": outlook.modules,
+            }),
+            RowResult::BreakEven(point) => json!({
+                "plain": point.plain,
+                "waited": point.waited,
+                "after": point.after,
+                "chance": point.chance,
+                "reroll_cost": point.reroll_cost,
+            }),
+        }
+    }
+
+    fn from_saved_json(json: &Value) -> Result<Self, String> {
+        if let Some(histogram) = json["histogram"].as_str() {
+            let locked_buff = match &json["locked_buff"] {
+                Value::Null => None,
+                buff => Some(parse_buff(buff)?),
+            };
+            let buffs = json["buffs"]
+                .as_array()
+                .ok_or("missing buffs")?
+                .iter()
+                .map(parse_buff)
+                .collect::<Result<_, _>>()?;
+            let mut result =
+                SimulationResult::from_histogram_base64(locked_buff, buffs, histogram)?;
+            // Saved runs from before the outcomes were recorded have none.
+            if !json["outcomes"].is_null() {
+                result.outcomes = outcomes_from_json(&json["outcomes"])?;
+                let runs = result.outcomes.runs;
+                if runs != 0 && runs != result.samples() {
+                    return Err(format!(
+                        "the outcomes of {runs} runs do not cover the {} samples",
+                        result.samples()
+                    ));
+                }
+            }
+            return Ok(RowResult::Modules(result));
+        }
+        if let Some(waited) = json["waited"].as_f64() {
+            let field = |name: &str| json[name].as_f64().ok_or(format!("missing {name}"));
+            return Ok(RowResult::BreakEven(BreakEven {
+                plain: field("plain")?,
+                waited,
+                after: field("after")?,
+                chance: field("chance")?,
+                reroll_cost: json["reroll_cost"].as_u64().ok_or("missing reroll_cost")? as u32,
+            }));
+        }
+        if let Some(success) = json["success"].as_f64() {
+            return Ok(RowResult::Outlook(Outlook {
+                success,
+                modules: json["expected_modules"]
+                    .as_f64()
+                    .ok_or("missing expected_modules")?,
+            }));
+        }
+        let hits = json["hits"].as_u64().ok_or("missing hits")?;
+        let attempts = json["attempts"].as_u64().ok_or("missing attempts")?;
+        if let Some(weight_sum) = json["weight_sum"].as_f64() {
+            return Ok(RowResult::Weighted(WeightedResult {
+                hits,
+                attempts,
+                weight_sum,
+                weight_sum_of_squares: json["weight_sum_of_squares"]
+                    .as_f64()
+                    .ok_or("missing weight_sum_of_squares")?,
+            }));
+        }
+        Ok(RowResult::Probability(ProportionResult { hits, attempts }))
+    }
+}
+
+fn outcomes_json(outcomes: &Outcomes) -> Value {
+    json!({
+        "runs": outcomes.runs,
+        "modules": outcomes.modules,
+        "rerolls": outcomes.rerolls,
+        "locks": outcomes.locks,
+        "reroll_modules": outcomes.reroll_modules,
+        "lock_modules": outcomes.lock_modules,
+        "slots": outcomes.slots,
+        "third_slot": outcomes.third_slot,
+    })
+}
+
+fn outcomes_from_json(json: &Value) -> Result<Outcomes, String> {
+    let count = |name: &str| {
+        json[name]
+            .as_u64()
+            .ok_or(format!("missing outcomes {name}"))
+    };
+    let slots = json["slots"]
+        .as_array()
+        .ok_or("missing outcomes slots")?
+        .iter()
+        .map(|counts| {
+            serde_json::from_value::<[u64; 3]>(counts.clone())
+                .map_err(|error| format!("invalid outcomes slots: {error}"))
+        })
+        .collect::<Result<_, _>>()?;
+    let (modules, lock_modules) = (count("modules")?, count("lock_modules")?);
+    Ok(Outcomes {
+        runs: count("runs")?,
+        modules,
+        rerolls: count("rerolls")?,
+        locks: count("locks")?,
+        // The first saved outcomes left the modules of the rerolls to the rest.
+        reroll_modules: json["reroll_modules"]
+            .as_u64()
+            .unwrap_or(modules - lock_modules),
+        lock_modules,
+        slots,
+        third_slot: count("third_slot")?,
+    })
+}
+
+fn parse_buff(json: &Value) -> Result<Buff, String> {
+    let name = json.as_str().ok_or("a buff must be a string")?;
+    name.parse().map_err(|error| format!("{error}"))
+}
+
+pub struct ReportRow {
+    /// The values that identify the row, one per |SuiteReport::labels|.
+    pub labels: Vec<String>,
+    pub result: RowResult,
+}
+
+/// A matrix of probabilities under the table of a suite, e.g. of each pair of buffs, so that a
+/// cell can be looked up by its row and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    pub title: String,
+    /// The labels of the rows, which are also those of the columns.
+    pub labels: Vec<String>,
+    /// The probabilities (0 to 1), by row and then by column.
+    pub cells: Vec<Vec<f64>>,
+}
+
+impl Heatmap {
+    /// The header row, then each row with its label, the probabilities as percentages.
+    pub fn table_data(&self) -> Vec<Vec<String>> {
+        let mut data = vec![std::iter::once(String::new())
+            .chain(self.labels.iter().cloned())
+            .collect()];
+        for (label, cells) in self.labels.iter().zip(&self.cells) {
+            let mut row = vec![label.clone()];
+            row.extend(cells.iter().map(|cell| format!("{:.2}%", cell * 100.0)));
+            data.push(row);
+        }
+        data
+    }
+
+    /// The largest probability, which the darkest cells of the HTML format show.
+    pub fn max(&self) -> f64 {
+        self.cells.iter().flatten().copied().fold(0.0, f64::max)
+    }
+
+    fn saved_json(&self) -> Value {
+        json!({
+            "title": self.title,
+            "labels": self.labels,
+            "cells": self.cells,
+        })
+    }
+
+    fn from_saved_json(json: &Value) -> Result<Self, String> {
+        Ok(Heatmap {
+            title: json["title"]
+                .as_str()
+                .ok_or("missing heatmap title")?
+                .to_string(),
+            labels: serde_json::from_value(json["labels"].clone())
+                .map_err(|error| format!("invalid heatmap labels: {error}"))?,
+            cells: serde_json::from_value(json["cells"].clone())
+                .map_err(|error| format!("invalid heatmap cells: {error}"))?,
+        })
+    }
+}
+
+pub struct SuiteReport {
+    pub name: String,
+    pub description: String,
+    /// Headers of the columns that identify a row, e.g. "buff1".
+    pub labels: Vec<String>,
+    pub rows: Vec<ReportRow>,
+    /// The statistics of the module usage rows, instead of those of the output, e.g. the metrics
+    /// of a scenario.
+    pub columns: Option<Vec<Column>>,
+    /// Budgets of custom modules. The module usage rows also show the share of the runs that
+    /// stayed within each.
+    pub budgets: Vec<u32>,
+    /// A matrix of the probabilities under the table, if any. It does not depend on the samples,
+    /// so merging keeps it as it is.
+    pub heatmap: Option<Heatmap>,
+}
+
+impl SuiteReport {
+    pub fn new(name: &str, description: &str, labels: &[&str]) -> Self {
+        SuiteReport {
+            name: name.to_string(),
+            description: description.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            rows: vec![],
+            columns: None,
+            budgets: vec![],
+            heatmap: None,
+        }
+    }
+
+    /// The description on a single line. The descriptions are wrapped in the source code.
+    pub fn plain_description(&self) -> String {
+        self.description
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Adds the samples of the rows of |other|, a report of the same suite.
+    pub fn merge(&mut self, other: &SuiteReport) -> Result<(), String> {
+        if self.name != other.name || self.labels != other.labels {
+            return Err(format!(
+                "cannot merge suite '{}' into '{}'",
+                other.name, self.name
+            ));
+        }
+        if self.rows.len() != other.rows.len() {
+            return Err(format!(
+                "suite '{}' has a different number of rows",
+                self.name
+            ));
+        }
+        for (i, (row, other_row)) in self.rows.iter_mut().zip(&other.rows).enumerate() {
+            if row.labels != other_row.labels {
+                return Err(format!(
+                    "row {} of suite '{}' is different",
+                    i + 1,
+                    self.name
+                ));
+            }
+            row.result
+                .merge(&other_row.result)
+                .map_err(|error| format!("row {} of suite '{}': {error}", i + 1, self.name))?;
+        }
+        Ok(())
+    }
+
+    /// The report as JSON with all the samples, unlike output::report_json(), so that it can be
+    /// merged with other runs of the suite.
+    pub fn saved_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "labels": self.labels,
+            "columns": self
+                .columns
+                .as_ref()
+                .map(|columns| columns.iter().map(Column::key).collect::<Vec<_>>()),
+            "budgets": self.budgets,
+            "heatmap": self.heatmap.as_ref().map(Heatmap::saved_json),
+            "rows": self.rows.iter().map(|row| json!({
+                "labels": row.labels,
+                "result": row.result.saved_json(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn from_saved_json(json: &Value) -> Result<Self, String> {
+        let strings = |json: &Value| -> Result<Vec<String>, String> {
+            json.as_array()
+                .ok_or("missing labels")?
+                .iter()
+                .map(|label| {
+                    label
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or("labels must be strings")
+                })
+                .collect::<Result<_, _>>()
+                .map_err(str::to_string)
+        };
+        let rows = json["rows"]
+            .as_array()
+            .ok_or("missing rows")?
+            .iter()
+            .map(|row| {
+                Ok(ReportRow {
+                    labels: strings(&row["labels"])?,
+                    result: RowResult::from_saved_json(&row["result"])?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(SuiteReport {
+            name: json["name"].as_str().ok_or("missing name")?.to_string(),
+            description: json["description"].as_str().unwrap_or_default().to_string(),
+            labels: strings(&json["labels"])?,
+            rows,
+            columns: match &json["columns"] {
+                Value::Null => None,
+                columns => Some(
+                    strings(columns)?
+                        .iter()
+                        .map(|key| key.parse())
+                        .collect::<Result<_, _>>()?,
+                ),
+            },
+            budgets: json["budgets"]
+                .as_array()
+                .map(|budgets| {
+                    budgets
+                        .iter()
+                        .map(|budget| budget.as_u64().map(|budget| budget as u32))
+                        .collect::<Option<_>>()
+                        .ok_or("budgets must be numbers")
+                })
+                .transpose()?
+                .unwrap_or_default(),
+            heatmap: match &json["heatmap"] {
+                Value::Null => None,
+                heatmap => Some(Heatmap::from_saved_json(heatmap)?),
+            },
+        })
+    }
+
+    /// For each row, the (1 based) row numbers whose estimates cannot be distinguished from it.
+    pub fn indistinguishable_rows(&self) -> Vec<Vec<usize>> {
+        let intervals: Vec<Interval> = self
+            .rows
+            .iter()
+            .map(|row| row.result.confidence_interval())
+            .collect();
+        stats::overlapping_rows(&intervals)
+    }
+}
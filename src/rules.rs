@@ -0,0 +1,290 @@
+// The rules of the overload gear: the chances of the buffs and of the extra slots, and the custom
+// modules that rerolls and locks cost. The standard rules are the game's. Others can be loaded
+// from a TOML file, e.g. to see what a change of the chances would do.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use itertools::Itertools;
+use rand::Rng;
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::buff_set::{AliasTables, BUFF_COUNT};
+use crate::{Buff, BuffSet};
+
+// The standard chances of the second and third slots getting a buff on a roll.
+pub(crate) const SECOND_SLOT_PERCENT: f64 = 50.0;
+pub(crate) const THIRD_SLOT_PERCENT: f64 = 30.0;
+
+// The largest chance of a buff. The alias tables count in hundredths of a percent, times the number
+// of buffs, in u32: 9 buffs of this chance make 9 * 9 * 10000000, well within.
+const MAX_BUFF_PERCENT: f64 = 100000.0;
+
+#[derive(Clone)]
+pub struct Rules {
+    /// "standard", or where the rules were loaded from.
+    pub name: String,
+    // The chances of the buffs in declaration order.
+    percents: [f64; BUFF_COUNT],
+    /// The chances of the second and third slots getting a buff on a roll, in percent.
+    pub second_slot_percent: f64,
+    pub third_slot_percent: f64,
+    /// The custom modules that a reroll costs with 0, 1, ... locked buffs.
+    pub reroll_costs: Vec<u32>,
+    /// The custom modules that locking a buff costs with 0, 1, ... buffs locked already. There
+    /// is a cost for each buff that can be locked.
+    pub lock_costs: Vec<u32>,
+    tables: Arc<AliasTables>,
+}
+
+// A rules file. Everything is optional and defaults to the standard rules.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    buff_percents: Option<BTreeMap<String, f64>>,
+    second_slot_percent: Option<f64>,
+    third_slot_percent: Option<f64>,
+    reroll_costs: Option<Vec<u32>>,
+    lock_costs: Option<Vec<u32>>,
+}
+
+impl Rules {
+    /// The rules of the game.
+    pub fn standard() -> Arc<Rules> {
+        static STANDARD: OnceLock<Arc<Rules>> = OnceLock::new();
+        STANDARD
+            .get_or_init(|| {
+                let percents = Buff::iter()
+                    .map(|buff| buff.percent())
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap();
+                Arc::new(Rules {
+                    name: "standard".to_string(),
+                    percents,
+                    second_slot_percent: SECOND_SLOT_PERCENT,
+                    third_slot_percent: THIRD_SLOT_PERCENT,
+                    reroll_costs: vec![1, 2, 3],
+                    lock_costs: vec![2, 3],
+                    tables: Arc::new(AliasTables::new(&percents)),
+                })
+            })
+            .clone()
+    }
+
+    /// Reads the rules from a TOML file, e.g.
+    ///
+    /// ```toml
+    /// second_slot_percent = 50
+    /// third_slot_percent = 30
+    /// reroll_costs = [1, 2, 3]
+    /// lock_costs = [2, 3]
+    ///
+    /// [buff_percents]
+    /// Attack = 10
+    /// ```
+    ///
+    /// Anything that is left out is standard. The buff chances are relative, so they do not need
+    /// to add up to 100.
+    pub fn load(path: &Path) -> Result<Rules, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        Rules::from_toml(&path.display().to_string(), &text)
+            .map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// Parses the rules from |text|, a rules file, and names them |name|.
+    pub fn from_toml(name: &str, text: &str) -> Result<Rules, String> {
+        let file: RulesFile = toml::from_str(text).map_err(|error| error.message().to_string())?;
+        let mut rules = Rules::clone(&Rules::standard());
+        rules.name = name.to_string();
+        for (name, percent) in file.buff_percents.unwrap_or_default() {
+            let buff: Buff = name.parse().map_err(|error| format!("{error}"))?;
+            rules.percents[buff as usize] = percent;
+        }
+        rules.second_slot_percent = file.second_slot_percent.unwrap_or(SECOND_SLOT_PERCENT);
+        rules.third_slot_percent = file.third_slot_percent.unwrap_or(THIRD_SLOT_PERCENT);
+        if let Some(lock_costs) = file.lock_costs {
+            rules.lock_costs = lock_costs;
+        }
+        if let Some(reroll_costs) = file.reroll_costs {
+            rules.reroll_costs = reroll_costs;
+        }
+        rules.validate()?;
+        rules.tables = Arc::new(AliasTables::new(&rules.percents));
+        Ok(rules)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let invalid = |percent: f64| !(0.01..=MAX_BUFF_PERCENT).contains(&percent);
+        if let Some(buff) = Buff::iter().find(|buff| invalid(self.percent(*buff))) {
+            return Err(format!(
+                "the chance of {buff:?} must be 0.01 to {MAX_BUFF_PERCENT}%, not {}",
+                self.percent(buff)
+            ));
+        }
+        for percent in [self.second_slot_percent, self.third_slot_percent] {
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!("a slot chance must be 0 to 100%, not {percent}"));
+            }
+        }
+        // Locking all three slots would leave nothing to reroll.
+        if self.lock_costs.len() > 2 {
+            return Err("at most 2 buffs can be locked".to_string());
+        }
+        if self.reroll_costs.len() != self.lock_costs.len() + 1 {
+            return Err(format!(
+                "there must be a reroll cost for 0 to {} locked buffs",
+                self.lock_costs.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The chance of the slot at |position| (0 based) getting a buff on a roll, in percent. The
+    /// first slot always gets one.
+    pub fn slot_percent(&self, position: usize) -> f64 {
+        [100.0, self.second_slot_percent, self.third_slot_percent][position]
+    }
+
+    /// Checks that all of |want| can be shown at once with the buff locked in each slot of
+    /// |locks|: the wanted buffs that are not locked need as many slots that are not locked and
+    /// can get a buff. Otherwise rerolling for them would never end.
+    pub fn check_shown(&self, locks: [Option<Buff>; 3], want: BuffSet) -> Result<(), String> {
+        let locked: BuffSet = locks.iter().flatten().copied().collect();
+        let missing = want.intersection(locked.complement()).len();
+        let open = (0..3)
+            .filter(|pos| locks[*pos].is_none() && self.slot_percent(*pos) > 0.0)
+            .count();
+        if missing > open {
+            return Err(format!(
+                "at most {open} of the {missing} buffs can be shown at once with the slot \
+                chances of the rules"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The chance of |buff| being drawn, in percent, relative to the others.
+    pub fn percent(&self, buff: Buff) -> f64 {
+        self.percents[buff as usize]
+    }
+
+    /// Chooses a buff that is not in |excluded|, weighted by the chances.
+    pub fn choose<R: Rng>(&self, excluded: BuffSet, rng: &mut R) -> Buff {
+        self.tables.choose(excluded, rng)
+    }
+
+    /// The custom modules that a reroll costs with |locked| buffs, or None if the rules do not
+    /// let that many buffs be locked.
+    pub fn reroll_cost(&self, locked: u32) -> Option<u32> {
+        self.reroll_costs.get(locked as usize).copied()
+    }
+
+    /// The custom modules that locking another buff costs with |locked| buffs, or None if no
+    /// more buffs can be locked.
+    pub fn lock_cost(&self, locked: u32) -> Option<u32> {
+        self.lock_costs.get(locked as usize).copied()
+    }
+
+    /// The rules as (name, value) pairs, for the headers of the reports.
+    pub fn parameters(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("rules", self.name.clone()),
+            (
+                "chance of a buff on the second slot",
+                format!("{}%", self.second_slot_percent),
+            ),
+            (
+                "chance of a buff on the third slot",
+                format!("{}%", self.third_slot_percent),
+            ),
+            (
+                "reroll cost (by locked buffs)",
+                self.reroll_costs.iter().join(", "),
+            ),
+            (
+                "lock cost (by locked buffs)",
+                self.lock_costs.iter().join(", "),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn rules_file_overrides_standard_rules() {
+        let rules = Rules::from_toml(
+            "test",
+            "third_slot_percent = 40\nlock_costs = [3]\nreroll_costs = [1, 3]\n\n\
+            [buff_percents]\nAttack = 20\n",
+        )
+        .unwrap();
+
+        assert_eq!(rules.percent(Buff::Attack), 20.0);
+        assert_eq!(rules.percent(Buff::Elemental), 10.0);
+        assert_eq!(rules.second_slot_percent, 50.0);
+        assert_eq!(rules.third_slot_percent, 40.0);
+        assert_eq!(rules.lock_cost(0), Some(3));
+        assert_eq!(rules.lock_cost(1), None);
+        assert_eq!(rules.reroll_cost(1), Some(3));
+        assert_eq!(rules.reroll_cost(2), None);
+    }
+
+    #[test]
+    fn buffs_need_slots_that_get_buffs() {
+        let rules = Rules::from_toml("test", "third_slot_percent = 0\n").unwrap();
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental, Buff::MaxAmmo]);
+        assert!(Rules::standard().check_shown([None; 3], want).is_ok());
+        assert!(rules.check_shown([None; 3], want).is_err());
+        assert!(rules
+            .check_shown([Some(Buff::Attack), None, None], want)
+            .is_err());
+        let two = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        assert!(rules.check_shown([None; 3], two).is_ok());
+        assert!(rules
+            .check_shown([None, Some(Buff::Attack), None], two)
+            .is_ok());
+        assert!(rules
+            .check_shown([None, Some(Buff::Defense), None], two)
+            .is_err());
+    }
+
+    #[test]
+    fn largest_chances_fit_the_alias_tables() {
+        let text = Buff::iter()
+            .map(|buff| format!("{buff:?} = {MAX_BUFF_PERCENT}\n"))
+            .join("");
+        let rules = Rules::from_toml("test", &format!("[buff_percents]\n{text}")).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        rules.choose(BuffSet::EMPTY, &mut rng);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for text in [
+            "[buff_percents]\nAtack = 20\n",
+            "[buff_percents]\nAttack = 0\n",
+            "[buff_percents]\nAttack = inf\n",
+            "[buff_percents]\nAttack = nan\n",
+            "[buff_percents]\nAttack = 1e9\n",
+            "second_slot_percent = 120\n",
+            "lock_costs = [2, 3, 4]\nreroll_costs = [1, 2, 3, 4]\n",
+            "lock_costs = [2]\n",
+            "unknown = 1\n",
+        ] {
+            assert!(Rules::from_toml("test", text).is_err(), "{text}");
+        }
+    }
+}
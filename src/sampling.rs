@@ -0,0 +1,350 @@
+// Stopping rules for the Monte Carlo loops, and running the loops in parallel.
+
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rayon::prelude::*;
+
+use crate::stats::Interval;
+
+// The number of samples in a chunk. The stopping rule is checked between chunks, since computing
+// the confidence interval on every sample is wasteful.
+pub const CHUNK_SAMPLES: u64 = 1000;
+
+/// Derives an independent seed from the |master| seed and a |key| that identifies what is being
+/// simulated, e.g. a row of a suite. Rows therefore do not depend on the order they run in.
+pub fn derive_seed(master: u64, key: &str) -> u64 {
+    // FNV-1a, then a SplitMix64 finalizer to spread the bits.
+    let mut hash = 0xcbf29ce484222325 ^ master;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+/// An estimate that gets more precise as more samples are recorded.
+pub trait Estimate {
+    /// The number of samples recorded so far.
+    fn samples(&self) -> u64;
+    fn estimate(&self) -> f64;
+    /// 95% confidence interval of the estimate.
+    fn confidence_interval(&self) -> Interval;
+}
+
+/// Target precision of an estimate, as the half-width of its 95% confidence interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    /// In the unit of the estimate, e.g. modules.
+    Absolute(f64),
+    /// As a fraction of the estimate, e.g. 0.05 for ±5%. This suits probabilities of rare
+    /// events where an absolute target would either be meaningless or unreachable.
+    Relative(f64),
+}
+
+impl Precision {
+    fn is_reached<E: Estimate>(&self, estimate: &E) -> bool {
+        let half_width = estimate.confidence_interval().half_width();
+        match self {
+            Precision::Absolute(target) => half_width < *target,
+            Precision::Relative(target) => {
+                let value = estimate.estimate().abs();
+                // Nothing has been observed yet, so the relative error is undefined.
+                value > 0.0 && half_width / value < *target
+            }
+        }
+    }
+}
+
+/// Decides how many samples a row of a suite uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopRule {
+    /// Keep sampling until this precision is reached. None means a fixed number of samples,
+    /// i.e. |max_samples|.
+    pub precision: Option<Precision>,
+    /// Never stop before this many samples, so that the confidence interval itself is reliable.
+    pub min_samples: u64,
+    /// Iteration budget.
+    pub max_samples: u64,
+    /// Time budget per row. The samples it stops at depend on the speed of the machine, so the
+    /// results are only reproducible from the seed without it.
+    pub max_duration: Option<Duration>,
+}
+
+impl StopRule {
+    /// Exactly |samples| samples, however precise they are.
+    pub fn fixed(samples: u64) -> Self {
+        StopRule {
+            precision: None,
+            min_samples: samples,
+            max_samples: samples,
+            max_duration: None,
+        }
+    }
+
+    /// Human readable summary, used in the suite descriptions.
+    pub fn describe(&self, unit: &str) -> String {
+        let mut conditions = vec![];
+        match self.precision {
+            Some(Precision::Absolute(target)) => conditions.push(format!(
+                "the 95% confidence interval half-width is below {target} {unit}"
+            )),
+            Some(Precision::Relative(target)) => conditions.push(format!(
+                "the 95% confidence interval half-width is below {}% of the estimate",
+                target * 100.0
+            )),
+            None => {}
+        }
+        conditions.push(format!("{} samples were taken", self.max_samples));
+        if let Some(duration) = self.max_duration {
+            conditions.push(format!(
+                "{} seconds passed, which depends on the speed of the machine",
+                duration.as_secs_f64()
+            ));
+        }
+        format!(
+            "Sampling stopped when {} (at least {} samples).",
+            conditions.join(", or "),
+            self.min_samples
+        )
+    }
+
+    // Whether |estimate| can stop. Only called on chunk boundaries.
+    fn is_satisfied<E: Estimate>(&self, estimate: &E, start: Instant) -> bool {
+        let samples = estimate.samples();
+        if samples >= self.max_samples {
+            return true;
+        }
+        if samples < self.min_samples {
+            return false;
+        }
+        if let Some(precision) = &self.precision {
+            if precision.is_reached(estimate) {
+                return true;
+            }
+        }
+        matches!(self.max_duration, Some(duration) if start.elapsed() >= duration)
+    }
+
+    /// Records samples into |estimate| until the rule is satisfied. The samples are taken in
+    /// chunks of |CHUNK_SAMPLES|: |sample| is called with the index of a chunk and its number of
+    /// samples, and returns the part of the estimate that |merge| adds to |estimate|.
+    ///
+    /// A batch of chunks runs in parallel, one per thread, but the chunks are merged and the rule
+    /// is checked in chunk order. The chunks after the rule is satisfied are discarded. As long as
+    /// |sample| only depends on the chunk index, e.g. by seeding a generator with
+    /// |chunk_rng|, the result is the same no matter how many threads run.
+    pub fn run<E: Estimate, P: Send>(
+        &self,
+        estimate: &mut E,
+        sample: impl Fn(u64, u64) -> P + Sync,
+        mut merge: impl FnMut(&mut E, P),
+    ) {
+        let start = Instant::now();
+        let mut chunk = 0;
+        while !self.is_satisfied(estimate, start) {
+            let batch = rayon::current_num_threads() as u64;
+            let parts: Vec<P> = (chunk..chunk + batch)
+                .into_par_iter()
+                .map(|index| {
+                    let first = index * CHUNK_SAMPLES;
+                    let samples = CHUNK_SAMPLES.min(self.max_samples.saturating_sub(first));
+                    sample(index, samples)
+                })
+                .collect();
+            for part in parts {
+                if self.is_satisfied(estimate, start) {
+                    break;
+                }
+                merge(estimate, part);
+                chunk += 1;
+            }
+        }
+    }
+}
+
+/// The generator of a chunk of samples (see |StopRule::run|). |seed| identifies what is being
+/// simulated, see |derive_seed|.
+pub fn chunk_rng(seed: u64, chunk: u64) -> StdRng {
+    StdRng::seed_from_u64(derive_seed(seed, &chunk.to_string()))
+}
+
+/// Ways of reducing the variance of the estimates, on top of plain Monte Carlo sampling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VarianceReduction {
+    /// The strategies that want the same buffs get the same random numbers for each run, so that
+    /// the differences between them are measured with less noise. The estimates of each strategy
+    /// alone do not change.
+    pub common_random_numbers: bool,
+    /// Every other run uses the complements of the random numbers of the run before it.
+    pub antithetic: bool,
+}
+
+impl VarianceReduction {
+    /// Human readable summary, for the parameters of a run.
+    pub fn describe(&self) -> String {
+        match (self.common_random_numbers, self.antithetic) {
+            (false, false) => "none".to_string(),
+            (true, false) => "common random numbers".to_string(),
+            (false, true) => "antithetic variates".to_string(),
+            (true, true) => "common random numbers and antithetic variates".to_string(),
+        }
+    }
+}
+
+impl FromStr for VarianceReduction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let (common_random_numbers, antithetic) = match name {
+            "none" => (false, false),
+            "crn" => (true, false),
+            "antithetic" => (false, true),
+            "both" => (true, true),
+            _ => {
+                return Err(format!(
+                    "unknown variance reduction '{name}', expected none, crn, antithetic or both"
+                ))
+            }
+        };
+        Ok(VarianceReduction {
+            common_random_numbers,
+            antithetic,
+        })
+    }
+}
+
+/// The generator of a simulation run. An antithetic generator returns the complements of the
+/// numbers of the generator it wraps, so that e.g. a uniform draw u becomes 1 - u.
+pub struct SampleRng {
+    rng: StdRng,
+    mask: u64,
+}
+
+impl SampleRng {
+    pub fn new(rng: StdRng) -> Self {
+        SampleRng { rng, mask: 0 }
+    }
+
+    pub fn antithetic(rng: StdRng) -> Self {
+        SampleRng { rng, mask: !0 }
+    }
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32() ^ self.mask as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64() ^ self.mask
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest);
+        for byte in dest {
+            *byte ^= self.mask as u8;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::stats::{self, Z_95};
+
+    struct Coin {
+        hits: u64,
+        trials: u64,
+    }
+
+    impl Estimate for Coin {
+        fn samples(&self) -> u64 {
+            self.trials
+        }
+
+        fn estimate(&self) -> f64 {
+            self.hits as f64 / self.trials as f64
+        }
+
+        fn confidence_interval(&self) -> Interval {
+            stats::wilson_interval(self.hits, self.trials, Z_95)
+        }
+    }
+
+    fn add_trials(coin: &mut Coin, samples: u64) {
+        coin.trials += samples;
+    }
+
+    #[test]
+    fn derived_seeds_differ_by_key() {
+        assert_eq!(derive_seed(1, "row"), derive_seed(1, "row"));
+        assert_ne!(derive_seed(1, "row"), derive_seed(2, "row"));
+        assert_ne!(derive_seed(1, "row 1"), derive_seed(1, "row 2"));
+    }
+
+    #[test]
+    fn fixed_rule_takes_exact_samples() {
+        let rule = StopRule::fixed(12345);
+        let mut coin = Coin { hits: 0, trials: 0 };
+        rule.run(&mut coin, |_, samples| samples, add_trials);
+        assert_eq!(coin.trials, 12345);
+    }
+
+    // A fair coin with a ±1% absolute target needs about 9604 samples.
+    #[test]
+    fn precision_rule_stops_early() {
+        let rule = StopRule {
+            precision: Some(Precision::Absolute(0.01)),
+            min_samples: 1000,
+            max_samples: 1000000,
+            max_duration: None,
+        };
+        let mut coin = Coin { hits: 0, trials: 0 };
+        // Every other sample is a hit.
+        rule.run(
+            &mut coin,
+            |_, samples| samples,
+            |coin, samples| {
+                coin.hits += samples / 2;
+                coin.trials += samples;
+            },
+        );
+        assert_eq!(coin.trials, 10000);
+    }
+
+    // An event that is never observed cannot reach a relative precision.
+    #[test]
+    fn relative_rule_falls_back_to_budget() {
+        let rule = StopRule {
+            precision: Some(Precision::Relative(0.05)),
+            min_samples: 1000,
+            max_samples: 5000,
+            max_duration: None,
+        };
+        let mut coin = Coin { hits: 0, trials: 0 };
+        rule.run(&mut coin, |_, samples| samples, add_trials);
+        assert_eq!(coin.trials, 5000);
+    }
+
+    #[test]
+    fn antithetic_draws_are_complements() {
+        let mut rng = SampleRng::new(StdRng::seed_from_u64(1));
+        let mut antithetic = SampleRng::antithetic(StdRng::seed_from_u64(1));
+        for _ in 0..1000 {
+            let (u, v): (f64, f64) = (rand::Rng::gen(&mut rng), rand::Rng::gen(&mut antithetic));
+            assert!((u + v - 1.0).abs() < 1e-15);
+        }
+    }
+}
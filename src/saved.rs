@@ -0,0 +1,212 @@
+// Saved runs: the suite reports with all their samples, so that long runs can be split across
+// processes or machines and merged afterwards.
+
+use std::{fs, io, path::Path};
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+use crate::report::SuiteReport;
+use crate::simulation::SuiteConfig;
+
+// Identifies the files, in case the layout changes.
+const FORMAT: &str = "nikke_overload_simulation results v1";
+
+/// The reports of one or more (merged) runs.
+pub struct SavedRun {
+    /// The master seed of every run that was merged into this.
+    pub seeds: Vec<u64>,
+    pub reports: Vec<SuiteReport>,
+}
+
+impl SavedRun {
+    /// |config|'s parameters, with the seeds of the runs.
+    pub fn parameters(&self, config: &SuiteConfig) -> Vec<(&'static str, String)> {
+        config
+            .parameters()
+            .into_iter()
+            .map(|(name, value)| match name {
+                "seed" => ("seeds", self.seeds.iter().join(", ")),
+                _ => (name, value),
+            })
+            .collect()
+    }
+}
+
+/// Writes the reports, as returned by SuiteReport::saved_json(), of the runs with |seeds| to
+/// |path|.
+pub fn write(path: &Path, seeds: &[u64], reports: Vec<Value>) -> io::Result<()> {
+    let json = json!({
+        "format": FORMAT,
+        "engine": format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        "seeds": seeds,
+        "suites": reports,
+    });
+    fs::write(path, serde_json::to_string(&json).unwrap())
+}
+
+pub fn read(path: &Path) -> io::Result<SavedRun> {
+    let invalid = |error: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {error}", path.display()),
+        )
+    };
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| invalid(error.to_string()))?;
+    if json["format"] != FORMAT {
+        return Err(invalid(format!("not a '{FORMAT}' file")));
+    }
+
+    let seeds = json["seeds"]
+        .as_array()
+        .and_then(|seeds| seeds.iter().map(Value::as_u64).collect())
+        .ok_or_else(|| invalid("missing seeds".to_string()))?;
+    let reports = json["suites"]
+        .as_array()
+        .ok_or_else(|| invalid("missing suites".to_string()))?
+        .iter()
+        .map(SuiteReport::from_saved_json)
+        .collect::<Result<_, _>>()
+        .map_err(invalid)?;
+    Ok(SavedRun { seeds, reports })
+}
+
+/// Merges the runs into the first one. The runs must have the same suites, and different seeds
+/// since runs with the same seed are the same.
+pub fn merge(runs: Vec<SavedRun>) -> Result<SavedRun, String> {
+    let mut runs = runs.into_iter();
+    let mut merged = runs.next().ok_or("nothing to merge")?;
+    for run in runs {
+        if let Some(seed) = run.seeds.iter().find(|seed| merged.seeds.contains(seed)) {
+            return Err(format!(
+                "seed {seed} is in more than one run. Split runs need different seeds"
+            ));
+        }
+        if run.reports.len() != merged.reports.len() {
+            return Err("the runs have different suites".to_string());
+        }
+        for (report, other) in merged.reports.iter_mut().zip(&run.reports) {
+            report.merge(other)?;
+        }
+        merged.seeds.extend(run.seeds);
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::report::{ReportRow, RowResult};
+    use crate::simulation::{Column, Outcomes, ProportionResult, RunOutcome, SimulationResult};
+    use crate::Buff;
+
+    fn run(seed: u64, modules: std::ops::Range<u32>) -> SavedRun {
+        let mut result = SimulationResult::new();
+        result.locked_buff = Some(Buff::Attack);
+        result.buffs = vec![Buff::Elemental];
+        // A reroll for each module, and a lock.
+        for rerolls in modules {
+            result.record_run(&RunOutcome {
+                modules: rerolls + 2,
+                rerolls,
+                locks: 1,
+                reroll_modules: rerolls,
+                lock_modules: 2,
+                slots: [Some(Buff::Elemental), None, Some(Buff::Attack)],
+            });
+        }
+        let mut modules_report = SuiteReport::new("modules", "", &["buffs"]);
+        modules_report.columns = Some(vec![Column::Mean, Column::Ci95]);
+        modules_report.budgets = vec![50];
+        modules_report.rows.push(ReportRow {
+            labels: vec!["a".to_string()],
+            result: RowResult::Modules(result),
+        });
+        let mut probability_report = SuiteReport::new("probability", "", &["buffs"]);
+        probability_report.rows.push(ReportRow {
+            labels: vec!["a".to_string()],
+            result: RowResult::Probability(ProportionResult {
+                hits: 1,
+                attempts: 10,
+            }),
+        });
+        SavedRun {
+            seeds: vec![seed],
+            reports: vec![modules_report, probability_report],
+        }
+    }
+
+    // Round trips the runs through files.
+    fn saved(runs: Vec<SavedRun>) -> Vec<SavedRun> {
+        let dir = std::env::temp_dir();
+        runs.into_iter()
+            .map(|run| {
+                let path = dir.join(format!(
+                    "saved-{}-{}.json",
+                    std::process::id(),
+                    run.seeds[0]
+                ));
+                let reports = run.reports.iter().map(SuiteReport::saved_json).collect();
+                write(&path, &run.seeds, reports).unwrap();
+                let read = read(&path).unwrap();
+                fs::remove_file(path).unwrap();
+                read
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merged_runs_add_up() {
+        let merged = merge(saved(vec![run(1, 1..101), run(2, 101..201)])).unwrap();
+
+        assert_eq!(merged.seeds, vec![1, 2]);
+        assert_eq!(
+            merged.reports[0].columns,
+            Some(vec![Column::Mean, Column::Ci95])
+        );
+        assert_eq!(merged.reports[0].budgets, vec![50]);
+        assert_eq!(merged.reports[1].columns, None);
+        let RowResult::Modules(modules) = &merged.reports[0].rows[0].result else {
+            panic!("expected module usage");
+        };
+        assert_eq!(modules.locked_buff, Some(Buff::Attack));
+        assert_eq!(modules.buffs, vec![Buff::Elemental]);
+        assert_eq!(modules.samples(), 200);
+        assert_eq!(modules.max(), 202);
+        assert_eq!(modules.percentile(50.0), 102);
+        assert_eq!(modules.outcomes.runs, 200);
+        assert_eq!(modules.outcomes.mean_lock_modules(), Some(2.0));
+        assert_eq!(modules.outcomes.mean_reroll_modules(), Some(100.5));
+        // Attack is the locked buff, so it comes first.
+        assert_eq!(modules.outcomes.slots, vec![[0, 0, 200], [200, 0, 0]]);
+        assert_eq!(modules.outcomes.third_slot, 200);
+        let RowResult::Probability(probability) = &merged.reports[1].rows[0].result else {
+            panic!("expected a probability");
+        };
+        assert_eq!((probability.hits, probability.attempts), (2, 20));
+    }
+
+    // Runs saved before the outcomes were recorded cannot be merged with those that have them,
+    // or the outcome columns would cover only part of the samples.
+    #[test]
+    fn runs_without_outcomes_are_not_merged_with_outcomes() {
+        let old = |seed| {
+            let mut old = run(seed, 101..201);
+            let RowResult::Modules(result) = &mut old.reports[0].rows[0].result else {
+                panic!("expected module usage");
+            };
+            result.outcomes = Outcomes::default();
+            old
+        };
+        assert!(merge(saved(vec![run(1, 1..101), old(2)])).is_err());
+        assert!(merge(saved(vec![old(2), run(1, 1..101)])).is_err());
+        assert!(merge(saved(vec![old(1), old(2)])).is_ok());
+    }
+
+    #[test]
+    fn same_seed_is_not_merged() {
+        assert!(merge(vec![run(1, 1..10), run(1, 1..10)]).is_err());
+    }
+}
@@ -0,0 +1,587 @@
+// Suites declared in TOML or YAML files instead of code, so that cases can be added without
+// recompiling. A scenario lists its goals, i.e. the wanted buffs of each row, the strategy and the
+// buff locked at the start for each, the statistics and budgets to show and the columns of the
+// table. Instead of listing them, the goals can be generated for every pattern of chances, see
+// coverage.rs. See scenarios/ for the scenarios that run by default.
+
+use std::{fs, path::Path};
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::coverage::{self, Coverage};
+use crate::registry::{coverage_param, Suite};
+use crate::report::{ReportRow, RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::simulation::{self, buff_to_string, buffs_to_string, Column, Strategy, SuiteConfig};
+use crate::Buff;
+
+/// The scenarios that run by default, after the suites in code.
+const STANDARD: [(&str, &str); 6] = [
+    (
+        "first-slot-locked.toml",
+        include_str!("../scenarios/first-slot-locked.toml"),
+    ),
+    (
+        "second-slot-locked.toml",
+        include_str!("../scenarios/second-slot-locked.toml"),
+    ),
+    (
+        "third-slot-locked.toml",
+        include_str!("../scenarios/third-slot-locked.toml"),
+    ),
+    (
+        "first-slot-waited.toml",
+        include_str!("../scenarios/first-slot-waited.toml"),
+    ),
+    (
+        "second-slot-waited.toml",
+        include_str!("../scenarios/second-slot-waited.toml"),
+    ),
+    (
+        "third-slot-waited.toml",
+        include_str!("../scenarios/third-slot-waited.toml"),
+    ),
+];
+
+/// The strategies of the files, see |Strategy|.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyName {
+    NoLocking,
+    LockImmediately,
+    Locked,
+    Waited,
+}
+
+impl StrategyName {
+    // Whether the strategy starts from a buff in a slot.
+    fn has_slot(self) -> bool {
+        matches!(self, StrategyName::Locked | StrategyName::Waited)
+    }
+}
+
+/// Checks that |want| can be got on a gear: 1 to 3 buffs, each of them once.
+pub fn check_want(want: &[Buff]) -> Result<(), String> {
+    if want.is_empty() || want.len() > 3 {
+        return Err("a goal wants 1 to 3 buffs".to_string());
+    }
+    if let Some(buff) = want.iter().duplicates().next() {
+        return Err(format!("{buff:?} is wanted more than once"));
+    }
+    Ok(())
+}
+
+fn parse_slot(slot: Option<usize>) -> Result<usize, String> {
+    match slot {
+        None => Err("the slot (1 to 3) to lock the buff in is missing".to_string()),
+        Some(slot) if !(1..=3).contains(&slot) => {
+            Err(format!("{slot} is not a slot, they are 1 to 3"))
+        }
+        Some(slot) => Ok(slot - 1),
+    }
+}
+
+// The strategy |name| that starts from |locked| in |slot| (1 to 3), if any. Without a name, it is
+// the locked strategy with a buff, and lock-immediately otherwise.
+fn strategy(
+    name: Option<StrategyName>,
+    locked: Option<Buff>,
+    slot: Option<usize>,
+) -> Result<Strategy, String> {
+    match (name, locked) {
+        (None | Some(StrategyName::Locked), Some(buff)) => {
+            Ok(Strategy::Locked(buff, parse_slot(slot)?))
+        }
+        (Some(StrategyName::Waited), Some(buff)) => Ok(Strategy::Waited(buff, parse_slot(slot)?)),
+        (Some(StrategyName::Locked | StrategyName::Waited), None) => {
+            Err("the locked and waited strategies need the buff that is locked".to_string())
+        }
+        (Some(_), Some(_)) => {
+            Err("only the locked and waited strategies start with a locked buff".to_string())
+        }
+        (None | Some(StrategyName::LockImmediately), None) => Ok(Strategy::LockImmediately),
+        (Some(StrategyName::NoLocking), None) => Ok(Strategy::NoLocking),
+    }
+}
+
+/// What a column of the table shows for each goal.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Value {
+    /// The buff that is locked at the start or waited for, or "-".
+    Locked,
+    /// All the wanted buffs.
+    Want,
+    /// The wanted buffs that are not locked at the start.
+    Buffs,
+    /// The first, second or third of the wanted buffs that are not locked at the start, or "N/A".
+    Buff1,
+    Buff2,
+    Buff3,
+    /// The name of the strategy.
+    Strategy,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnFile {
+    header: String,
+    value: Value,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GoalFile {
+    want: Vec<String>,
+    locked: Option<String>,
+    slot: Option<usize>,
+    strategy: Option<StrategyName>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CoverageFile {
+    sizes: Vec<usize>,
+    #[serde(default)]
+    buffs: Coverage,
+}
+
+// A scenario file. The strategy and the slot apply to the goals that do not have their own, and to
+// the generated goals.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    name: String,
+    summary: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    title: String,
+    description: String,
+    strategy: Option<StrategyName>,
+    slot: Option<usize>,
+    columns: Vec<ColumnFile>,
+    metrics: Option<Vec<String>>,
+    #[serde(default)]
+    budgets: Vec<u32>,
+    coverage: Option<CoverageFile>,
+    #[serde(default)]
+    goals: Vec<GoalFile>,
+}
+
+/// A row of a scenario: the wanted buffs and how to get them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Goal {
+    pub want: Vec<Buff>,
+    pub strategy: Strategy,
+}
+
+impl Goal {
+    fn locked(&self) -> Option<Buff> {
+        match self.strategy {
+            Strategy::Locked(buff, _) | Strategy::Waited(buff, _) => Some(buff),
+            _ => None,
+        }
+    }
+
+    // The wanted buffs that are not locked at the start.
+    fn rest(&self) -> Vec<Buff> {
+        let locked = self.locked();
+        self.want
+            .iter()
+            .copied()
+            .filter(|buff| Some(*buff) != locked)
+            .collect()
+    }
+
+    // The |value| of the row, with the chances of |rules|.
+    fn label(&self, rules: &Rules, value: Value) -> String {
+        let nth = |n: usize| {
+            self.rest()
+                .get(n)
+                .map_or("N/A".to_string(), |buff| buff_to_string(rules, *buff))
+        };
+        match value {
+            Value::Locked => self
+                .locked()
+                .map_or("-".to_string(), |buff| buff_to_string(rules, buff)),
+            Value::Want => buffs_to_string(rules, self.want.iter()),
+            Value::Buffs => buffs_to_string(rules, self.rest().iter()),
+            Value::Buff1 => nth(0),
+            Value::Buff2 => nth(1),
+            Value::Buff3 => nth(2),
+            Value::Strategy => self.strategy.name(),
+        }
+    }
+}
+
+/// Goals generated for the patterns of chances of the rules that the scenario runs with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generated {
+    /// The numbers of wanted buffs.
+    pub sizes: Vec<usize>,
+    pub buffs: Coverage,
+    /// The strategy of the file. The locked and waited strategies start from the first buff of
+    /// each goal in |slot| (1 to 3).
+    pub strategy: Option<StrategyName>,
+    pub slot: Option<usize>,
+}
+
+/// A suite declared in a file.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    /// The name that the command line uses.
+    pub name: String,
+    /// What it measures, for listing the suites.
+    pub summary: String,
+    pub tags: Vec<String>,
+    /// The name of the report.
+    pub title: String,
+    pub description: String,
+    /// The header of each column that identifies a row, and what it shows.
+    pub columns: Vec<(String, Value)>,
+    /// The statistics to show, or None for those of the output.
+    pub metrics: Option<Vec<Column>>,
+    pub budgets: Vec<u32>,
+    /// The goals listed in the file, which come first.
+    pub goals: Vec<Goal>,
+    pub generated: Option<Generated>,
+}
+
+impl Scenario {
+    /// Reads a scenario from a TOML file, or a YAML file if it ends with .yaml or .yml.
+    pub fn load(path: &Path) -> Result<Scenario, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        Scenario::parse(&path.display().to_string(), &text)
+            .map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// Parses the scenario file |text|. |file| is its name, whose extension decides the format.
+    pub fn parse(file: &str, text: &str) -> Result<Scenario, String> {
+        let file: ScenarioFile = if is_yaml(Path::new(file)) {
+            serde_yaml::from_str(text).map_err(|error| error.to_string())?
+        } else {
+            toml::from_str(text).map_err(|error| error.message().to_string())?
+        };
+        let parse_buff = |name: &str| name.parse::<Buff>().map_err(|error| format!("{error}"));
+
+        let mut goals = vec![];
+        for (i, goal) in file.goals.iter().enumerate() {
+            let invalid = |error: String| format!("goal {}: {error}", i + 1);
+            let want: Vec<Buff> = goal
+                .want
+                .iter()
+                .map(|name| parse_buff(name))
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+            check_want(&want).map_err(invalid)?;
+            let locked = goal
+                .locked
+                .as_deref()
+                .map(parse_buff)
+                .transpose()
+                .map_err(invalid)?;
+            if let Some(buff) = locked.filter(|buff| !want.contains(buff)) {
+                return Err(invalid(format!("the locked {buff:?} is not wanted")));
+            }
+            let strategy = strategy(
+                goal.strategy.or(file.strategy),
+                locked,
+                goal.slot.or(file.slot),
+            )
+            .map_err(invalid)?;
+            goals.push(Goal { want, strategy });
+        }
+
+        let generated = file
+            .coverage
+            .map(|coverage| {
+                let invalid = |error: String| format!("coverage: {error}");
+                if coverage.sizes.is_empty()
+                    || coverage.sizes.iter().any(|size| !(1..=3).contains(size))
+                {
+                    return Err(invalid("the goals want 1 to 3 buffs".to_string()));
+                }
+                if file.strategy.is_some_and(StrategyName::has_slot) {
+                    parse_slot(file.slot).map_err(invalid)?;
+                }
+                Ok(Generated {
+                    sizes: coverage.sizes,
+                    buffs: coverage.buffs,
+                    strategy: file.strategy,
+                    slot: file.slot,
+                })
+            })
+            .transpose()?;
+        if goals.is_empty() && generated.is_none() {
+            return Err("a scenario needs goals or a coverage".to_string());
+        }
+
+        let metrics = file
+            .metrics
+            .map(|metrics| metrics.iter().map(|key| key.parse()).collect())
+            .transpose()?;
+        Ok(Scenario {
+            name: file.name,
+            summary: file.summary,
+            tags: file.tags,
+            title: file.title,
+            description: file.description,
+            columns: file
+                .columns
+                .into_iter()
+                .map(|column| (column.header, column.value))
+                .collect(),
+            metrics,
+            budgets: file.budgets,
+            goals,
+            generated,
+        })
+    }
+
+    /// The goals of the rows with the chances of |rules|. |buffs| overrides the coverage of the
+    /// generated goals.
+    pub fn all_goals(&self, rules: &Rules, buffs: Option<Coverage>) -> Vec<Goal> {
+        let mut goals = self.goals.clone();
+        let Some(generated) = &self.generated else {
+            return goals;
+        };
+        for size in &generated.sizes {
+            let cases = coverage::cases(
+                rules,
+                *size,
+                generated.strategy.is_some_and(StrategyName::has_slot),
+                buffs.unwrap_or(generated.buffs),
+            );
+            goals.extend(cases.into_iter().map(|case| Goal {
+                want: case.want(),
+                strategy: strategy(generated.strategy, case.locked, generated.slot).unwrap(),
+            }));
+        }
+        goals
+    }
+
+    /// Simulates each goal, with |buffs| overriding the coverage of the generated goals.
+    pub fn run(&self, config: &SuiteConfig, buffs: Option<Coverage>) -> SuiteReport {
+        let headers: Vec<&str> = self
+            .columns
+            .iter()
+            .map(|(header, _)| header.as_str())
+            .collect();
+        let goals = self.all_goals(&config.rules, buffs);
+        let mut description = self.description.clone();
+        if goals.iter().any(|goal| goal.strategy.locks()) {
+            description += &format!(" {}", simulation::lock_cost_note(&config.rules));
+        }
+        let mut report = SuiteReport::new(
+            &self.title,
+            &format!("{description} {}", config.modules_stop.describe("modules")),
+            &headers,
+        );
+        report.columns = self.metrics.clone();
+        if !self.budgets.is_empty() {
+            report.columns.get_or_insert_with(|| config.columns.clone());
+            report.budgets = self.budgets.clone();
+        }
+        let sampler = config.sampler(report.name.as_str());
+        let mut left_out = false;
+        for goal in &goals {
+            if goal.strategy.check(&config.rules, &goal.want).is_err() {
+                left_out = true;
+                continue;
+            }
+            report.rows.push(ReportRow {
+                labels: self
+                    .columns
+                    .iter()
+                    .map(|(_, value)| goal.label(&config.rules, *value))
+                    .collect(),
+                result: RowResult::Modules(simulation::simulate_strategy(
+                    goal.strategy,
+                    &goal.want,
+                    &sampler,
+                )),
+            });
+        }
+        simulation::note_left_out(&mut report, left_out);
+        report
+    }
+
+    /// The scenario as a suite. With generated goals, the coverage is a parameter.
+    pub fn suite(self) -> Suite {
+        let (name, summary, tags) = (self.name.clone(), self.summary.clone(), self.tags.clone());
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        let params = match &self.generated {
+            Some(generated) => vec![coverage_param(Some(generated.buffs.name()))],
+            None => vec![],
+        };
+        Suite::new(&name, &summary, &tags, params, move |config, params| {
+            self.run(config, params.coverage())
+        })
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("yaml" | "yml")
+    )
+}
+
+/// The scenarios that run by default.
+pub fn standard() -> Vec<Scenario> {
+    STANDARD
+        .iter()
+        .map(|(file, text)| Scenario::parse(file, text).unwrap())
+        .collect()
+}
+
+/// The scenarios of |path|, a scenario file or a directory of them, in the order of their names.
+pub fn load(path: &Path) -> Result<Vec<Scenario>, String> {
+    if !path.is_dir() {
+        return Ok(vec![Scenario::load(path)?]);
+    }
+    let mut paths: Vec<_> = fs::read_dir(path)
+        .map_err(|error| format!("{}: {error}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            is_yaml(path)
+                || path.extension().and_then(|extension| extension.to_str()) == Some("toml")
+        })
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| Scenario::load(path)).collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::sampling::StopRule;
+
+    const YAML: &str = "
+name: attack-elemental
+summary: custom modules to get Attack and Elemental
+tags: [modules]
+title: Attack and Elemental
+description: Each strategy.
+metrics: [mean, p90]
+budgets: [30]
+columns:
+  - { header: strategy, value: strategy }
+  - { header: buffs, value: want }
+goals:
+  - { want: [atk, elem], strategy: no-locking }
+  - { want: [atk, elem] }
+  - { want: [atk, elem], locked: atk, slot: 3 }
+";
+
+    #[test]
+    fn standard_scenarios_are_valid() {
+        let scenarios = standard();
+        assert_eq!(scenarios.len(), 6);
+        let third = &scenarios[2];
+        assert_eq!(third.name, "third-slot-locked");
+        // 4 patterns of two buffs and 6 of three buffs.
+        let goals = third.all_goals(&Rules::standard(), None);
+        assert_eq!(goals.len(), 10);
+        assert_eq!(
+            goals[0],
+            Goal {
+                want: vec![Buff::Elemental, Buff::Attack],
+                strategy: Strategy::Locked(Buff::Elemental, 2),
+            }
+        );
+        assert_eq!(goals[0].label(&Rules::standard(), Value::Buff2), "N/A");
+        let all = third.all_goals(&Rules::standard(), Some(Coverage::All));
+        // 9 locked buffs, and 8 others or 8 choose 2 others each.
+        assert_eq!(all.len(), 9 * 8 + 9 * 28);
+        assert!(third
+            .description
+            .starts_with("The following table shows how many"));
+        assert!(!third.description.contains("  "));
+    }
+
+    #[test]
+    fn yaml_scenarios_run() {
+        let scenario = Scenario::parse("test.yaml", YAML).unwrap();
+        assert_eq!(scenario.metrics, Some(vec![Column::Mean, Column::P90]));
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(500),
+            ..Default::default()
+        };
+        let report = scenario.clone().suite().run(&config, &[]).unwrap();
+        assert_eq!(report.name, "Attack and Elemental");
+        assert_eq!(report.labels, vec!["strategy", "buffs"]);
+        assert_eq!(report.rows.len(), 3);
+        assert_eq!(report.rows[2].labels[0], "Attack locked in slot 3");
+        assert_eq!(report.columns, Some(vec![Column::Mean, Column::P90]));
+        assert_eq!(report.budgets, vec![30]);
+
+        // The third slot never gets a buff, so the goal that locks a buff in it is left out.
+        let config = SuiteConfig {
+            rules: Arc::new(Rules::from_toml("test", "third_slot_percent = 0\n").unwrap()),
+            ..config
+        };
+        let report = scenario.suite().run(&config, &[]).unwrap();
+        assert_eq!(report.rows.len(), 2);
+        assert!(report.description.ends_with("are left out."));
+    }
+
+    #[test]
+    fn invalid_scenarios_are_rejected() {
+        let goal = |goal: &str| {
+            format!(
+                "name = 'x'\nsummary = 'x'\ntitle = 'x'\ndescription = 'x'\ncolumns = []\n\
+                [[goals]]\n{goal}\n"
+            )
+        };
+        assert!(Scenario::parse("x.toml", &goal("want = ['Attack']")).is_ok());
+        let waited = goal("want = ['Attack']\nlocked = 'Attack'\nslot = 3\nstrategy = 'waited'");
+        assert_eq!(
+            Scenario::parse("x.toml", &waited).unwrap().goals[0].strategy,
+            Strategy::Waited(Buff::Attack, 2)
+        );
+        for invalid in [
+            "want = []",
+            "want = ['Attack', 'atk']",
+            "want = ['Attack', 'Elemental', 'CritDamage', 'Defense']",
+            "want = ['Atack']",
+            "want = ['Attack']\nlocked = 'Attack'",
+            "want = ['Attack']\nlocked = 'Elemental'\nslot = 1",
+            "want = ['Attack']\nlocked = 'Attack'\nslot = 4",
+            "want = ['Attack']\nstrategy = 'locked'",
+            "want = ['Attack']\nstrategy = 'waited'\nslot = 3",
+            "want = ['Attack']\nlocked = 'Attack'\nslot = 1\nstrategy = 'no-locking'",
+            "want = ['Attack']\nunknown = 1",
+        ] {
+            assert!(
+                Scenario::parse("x.toml", &goal(invalid)).is_err(),
+                "{invalid}"
+            );
+        }
+        let metrics = goal("want = ['Attack']").replace("columns", "metrics = ['median']\ncolumns");
+        assert!(Scenario::parse("x.toml", &metrics).is_err());
+
+        let coverage = |coverage: &str| {
+            format!(
+                "name = 'x'\nsummary = 'x'\ntitle = 'x'\ndescription = 'x'\ncolumns = []\n{coverage}"
+            )
+        };
+        assert!(Scenario::parse("x.toml", &coverage("[coverage]\nsizes = [2]\n")).is_ok());
+        for invalid in [
+            "[coverage]\nsizes = []\n",
+            "[coverage]\nsizes = [4]\n",
+            "[coverage]\nsizes = [2]\nbuffs = 'some'\n",
+            "strategy = 'locked'\n[coverage]\nsizes = [2]\n",
+        ] {
+            assert!(
+                Scenario::parse("x.toml", &coverage(invalid)).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,333 @@
+// An interactive session that mirrors the reroll screen of the game, for practicing when to lock
+// without spending real modules. Commands are read line by line, and after every step the slots,
+// the modules spent and the advisor's suggestion are shown.
+
+use std::{
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+use rand::Rng;
+
+use crate::advisor::{self, Action};
+use crate::simulation::SuiteConfig;
+use crate::solver::MAX_STOCK;
+use crate::{BuffSet, Simulation, SlotState};
+
+const HELP: &str = "Commands: reroll (r), lock <slot> (l), unlock <slot> (u), status (s), \
+    help (h), quit (q).";
+
+/// A command of the session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Reroll,
+    /// Lock the buff in the slot (0 based).
+    Lock(usize),
+    /// Unlock the buff in the slot (0 based).
+    Unlock(usize),
+    Status,
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let slot = |word: &str| match word.parse::<usize>() {
+            Ok(slot @ 1..=3) => Ok(slot - 1),
+            _ => Err(format!("'{word}' is not a slot, the slots are 1, 2 and 3")),
+        };
+        let words: Vec<String> = line.split_whitespace().map(str::to_lowercase).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        match words.as_slice() {
+            ["reroll" | "r"] => Ok(Command::Reroll),
+            ["lock" | "l", word] => Ok(Command::Lock(slot(word)?)),
+            ["unlock" | "u", word] => Ok(Command::Unlock(slot(word)?)),
+            ["status" | "s"] => Ok(Command::Status),
+            ["help" | "h" | "?"] => Ok(Command::Help),
+            ["quit" | "q" | "exit"] => Ok(Command::Quit),
+            _ => Err(format!("unknown command '{}'. {HELP}", line.trim())),
+        }
+    }
+}
+
+/// The gear of a session, rolled with |config|'s rules, and the buffs that the player wants.
+pub struct Session<'a, R: Rng> {
+    config: &'a SuiteConfig,
+    sim: Simulation<R>,
+    want: BuffSet,
+    // The custom modules that the player has, or None for unlimited modules.
+    stock: Option<u32>,
+}
+
+impl<'a, R: Rng> Session<'a, R> {
+    /// A session with a gear without buffs, which rolls with |rng|.
+    pub fn new(config: &'a SuiteConfig, want: BuffSet, stock: Option<u32>, rng: R) -> Self {
+        Session {
+            config,
+            sim: Simulation::with_rules(config.rules.clone(), rng),
+            want,
+            stock,
+        }
+    }
+
+    pub fn slots(&self) -> [SlotState; 3] {
+        self.sim.buffs().try_into().unwrap()
+    }
+
+    // The modules left from the stock.
+    fn left(&self) -> Option<u32> {
+        self.stock
+            .map(|stock| stock.saturating_sub(self.sim.custom_modules()))
+    }
+
+    /// Rerolls, locks or unlocks, or returns why it cannot be done. The other commands do not
+    /// change the session.
+    pub fn apply(&mut self, command: Command) -> Result<(), String> {
+        let rules = &self.config.rules;
+        let slots = self.slots();
+        let locked = slots
+            .iter()
+            .filter(|slot| matches!(slot, SlotState::Locked(_)))
+            .count() as u32;
+        let can_pay = |cost: u32| -> Result<(), String> {
+            match self.left() {
+                Some(left) if cost > left => Err(format!(
+                    "not enough custom modules, it costs {cost} and {left} are left"
+                )),
+                _ => Ok(()),
+            }
+        };
+        match command {
+            Command::Reroll => {
+                let cost = rules
+                    .reroll_cost(locked)
+                    .ok_or(format!("{locked} locked buffs cannot be rerolled"))?;
+                can_pay(cost)?;
+                self.sim.reroll();
+            }
+            Command::Lock(pos) => {
+                match slots[pos] {
+                    SlotState::None => return Err(format!("slot {} has no buff", pos + 1)),
+                    SlotState::Locked(_) => {
+                        return Err(format!("slot {} is already locked", pos + 1))
+                    }
+                    SlotState::Free(_) => {}
+                }
+                let cost = rules
+                    .lock_cost(locked)
+                    .ok_or(format!("at most {locked} buffs can be locked"))?;
+                can_pay(cost)?;
+                self.sim.lock(pos);
+            }
+            Command::Unlock(pos) => {
+                if !matches!(slots[pos], SlotState::Locked(_)) {
+                    return Err(format!("slot {} is not locked", pos + 1));
+                }
+                self.sim.unlock(pos);
+            }
+            Command::Status | Command::Help | Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// The slots and their locks, the modules spent and the advisor's suggestion. Stocks that the
+    /// solver cannot go through are advised as unlimited, rather than simulated on every step.
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "Rerolls: {}, custom modules spent: {}",
+            self.sim.attempts(),
+            self.sim.custom_modules()
+        );
+        if let Some(left) = self.left() {
+            status += &format!(" ({left} left)");
+        }
+        status += "\n";
+        let slots = self.slots();
+        for (pos, slot) in slots.iter().enumerate() {
+            let (name, lock) = match slot {
+                SlotState::None => ("-".to_string(), ""),
+                SlotState::Free(buff) => (format!("{buff:?}"), ""),
+                SlotState::Locked(buff) => (format!("{buff:?}"), " [locked]"),
+            };
+            let wanted = match slot {
+                SlotState::Free(buff) | SlotState::Locked(buff) if self.want.contains(*buff) => {
+                    " *"
+                }
+                _ => "",
+            };
+            status += &format!("  slot {}: {name}{lock}{wanted}\n", pos + 1);
+        }
+        let missing: Vec<String> = self
+            .want
+            .intersection(self.sim.shown_buffs().complement())
+            .iter()
+            .map(|buff| format!("{buff:?}"))
+            .collect();
+        if missing.is_empty() {
+            status += "All the wanted buffs (*) are shown.\n";
+        } else {
+            status += &format!("Missing: {}\n", missing.join(", "));
+        }
+
+        let stock = self.left().filter(|left| *left <= MAX_STOCK);
+        let advice = advisor::advice(self.config, slots, self.want, stock);
+        let (_, outlook) = advice
+            .outlooks
+            .iter()
+            .find(|(action, _)| *action == advice.best)
+            .unwrap();
+        status += &format!(
+            "Advisor: {} ({:.2}% to get the buffs, {:.2} more modules expected)\n",
+            advice.best.name(),
+            outlook.success * 100.0,
+            outlook.modules
+        );
+        if advice.best != Action::Stop {
+            let others = advice
+                .outlooks
+                .iter()
+                .filter(|(action, _)| *action != advice.best && *action != Action::Stop)
+                .map(|(action, outlook)| format!("{} {:.2}", action.name(), outlook.modules))
+                .join(", ");
+            if !others.is_empty() {
+                status += &format!("  other choices (modules expected): {others}\n");
+            }
+        }
+        status
+    }
+}
+
+// Writes to the output and to the transcript, if there is one.
+struct Tee<'a, 'b> {
+    out: &'a mut dyn Write,
+    transcript: Option<&'b mut dyn Write>,
+}
+
+impl Write for Tee<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write_all(buf)?;
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs |session| with the commands of |input| until it ends or a quit command, and writes what
+/// happens to |out|. The |transcript| gets the same, with the commands.
+pub fn run<R: Rng>(
+    session: &mut Session<R>,
+    input: impl BufRead,
+    out: &mut dyn Write,
+    transcript: Option<&mut dyn Write>,
+) -> io::Result<()> {
+    let mut tee = Tee { out, transcript };
+    writeln!(tee, "{HELP}")?;
+    write!(tee, "{}", session.status())?;
+    for line in input.lines() {
+        let line = line?;
+        if let Some(transcript) = tee.transcript.as_mut() {
+            writeln!(transcript, "> {line}")?;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse() {
+            Ok(Command::Quit) => break,
+            Ok(Command::Help) => writeln!(tee, "{HELP}")?,
+            Ok(command) => match session.apply(command) {
+                Ok(()) => write!(tee, "{}", session.status())?,
+                Err(error) => writeln!(tee, "{error}")?,
+            },
+            Err(error) => writeln!(tee, "{error}")?,
+        }
+        tee.flush()?;
+    }
+    tee.flush()
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Buff;
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!("reroll".parse(), Ok(Command::Reroll));
+        assert_eq!(" R ".parse(), Ok(Command::Reroll));
+        assert_eq!("lock 2".parse(), Ok(Command::Lock(1)));
+        assert_eq!("unlock 1".parse(), Ok(Command::Unlock(0)));
+        assert_eq!("status".parse(), Ok(Command::Status));
+        assert!("lock 4".parse::<Command>().is_err());
+        assert!("lock".parse::<Command>().is_err());
+        assert!("jump".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn session_follows_the_commands() {
+        let config = SuiteConfig::default();
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        let mut session = Session::new(&config, want, Some(10), StdRng::seed_from_u64(3));
+        assert_eq!(
+            session.apply(Command::Lock(0)),
+            Err("slot 1 has no buff".to_string())
+        );
+        session.apply(Command::Reroll).unwrap();
+        session.apply(Command::Lock(0)).unwrap();
+        assert!(matches!(session.slots()[0], SlotState::Locked(_)));
+        assert_eq!(
+            session.apply(Command::Lock(0)),
+            Err("slot 1 is already locked".to_string())
+        );
+        assert!(session
+            .status()
+            .contains("custom modules spent: 3 (7 left)"));
+        session.apply(Command::Unlock(0)).unwrap();
+        assert!(matches!(session.slots()[0], SlotState::Free(_)));
+        for _ in 0..7 {
+            session.apply(Command::Reroll).unwrap();
+        }
+        assert_eq!(
+            session.apply(Command::Reroll),
+            Err("not enough custom modules, it costs 1 and 0 are left".to_string())
+        );
+    }
+
+    #[test]
+    fn transcript_records_the_commands() {
+        let config = SuiteConfig::default();
+        let want = BuffSet::from_iter([Buff::Attack]);
+        let mut session = Session::new(&config, want, None, StdRng::seed_from_u64(3));
+        let mut out = vec![];
+        let mut transcript = vec![];
+        run(
+            &mut session,
+            "reroll\njump\nstatus\nquit\nreroll\n".as_bytes(),
+            &mut out,
+            Some(&mut transcript),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        assert!(out.contains("Rerolls: 1"));
+        assert!(out.contains("unknown command 'jump'"));
+        assert!(!out.contains("Rerolls: 2"));
+        assert!(transcript.contains("> reroll\n"));
+        assert!(transcript.contains("> quit\n"));
+        assert_eq!(transcript.matches("Advisor:").count(), 3);
+    }
+}
@@ -1,53 +1,556 @@
-use tabled::{
-    builder::Builder,
-    settings::{style::Style, themes::ColumnNames},
-};
-
+use enum_assoc::Assoc;
+use serde_json::{json, Value};
+
+use crate::coverage::{self, Coverage};
+use crate::importance::{self, Proposal};
+use crate::report::{ReportRow, RowResult, SuiteReport};
+use crate::rules::Rules;
+use crate::sampling::{self, Estimate, Precision, SampleRng, StopRule, VarianceReduction};
+use crate::stats::{self, Interval};
 use crate::*;
 
-use hdrhistogram::Histogram;
+use std::{str::FromStr, sync::Arc};
 
-const DEFAULT_ATTEMPTS: u32 = 100000;
+use itertools::Itertools;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hdrhistogram::{
+    serialization::{Deserializer, Serializer, V2DeflateSerializer},
+    Histogram,
+};
 
-const START_SUITE_MARKER: &str = "===== SUITE START =====";
-const END_SUITE_MARKER: &str = "=====  SUITE END  =====";
+const DEFAULT_ATTEMPTS: u64 = 100000;
 
-fn buff_to_string(buff: Buff) -> String {
-    buffs_to_string([buff].iter())
+pub fn buff_to_string(rules: &Rules, buff: Buff) -> String {
+    buffs_to_string(rules, [buff].iter())
 }
 
-fn buffs_to_string<'a, I>(buffs: I) -> String
+/// E.g. "Attack(12.5%) and Elemental(10%)", with the chances of |rules|.
+pub fn buffs_to_string<'a, I>(rules: &Rules, buffs: I) -> String
 where
     I: Iterator<Item = &'a Buff>,
 {
     buffs
-        .map(|item| format!("{:?}({}%)", item, item.percent()))
+        .map(|item| format!("{:?}({}%)", item, rules.percent(*item)))
         .collect::<Vec<String>>()
         .join(" and ")
 }
 
-struct SuitePrint {}
+// Notes in the description of |report| that the rows of the buffs that the rules never get were
+// left out, if |left_out|.
+pub(crate) fn note_left_out(report: &mut SuiteReport, left_out: bool) {
+    if left_out {
+        report.description += " The buffs that the rules never show together, e.g. because a \
+            slot never gets a buff, are left out.";
+    }
+}
 
-impl SuitePrint {
-    pub fn new(name: &str, description: &str) -> Self {
-        println!("{}", START_SUITE_MARKER);
-        println!("Name: {}", name);
-        println!("Description: {}", description);
-        Self {}
+// The sentence of the suite descriptions on what locking costs with |rules|, e.g. "The cost of
+// locking modules (2, then 3) is accounted."
+pub(crate) fn lock_cost_note(rules: &Rules) -> String {
+    if rules.lock_costs.is_empty() {
+        return "Nothing can be locked with the rules.".to_string();
     }
+    format!(
+        "The cost of locking modules ({}) is accounted.",
+        rules.lock_costs.iter().join(", then ")
+    )
 }
 
-impl Drop for SuitePrint {
-    fn drop(&mut self) {
-        println!("{}", END_SUITE_MARKER);
+/// A statistic of the custom module usage runs, e.g. on the number of custom modules or the
+/// rerolls, that can be shown as a column in a suite table.
+#[derive(Assoc, Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+#[func(pub fn header(&self) -> &'static str)]
+#[func(pub fn key(&self) -> &'static str)]
+pub enum Column {
+    #[assoc(header = "mean (modules)")]
+    #[assoc(key = "mean")]
+    Mean,
+    #[assoc(header = "std error (modules)")]
+    #[assoc(key = "std_error")]
+    StdErr,
+    #[assoc(header = "95% CI (modules)")]
+    #[assoc(key = "ci95")]
+    Ci95,
+    #[assoc(header = "std dev (modules)")]
+    #[assoc(key = "stddev")]
+    StdDev,
+    #[assoc(header = "p50 (modules)")]
+    #[assoc(key = "p50")]
+    P50,
+    #[assoc(header = "p75 (modules)")]
+    #[assoc(key = "p75")]
+    P75,
+    #[assoc(header = "p90 (modules)")]
+    #[assoc(key = "p90")]
+    P90,
+    #[assoc(header = "p95 (modules)")]
+    #[assoc(key = "p95")]
+    P95,
+    #[assoc(header = "p99 (modules)")]
+    #[assoc(key = "p99")]
+    P99,
+    #[assoc(header = "max (modules)")]
+    #[assoc(key = "max")]
+    Max,
+    #[assoc(header = "samples")]
+    #[assoc(key = "samples")]
+    Samples,
+    #[assoc(header = "rerolls (mean)")]
+    #[assoc(key = "rerolls")]
+    Rerolls,
+    #[assoc(header = "locks (mean)")]
+    #[assoc(key = "locks")]
+    Locks,
+    #[assoc(header = "on rerolls (modules)")]
+    #[assoc(key = "reroll_modules")]
+    RerollModules,
+    #[assoc(header = "on locks (modules)")]
+    #[assoc(key = "lock_modules")]
+    LockModules,
+    /// The share of the runs in which a wanted buff ended in the third slot.
+    #[assoc(header = "in third slot")]
+    #[assoc(key = "third_slot")]
+    ThirdSlot,
+    /// The shares of the runs in which each wanted buff ended in the first, second and third slot.
+    #[assoc(header = "slots 1/2/3")]
+    #[assoc(key = "slots")]
+    Slots,
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        Column::iter()
+            .find(|column| column.key() == key)
+            .ok_or(format!(
+                "unknown statistic '{key}', the statistics are {}",
+                Column::iter().map(|column| column.key()).join(", ")
+            ))
+    }
+}
+
+impl Column {
+    /// The statistic formatted for a table cell.
+    pub fn value(&self, result: &SimulationResult) -> String {
+        match self {
+            Column::Mean => format!("{:.3}", result.mean()),
+            Column::StdErr => format!("{:.3}", result.standard_error()),
+            Column::Ci95 => {
+                let interval = result.confidence_interval();
+                format!("[{:.3}, {:.3}]", interval.lower, interval.upper)
+            }
+            Column::StdDev => format!("{:.3}", result.stddev()),
+            Column::P50 => result.percentile(50.0).to_string(),
+            Column::P75 => result.percentile(75.0).to_string(),
+            Column::P90 => result.percentile(90.0).to_string(),
+            Column::P95 => result.percentile(95.0).to_string(),
+            Column::P99 => result.percentile(99.0).to_string(),
+            Column::Max => result.max().to_string(),
+            Column::Samples => result.samples().to_string(),
+            Column::Rerolls => outcome_cell(result.outcomes.mean_rerolls(), "", 1.0),
+            Column::Locks => outcome_cell(result.outcomes.mean_locks(), "", 1.0),
+            Column::RerollModules => outcome_cell(result.outcomes.mean_reroll_modules(), "", 1.0),
+            Column::LockModules => outcome_cell(result.outcomes.mean_lock_modules(), "", 1.0),
+            Column::ThirdSlot => outcome_cell(result.outcomes.third_slot_share(), "%", 100.0),
+            Column::Slots => match result.outcomes.slot_shares() {
+                Some(shares) => result
+                    .wanted()
+                    .iter()
+                    .zip(shares)
+                    .map(|(buff, shares)| {
+                        format!(
+                            "{buff:?} {}%",
+                            shares
+                                .map(|share| format!("{:.0}", share * 100.0))
+                                .join("/")
+                        )
+                    })
+                    .join(", "),
+                None => "-".to_string(),
+            },
+        }
+    }
+
+    /// The statistic as machine readable (key, value) pairs. Intervals are split into a lower and
+    /// an upper bound, and the slots into a share per wanted buff and slot.
+    pub fn json(&self, result: &SimulationResult) -> Vec<(String, Value)> {
+        let value = match self {
+            Column::Mean => json!(result.mean()),
+            Column::StdErr => json!(result.standard_error()),
+            Column::Ci95 => {
+                let interval = result.confidence_interval();
+                return vec![
+                    (format!("{}_lower", self.key()), json!(interval.lower)),
+                    (format!("{}_upper", self.key()), json!(interval.upper)),
+                ];
+            }
+            Column::StdDev => json!(result.stddev()),
+            Column::P50 => json!(result.percentile(50.0)),
+            Column::P75 => json!(result.percentile(75.0)),
+            Column::P90 => json!(result.percentile(90.0)),
+            Column::P95 => json!(result.percentile(95.0)),
+            Column::P99 => json!(result.percentile(99.0)),
+            Column::Max => json!(result.max()),
+            Column::Samples => json!(result.samples()),
+            Column::Rerolls => json!(result.outcomes.mean_rerolls()),
+            Column::Locks => json!(result.outcomes.mean_locks()),
+            Column::RerollModules => json!(result.outcomes.mean_reroll_modules()),
+            Column::LockModules => json!(result.outcomes.mean_lock_modules()),
+            Column::ThirdSlot => json!(result.outcomes.third_slot_share()),
+            Column::Slots => {
+                let Some(shares) = result.outcomes.slot_shares() else {
+                    return vec![(self.key().to_string(), Value::Null)];
+                };
+                return result
+                    .wanted()
+                    .iter()
+                    .zip(shares)
+                    .flat_map(|(buff, shares)| {
+                        shares.into_iter().enumerate().map(move |(pos, share)| {
+                            (format!("{}_{buff:?}_{}", self.key(), pos + 1), json!(share))
+                        })
+                    })
+                    .collect();
+            }
+        };
+        vec![(self.key().to_string(), value)]
+    }
+}
+
+// A cell of a statistic of the outcomes, scaled by |scale|, or "-" if the outcomes were not
+// recorded, e.g. in a saved run from before they were.
+fn outcome_cell(value: Option<f64>, unit: &str, scale: f64) -> String {
+    value.map_or("-".to_string(), |value| {
+        format!("{:.2}{unit}", value * scale)
+    })
+}
+
+/// Options shared by all the suites.
+pub struct SuiteConfig {
+    /// The statistics shown in each table, in order.
+    pub columns: Vec<Column>,
+    /// How many runs each row that reports custom module usage takes.
+    pub modules_stop: StopRule,
+    /// How many rolls each row that reports a probability takes.
+    pub probability_stop: StopRule,
+    /// Receives the individual runs of the custom module usage simulations, if set.
+    pub runs: Option<Arc<dyn RunSink>>,
+    /// Master seed of the random number generators. The same seed gives the same results.
+    pub seed: u64,
+    /// Applies to the custom module usage simulations.
+    pub variance_reduction: VarianceReduction,
+    pub rules: Arc<Rules>,
+}
+
+impl SuiteConfig {
+    /// The parameters of a run as (name, value) pairs, for the headers of the reports.
+    pub fn parameters(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "engine version",
+                format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ),
+            ("seed", self.seed.to_string()),
+        ]
+        .into_iter()
+        .chain(self.rules.parameters())
+        .chain([
+            (
+                "custom module usage sampling",
+                self.modules_stop.describe("modules"),
+            ),
+            ("probability sampling", self.probability_stop.describe("")),
+            ("variance reduction", self.variance_reduction.describe()),
+        ])
+        .collect()
+    }
+
+    pub(crate) fn sampler<'a>(&'a self, suite: &'a str) -> Sampler<'a> {
+        Sampler {
+            rule: &self.modules_stop,
+            suite,
+            runs: self.runs.as_deref(),
+            seed: self.seed,
+            reduction: self.variance_reduction,
+            rules: &self.rules,
+        }
+    }
+
+    // The seed of the simulation identified by |key|.
+    pub(crate) fn seed(&self, key: &str) -> u64 {
+        sampling::derive_seed(self.seed, key)
+    }
+}
+
+/// Receives every individual run of the custom module usage simulations as it finishes.
+pub trait RunSink: Send + Sync {
+    /// |result| is the row that the run belongs to, and |run| is how the run went.
+    fn run(&self, suite: &str, result: &SimulationResult, run: &RunOutcome);
+}
+
+/// Runs the simulations of a suite's rows.
+pub struct Sampler<'a> {
+    rule: &'a StopRule,
+    suite: &'a str,
+    runs: Option<&'a dyn RunSink>,
+    seed: u64,
+    reduction: VarianceReduction,
+    rules: &'a Arc<Rules>,
+}
+
+impl Sampler<'_> {
+    // Records the custom module usage returned by |simulate|, and the rest of the outcome of the
+    // simulation, until the stop rule is satisfied.
+    // The generators of the simulations passed to |simulate| are seeded from the suite, the row,
+    // i.e. |result|'s buffs, and the chunk of runs. With common random numbers, the rows of all
+    // the suites that want the same buffs get the same seeds.
+    pub(crate) fn run(
+        &self,
+        result: &mut SimulationResult,
+        simulate: impl Fn(&mut Simulation<&mut SampleRng>) -> u32 + Sync,
+    ) {
+        let reduction = self.reduction;
+        let key = if reduction.common_random_numbers {
+            format!("{:?}/{:?}", result.locked_buff, result.buffs)
+        } else {
+            format!("{}/{:?}/{:?}", self.suite, result.locked_buff, result.buffs)
+        };
+        let seed = sampling::derive_seed(self.seed, &key);
+        let (locked_buff, buffs) = (result.locked_buff, result.buffs.clone());
+        let simulate_with = |mut rng: SampleRng| {
+            let mut sim = Simulation::with_rules(self.rules.clone(), &mut rng);
+            if reduction.common_random_numbers {
+                sim.synchronize_draws();
+            }
+            let modules = simulate(&mut sim);
+            RunOutcome::of(&sim, modules)
+        };
+        self.rule.run(
+            result,
+            |chunk, samples| {
+                let mut rng = sampling::chunk_rng(seed, chunk);
+                let mut part = SimulationResult::new();
+                part.locked_buff = locked_buff;
+                part.buffs = buffs.clone();
+                // The individual runs are kept only for the sink, which gets them in order.
+                let mut runs = vec![];
+                let mut record = |run: RunOutcome| {
+                    part.record_run(&run);
+                    if self.runs.is_some() {
+                        runs.push(run);
+                    }
+                };
+                if reduction == VarianceReduction::default() {
+                    let mut rng = SampleRng::new(rng);
+                    for _ in 0..samples {
+                        let mut sim = Simulation::with_rules(self.rules.clone(), &mut rng);
+                        let modules = simulate(&mut sim);
+                        record(RunOutcome::of(&sim, modules));
+                    }
+                } else {
+                    // Every run gets a generator of its own, so that the runs of the strategies,
+                    // which use different amounts of random numbers, stay in step.
+                    let mut recorded = 0;
+                    while recorded < samples {
+                        let run_seed = rng.next_u64();
+                        record(simulate_with(SampleRng::new(StdRng::seed_from_u64(
+                            run_seed,
+                        ))));
+                        recorded += 1;
+                        if reduction.antithetic && recorded < samples {
+                            record(simulate_with(SampleRng::antithetic(StdRng::seed_from_u64(
+                                run_seed,
+                            ))));
+                            recorded += 1;
+                        }
+                    }
+                }
+                (part, runs)
+            },
+            |result, (part, runs)| {
+                result.merge(&part).unwrap();
+                if let Some(sink) = self.runs {
+                    for run in runs {
+                        sink.run(self.suite, result, &run);
+                    }
+                }
+            },
+        );
+    }
+}
+
+impl Default for SuiteConfig {
+    fn default() -> Self {
+        SuiteConfig {
+            columns: vec![
+                Column::Mean,
+                Column::StdErr,
+                Column::Ci95,
+                Column::StdDev,
+                Column::P50,
+                Column::P75,
+                Column::P90,
+                Column::P95,
+                Column::P99,
+                Column::Max,
+                Column::Samples,
+            ],
+            modules_stop: StopRule {
+                precision: Some(Precision::Absolute(0.25)),
+                min_samples: 10000,
+                max_samples: 10000000,
+                max_duration: None,
+            },
+            probability_stop: StopRule {
+                precision: Some(Precision::Relative(0.05)),
+                min_samples: DEFAULT_ATTEMPTS,
+                max_samples: 100000000,
+                max_duration: None,
+            },
+            runs: None,
+            seed: rand::random(),
+            variance_reduction: VarianceReduction::default(),
+            rules: Rules::standard(),
+        }
+    }
+}
+
+/// How a run of a custom module usage simulation went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunOutcome {
+    pub modules: u32,
+    pub rerolls: u32,
+    pub locks: u32,
+    /// The parts of |modules| that the rerolls and the locks used.
+    pub reroll_modules: u32,
+    pub lock_modules: u32,
+    /// The buff of each slot at the end, if it has one.
+    pub slots: [Option<Buff>; 3],
+}
+
+impl RunOutcome {
+    /// The outcome of |sim| at the end of a run that used |modules|.
+    pub fn of<R: Rng>(sim: &Simulation<R>, modules: u32) -> Self {
+        RunOutcome {
+            modules,
+            rerolls: sim.attempts(),
+            locks: sim.locks(),
+            reroll_modules: sim.reroll_modules(),
+            lock_modules: sim.lock_modules(),
+            slots: slots(sim),
+        }
+    }
+}
+
+/// The outcomes of the runs of a row besides the histogram of the modules, summed up.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outcomes {
+    pub runs: u64,
+    pub modules: u64,
+    pub rerolls: u64,
+    pub locks: u64,
+    pub reroll_modules: u64,
+    pub lock_modules: u64,
+    /// The runs in which each wanted buff ended in each slot, in the order of
+    /// |SimulationResult::wanted|.
+    pub slots: Vec<[u64; 3]>,
+    /// The runs in which a wanted buff ended in the third slot.
+    pub third_slot: u64,
+}
+
+impl Outcomes {
+    fn record(&mut self, want: &[Buff], run: &RunOutcome) {
+        self.runs += 1;
+        self.modules += run.modules as u64;
+        self.rerolls += run.rerolls as u64;
+        self.locks += run.locks as u64;
+        self.reroll_modules += run.reroll_modules as u64;
+        self.lock_modules += run.lock_modules as u64;
+        self.slots.resize(want.len(), [0; 3]);
+        for (counts, buff) in self.slots.iter_mut().zip(want) {
+            if let Some(pos) = run.slots.iter().position(|slot| *slot == Some(*buff)) {
+                counts[pos] += 1;
+            }
+        }
+        if run.slots[2].is_some_and(|buff| want.contains(&buff)) {
+            self.third_slot += 1;
+        }
+    }
+
+    // Adds |other|. The results that were saved without outcomes have no runs.
+    fn merge(&mut self, other: &Outcomes) {
+        if self.slots.len() < other.slots.len() {
+            self.slots.resize(other.slots.len(), [0; 3]);
+        }
+        self.runs += other.runs;
+        self.modules += other.modules;
+        self.rerolls += other.rerolls;
+        self.locks += other.locks;
+        self.reroll_modules += other.reroll_modules;
+        self.lock_modules += other.lock_modules;
+        for (counts, other) in self.slots.iter_mut().zip(&other.slots) {
+            for (count, other) in counts.iter_mut().zip(other) {
+                *count += other;
+            }
+        }
+        self.third_slot += other.third_slot;
+    }
+
+    // The mean of |total| per run, or None without runs.
+    fn mean(&self, total: u64) -> Option<f64> {
+        (self.runs > 0).then(|| total as f64 / self.runs as f64)
+    }
+
+    pub fn mean_rerolls(&self) -> Option<f64> {
+        self.mean(self.rerolls)
+    }
+
+    pub fn mean_locks(&self) -> Option<f64> {
+        self.mean(self.locks)
+    }
+
+    pub fn mean_reroll_modules(&self) -> Option<f64> {
+        self.mean(self.reroll_modules)
+    }
+
+    pub fn mean_lock_modules(&self) -> Option<f64> {
+        self.mean(self.lock_modules)
+    }
+
+    /// The share (0 to 1) of the runs in which a wanted buff ended in the third slot.
+    pub fn third_slot_share(&self) -> Option<f64> {
+        self.mean(self.third_slot)
+    }
+
+    /// The shares (0 to 1) of the runs in which each wanted buff ended in each slot.
+    pub fn slot_shares(&self) -> Option<Vec<[f64; 3]>> {
+        (self.runs > 0).then(|| {
+            self.slots
+                .iter()
+                .map(|counts| counts.map(|count| count as f64 / self.runs as f64))
+                .collect()
+        })
     }
 }
 
 pub struct SimulationResult {
-    locked_buff: Option<Buff>,
-    buffs: Vec<Buff>,
-    modules: Vec<u64>,
-    histogram: Histogram<u32>,
+    pub locked_buff: Option<Buff>,
+    pub buffs: Vec<Buff>,
+    // The counts are 64 bit so that the results of long runs can be merged.
+    histogram: Histogram<u64>,
+    /// Empty if only the modules were recorded.
+    pub outcomes: Outcomes,
+}
+
+impl Default for SimulationResult {
+    fn default() -> Self {
+        SimulationResult::new()
+    }
 }
 
 impl SimulationResult {
@@ -56,14 +559,83 @@ impl SimulationResult {
         SimulationResult {
             locked_buff: None,
             buffs: vec![],
-            modules: vec![],
-            histogram: Histogram::<u32>::new(SIGNIFICANT_FIGURES).unwrap(),
+            histogram: Histogram::<u64>::new(SIGNIFICANT_FIGURES).unwrap(),
+            outcomes: Outcomes::default(),
         }
     }
 
     pub fn record(&mut self, data: u32) {
         self.histogram += data as u64;
-        self.modules.push(data as u64);
+    }
+
+    /// Records the modules of |run| and the rest of its outcome.
+    pub fn record_run(&mut self, run: &RunOutcome) {
+        self.record(run.modules);
+        let want = self.wanted();
+        self.outcomes.record(&want, run);
+    }
+
+    /// The wanted buffs, the locked one first.
+    pub fn wanted(&self) -> Vec<Buff> {
+        self.locked_buff
+            .iter()
+            .chain(&self.buffs)
+            .copied()
+            .collect()
+    }
+
+    /// Adds the runs of |other|, e.g. a part that ran on another thread or a saved run. Both
+    /// must be of the same row, i.e. want the same buffs, and either both or neither must have
+    /// the outcomes of their runs, so that the outcomes cover all the samples.
+    pub fn merge(&mut self, other: &SimulationResult) -> Result<(), String> {
+        if self.locked_buff != other.locked_buff || self.buffs != other.buffs {
+            return Err(format!(
+                "cannot merge the results of {:?} {:?} into {:?} {:?}",
+                other.locked_buff, other.buffs, self.locked_buff, self.buffs
+            ));
+        }
+        let runs = self.outcomes.runs + other.outcomes.runs;
+        let samples = self.samples() + other.samples();
+        if runs != 0 && runs != samples {
+            return Err(format!(
+                "cannot merge the results of {:?} {:?}: the outcomes of {runs} runs do not \
+                cover the {samples} samples, e.g. because some were saved without the outcomes",
+                self.locked_buff, self.buffs
+            ));
+        }
+        self.outcomes.merge(&other.outcomes);
+        self.histogram
+            .add(&other.histogram)
+            .map_err(|error| format!("cannot merge the histograms: {error:?}"))
+    }
+
+    /// The histogram in the HdrHistogram V2 format (compressed), as base64.
+    pub fn histogram_base64(&self) -> String {
+        let mut bytes = vec![];
+        V2DeflateSerializer::new()
+            .serialize(&self.histogram, &mut bytes)
+            .unwrap();
+        BASE64.encode(bytes)
+    }
+
+    /// The result with the runs of a histogram from |histogram_base64|.
+    pub fn from_histogram_base64(
+        locked_buff: Option<Buff>,
+        buffs: Vec<Buff>,
+        encoded: &str,
+    ) -> Result<Self, String> {
+        let bytes = BASE64
+            .decode(encoded)
+            .map_err(|error| format!("invalid histogram: {error}"))?;
+        let histogram = Deserializer::new()
+            .deserialize(&mut bytes.as_slice())
+            .map_err(|error| format!("invalid histogram: {error:?}"))?;
+        Ok(SimulationResult {
+            locked_buff,
+            buffs,
+            histogram,
+            outcomes: Outcomes::default(),
+        })
     }
 
     pub fn mean(&self) -> f64 {
@@ -73,6 +645,193 @@ impl SimulationResult {
     pub fn stddev(&self) -> f64 {
         self.histogram.stdev()
     }
+
+    /// The number of modules that |percentile|% of the runs finished within.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        self.histogram.value_at_percentile(percentile)
+    }
+
+    pub fn min(&self) -> u64 {
+        self.histogram.min()
+    }
+
+    pub fn max(&self) -> u64 {
+        self.histogram.max()
+    }
+
+    /// The share of the runs (0 to 1) that finished within each number of modules that was
+    /// recorded, in increasing order of modules.
+    pub fn cdf(&self) -> Vec<(u64, f64)> {
+        let samples = self.samples() as f64;
+        let mut within = 0;
+        self.histogram
+            .iter_recorded()
+            .map(|value| {
+                within += value.count_at_value();
+                (value.value_iterated_to(), within as f64 / samples)
+            })
+            .collect()
+    }
+
+    /// The number of runs that finished within |modules| modules.
+    pub fn runs_within(&self, modules: u64) -> u64 {
+        self.histogram.count_between(0, modules)
+    }
+
+    /// The number of runs recorded.
+    pub fn samples(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    /// Standard error of the mean.
+    pub fn standard_error(&self) -> f64 {
+        stats::mean_standard_error(self.stddev(), self.samples())
+    }
+
+    /// 95% confidence interval of the mean.
+    pub fn confidence_interval(&self) -> Interval {
+        stats::mean_interval(self.mean(), self.stddev(), self.samples(), stats::Z_95)
+    }
+}
+
+impl Estimate for SimulationResult {
+    fn samples(&self) -> u64 {
+        self.samples()
+    }
+
+    fn estimate(&self) -> f64 {
+        self.mean()
+    }
+
+    fn confidence_interval(&self) -> Interval {
+        self.confidence_interval()
+    }
+}
+
+/// The number of rolls that had an outcome (|hits|), out of |attempts| rolls.
+#[derive(Default)]
+pub struct ProportionResult {
+    pub hits: u64,
+    pub attempts: u64,
+}
+
+impl ProportionResult {
+    pub fn new() -> Self {
+        ProportionResult {
+            hits: 0,
+            attempts: 0,
+        }
+    }
+
+    pub fn probability(&self) -> f64 {
+        self.hits as f64 / self.attempts as f64
+    }
+
+    /// Adds the rolls of |other|, e.g. a part that ran on another thread.
+    pub fn merge(&mut self, other: &ProportionResult) {
+        self.hits += other.hits;
+        self.attempts += other.attempts;
+    }
+
+    pub fn standard_error(&self) -> f64 {
+        stats::proportion_standard_error(self.hits, self.attempts)
+    }
+
+    /// 95% Wilson score interval of the probability.
+    pub fn confidence_interval(&self) -> Interval {
+        stats::wilson_interval(self.hits, self.attempts, stats::Z_95)
+    }
+}
+
+impl Estimate for ProportionResult {
+    fn samples(&self) -> u64 {
+        self.attempts
+    }
+
+    fn estimate(&self) -> f64 {
+        self.probability()
+    }
+
+    fn confidence_interval(&self) -> Interval {
+        self.confidence_interval()
+    }
+}
+
+/// A probability estimated by importance sampling: the mean likelihood ratio of |attempts| rolls,
+/// where the rolls without the outcome count as 0.
+#[derive(Default)]
+pub struct WeightedResult {
+    /// The rolls that had the outcome, which are many more than with the real chances.
+    pub hits: u64,
+    pub attempts: u64,
+    /// The sums of the likelihood ratios of the hits, and of their squares.
+    pub weight_sum: f64,
+    pub weight_sum_of_squares: f64,
+}
+
+impl WeightedResult {
+    pub fn new() -> Self {
+        WeightedResult::default()
+    }
+
+    /// Records a roll whose likelihood ratio is |weight| if it had the outcome.
+    pub fn record(&mut self, hit: bool, weight: f64) {
+        self.attempts += 1;
+        if hit {
+            self.hits += 1;
+            self.weight_sum += weight;
+            self.weight_sum_of_squares += weight * weight;
+        }
+    }
+
+    pub fn probability(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.weight_sum / self.attempts as f64
+    }
+
+    /// Adds the rolls of |other|, e.g. a part that ran on another thread.
+    pub fn merge(&mut self, other: &WeightedResult) {
+        self.hits += other.hits;
+        self.attempts += other.attempts;
+        self.weight_sum += other.weight_sum;
+        self.weight_sum_of_squares += other.weight_sum_of_squares;
+    }
+
+    pub fn standard_error(&self) -> f64 {
+        if self.attempts < 2 {
+            return 0.0;
+        }
+        let n = self.attempts as f64;
+        let mean = self.probability();
+        let variance = (self.weight_sum_of_squares / n - mean * mean) * n / (n - 1.0);
+        stats::mean_standard_error(variance.max(0.0).sqrt(), self.attempts)
+    }
+
+    /// 95% normal approximation interval of the probability, which cannot be negative.
+    pub fn confidence_interval(&self) -> Interval {
+        let margin = stats::Z_95 * self.standard_error();
+        let probability = self.probability();
+        Interval {
+            lower: (probability - margin).max(0.0),
+            upper: probability + margin,
+        }
+    }
+}
+
+impl Estimate for WeightedResult {
+    fn samples(&self) -> u64 {
+        self.attempts
+    }
+
+    fn estimate(&self) -> f64 {
+        self.probability()
+    }
+
+    fn confidence_interval(&self) -> Interval {
+        self.confidence_interval()
+    }
 }
 
 // This is more of a check/test than a simluation.
@@ -80,12 +839,12 @@ impl SimulationResult {
 // Only one (which is the first slot)  populated 35%.
 // Any two slots populated is 50%.
 // All two slots populated is 15%.
-pub fn simulation_slots_shown_distribution() {
-    let attempts = 100000;
+pub fn simulation_slots_shown_distribution(config: &SuiteConfig, attempts: u64) -> SuiteReport {
+    let mut rng = StdRng::seed_from_u64(config.seed("slots shown distribution"));
 
     let mut tally = [0, 0, 0];
     for _ in 0..attempts {
-        let mut sim = Simulation::new();
+        let mut sim = Simulation::with_rules(config.rules.clone(), &mut rng);
         sim.reroll();
         let num_buffs = sim
             .buffs()
@@ -98,91 +857,274 @@ pub fn simulation_slots_shown_distribution() {
         tally[num_buffs - 1] += 1;
     }
 
-    println!("Tally of buff nums! {:?}", tally);
-    let sum: usize = tally.iter().sum();
-
-    let one = tally[0] as f64 / sum as f64 * 100.0;
-    let two = tally[1] as f64 / sum as f64 * 100.0;
-    let three = tally[2] as f64 / sum as f64 * 100.0;
-
-    println!("Percentage of buffs {:.2}% {:.2}% {:.2}%", one, two, three);
+    let expected = |count: usize| {
+        let probability = importance::exact_probability(&config.rules, |slots| {
+            slots.iter().flatten().count() == count
+        });
+        (probability * 10000.0).round() / 100.0
+    };
+    let mut report = SuiteReport::new(
+        "slots shown distribution",
+        &format!(
+            "Sanity check of how many slots get a buff on a roll. \
+        Expected: one slot {}%, two slots {}%, three slots {}%.",
+            expected(1),
+            expected(2),
+            expected(3)
+        ),
+        &["populated slots"],
+    );
+    for (i, count) in tally.into_iter().enumerate() {
+        report.rows.push(ReportRow {
+            labels: vec![(i + 1).to_string()],
+            result: RowResult::Probability(ProportionResult {
+                hits: count,
+                attempts,
+            }),
+        });
+    }
+    report
 }
 
 // The number of attempts required to get all the wanted buffs, without locking.
 // For example if you want Attack and ChargeSpeed. This simulation checks how many rolls
 // got you all the buffs that you want. Divide that number by the total attempts should get us
 // the probability.
-fn sim_want_buffs(want: &[Buff]) {
-    let want: HashSet<Buff> = HashSet::from_iter(want.iter().copied());
-    let attempts = DEFAULT_ATTEMPTS;
-
-    let mut hits = 0;
-    for _ in 0..attempts {
-        let mut sim = Simulation::new();
-        sim.reroll();
-        let mut found = HashSet::new();
-        for b in want.iter() {
-            if sim.has_buff(b) {
-                found.insert(*b);
+fn sim_want_buffs(
+    want: &[Buff],
+    rules: &Arc<Rules>,
+    rule: &StopRule,
+    seed: u64,
+) -> ProportionResult {
+    let mut result = ProportionResult::new();
+    let want = BuffSet::from_iter(want.iter().copied());
+
+    rule.run(
+        &mut result,
+        |chunk, samples| {
+            let mut rng = sampling::chunk_rng(seed, chunk);
+            let mut part = ProportionResult::new();
+            for _ in 0..samples {
+                part.attempts += 1;
+                let mut sim = Simulation::with_rules(rules.clone(), &mut rng);
+                sim.reroll();
+                if sim.shown_buffs().is_superset(want) {
+                    part.hits += 1;
+                }
             }
+            part
+        },
+        |result, part| result.merge(&part),
+    );
+
+    result
+}
+
+// A suite of simluations.
+// The buffs appear 10% or 12% of the time. This suite runs a simulation for every pattern of
+// chances of two and three buffs, e.g. 10% and 10%, 10% and 12%, ..., 12%, 12% and 12%, see
+// coverage.rs.
+pub fn suite_desired_buff_percent(config: &SuiteConfig, coverage: Coverage) -> SuiteReport {
+    let rule = &config.probability_stop;
+    let mut report = SuiteReport::new(
+        "desired buffs",
+        &format!(
+            "The following tests report how likely (probability) two or more desired buffs \
+        appear. Each row counts the rerolls that had all the buffs. \
+        The confidence intervals are Wilson score intervals. {}",
+            rule.describe("")
+        ),
+        &["buffs"],
+    );
+
+    let wants = [2, 3]
+        .into_iter()
+        .flat_map(|size| coverage::cases(&config.rules, size, false, coverage))
+        .map(|case| case.want());
+
+    let mut left_out = false;
+    for want in wants {
+        if Strategy::NoLocking.check(&config.rules, &want).is_err() {
+            left_out = true;
+            continue;
         }
+        report.rows.push(ReportRow {
+            labels: vec![buffs_to_string(&config.rules, want.iter())],
+            result: RowResult::Probability(sim_want_buffs(
+                &want,
+                &config.rules,
+                rule,
+                config.seed(&format!("desired buffs/{want:?}")),
+            )),
+        });
+    }
+    note_left_out(&mut report, left_out);
+    report
+}
 
-        if found.eq(&want) {
-            hits += 1;
+/// A rare outcome of a roll.
+#[derive(Clone, Copy, Debug)]
+pub enum RareOutcome {
+    /// The buffs are shown, in any slots.
+    Shown([Buff; 3]),
+    /// The buffs are in the slots in order.
+    InSlots([Buff; 3]),
+}
+
+impl RareOutcome {
+    pub fn want(&self) -> BuffSet {
+        match self {
+            RareOutcome::Shown(buffs) | RareOutcome::InSlots(buffs) => BuffSet::from_iter(*buffs),
+        }
+    }
+
+    /// Whether a roll with the buffs in |slots| had the outcome.
+    pub fn happened(&self, slots: [Option<Buff>; 3]) -> bool {
+        match self {
+            RareOutcome::Shown(_) => slots
+                .into_iter()
+                .flatten()
+                .collect::<BuffSet>()
+                .is_superset(self.want()),
+            RareOutcome::InSlots(buffs) => slots == buffs.map(Some),
+        }
+    }
+
+    fn label(&self, rules: &Rules) -> String {
+        match self {
+            RareOutcome::Shown(buffs) => buffs_to_string(rules, buffs.iter()),
+            RareOutcome::InSlots(buffs) => {
+                format!("{} in slots 1, 2, 3", buffs_to_string(rules, buffs.iter()))
+            }
         }
     }
+}
+
+// The buffs in the slots after a roll.
+fn slots<R: Rng>(sim: &Simulation<R>) -> [Option<Buff>; 3] {
+    std::array::from_fn(|pos| match sim.buffs()[pos] {
+        SlotState::Free(buff) | SlotState::Locked(buff) => Some(buff),
+        SlotState::None => None,
+    })
+}
 
-    println!(
-        "To get buffs {}. \
-        The simulation ran {attempts} rerolls, which {hits} had \
-        all the buffs. This is about {:.2}%.",
-        buffs_to_string(want.iter()),
-        hits as f64 / attempts as f64 * 100.0
+// The probability of |outcome| on a roll, by importance sampling with the proposal for its buffs.
+fn sim_rare_outcome(
+    outcome: RareOutcome,
+    rules: &Arc<Rules>,
+    rule: &StopRule,
+    seed: u64,
+) -> WeightedResult {
+    let mut result = WeightedResult::new();
+    let proposal = Proposal::new(rules, outcome.want());
+
+    rule.run(
+        &mut result,
+        |chunk, samples| {
+            let mut rng = sampling::chunk_rng(seed, chunk);
+            let mut part = WeightedResult::new();
+            for _ in 0..samples {
+                let mut sim = Simulation::with_rules(rules.clone(), &mut rng);
+                sim.sample_from(proposal);
+                sim.reroll();
+                part.record(outcome.happened(slots(&sim)), sim.likelihood_ratio());
+            }
+            part
+        },
+        |result, part| result.merge(&part),
     );
+
+    result
 }
 
-// A suite of simluations.
-// There are two types of buffs, those that appear 10% of the time and those that appear 12%.
-// This suite runs a few simluations.
-// Simulation that aims for 10% and 10% buffs.
-// Simulation that aims for 10% and 12% buffs.
-// Simulation that aims for 12% and 12% buffs.
-pub fn suite_desired_buff_percent() {
-    let _suite_print = SuitePrint::new(
-        "desired buffs",
-        "The following tests report how likely (probability) two or more desired buffs appear.",
+// The rolls with three wanted buffs are rare, so plain sampling needs millions of rolls for a
+// precise probability. This suite samples them from a proposal that shows the wanted buffs much
+// more often, and weights them back to the real chances.
+pub fn suite_rare_buffs(config: &SuiteConfig) -> SuiteReport {
+    let rule = &config.probability_stop;
+    let mut report = SuiteReport::new(
+        "rare buffs (importance sampling)",
+        &format!(
+            "The following tests report how likely (probability) rare rolls are, estimated by \
+        importance sampling: the rolls favour the wanted buffs and the extra slots, and are \
+        weighted by how much more likely they are with the real chances. The hits are the \
+        rolls of the proposal that had the buffs. The estimates are unbiased and the \
+        confidence intervals are normal intervals of the weighted mean. The exact \
+        probabilities are computed by going through all the rolls. {}",
+            rule.describe("")
+        ),
+        &["buffs", "exact"],
+    );
+
+    // Every pattern of chances of three buffs, and the first of them in order.
+    let shown: Vec<[Buff; 3]> = coverage::cases(&config.rules, 3, false, Coverage::Representative)
+        .into_iter()
+        .map(|case| case.want().try_into().unwrap())
+        .collect();
+    let outcomes = shown
+        .iter()
+        .map(|buffs| RareOutcome::Shown(*buffs))
+        .chain([RareOutcome::InSlots(shown[0])]);
+
+    let mut left_out = false;
+    for outcome in outcomes {
+        if config.rules.check_shown([None; 3], outcome.want()).is_err() {
+            left_out = true;
+            continue;
+        }
+        let exact = importance::exact_probability(&config.rules, |slots| outcome.happened(slots));
+        report.rows.push(ReportRow {
+            labels: vec![
+                outcome.label(&config.rules),
+                format!("{:.4}%", exact * 100.0),
+            ],
+            result: RowResult::Weighted(sim_rare_outcome(
+                outcome,
+                &config.rules,
+                rule,
+                config.seed(&format!("rare buffs/{outcome:?}")),
+            )),
+        });
+    }
+    note_left_out(&mut report, left_out);
+    report
+}
+
+/// How likely a roll shows all of |want|, e.g. for buffs picked on the command line. The row is
+/// seeded like the rows of the desired buffs suite.
+pub fn suite_want_probability(config: &SuiteConfig, want: &[Buff]) -> SuiteReport {
+    let rule = &config.probability_stop;
+    let mut report = SuiteReport::new(
+        "probability",
+        &format!(
+            "How likely (probability) a roll shows all the buffs. \
+        The confidence interval is a Wilson score interval. {}",
+            rule.describe("")
+        ),
+        &["buffs"],
     );
-    // 10%, 10%
-    sim_want_buffs(&[Buff::Attack, Buff::Elemental]);
-    // 10%, 12%
-    sim_want_buffs(&[Buff::Attack, Buff::MaxAmmo]);
-    // 12%, 12%
-    sim_want_buffs(&[Buff::ChargeDamage, Buff::ChargeSpeed]);
-
-    // 10%, 10%, 10%
-    sim_want_buffs(&[Buff::Attack, Buff::Elemental, Buff::CritDamage]);
-    // 10%, 10%, 12%
-    sim_want_buffs(&[Buff::Attack, Buff::Elemental, Buff::ChargeSpeed]);
-    // 10%, 12%, 12%
-    sim_want_buffs(&[Buff::Attack, Buff::MaxAmmo, Buff::ChargeSpeed]);
-    // 12%, 12%, 12%
-    sim_want_buffs(&[Buff::ChargeDamage, Buff::MaxAmmo, Buff::ChargeSpeed]);
+    if Strategy::NoLocking.check(&config.rules, want).is_err() {
+        note_left_out(&mut report, true);
+        return report;
+    }
+    report.rows.push(ReportRow {
+        labels: vec![buffs_to_string(&config.rules, want.iter())],
+        result: RowResult::Probability(sim_want_buffs(
+            want,
+            &config.rules,
+            rule,
+            config.seed(&format!("desired buffs/{want:?}")),
+        )),
+    });
+    report
 }
 
 // Rerolls without locking. Rerolls until all the buffs within |want| is
 // rolled.
-fn reroll_until_all_found(sim: &mut Simulation, want: &HashSet<Buff>) {
+fn reroll_until_all_found<R: Rng>(sim: &mut Simulation<R>, want: BuffSet) {
     loop {
         sim.reroll();
-        let mut found = HashSet::new();
-
-        for b in want.iter() {
-            if sim.has_buff(b) {
-                found.insert(b.clone());
-            }
-        }
-
-        if want.eq(&found) {
+        if sim.shown_buffs().is_superset(want) {
             break;
         }
     }
@@ -190,424 +1132,402 @@ fn reroll_until_all_found(sim: &mut Simulation, want: &HashSet<Buff>) {
 
 // Rerolls with locking. Rerolls until all the buffs within |want| is
 // rolled. If it rolls a wanted buff, it locks immediately.
-fn reroll_until_all_found_with_locking(sim: &mut Simulation, want: &HashSet<Buff>) {
+fn reroll_until_all_found_with_locking<R: Rng>(sim: &mut Simulation<R>, want: BuffSet) {
     loop {
         sim.reroll();
-        let mut found = HashSet::new();
+        if lock_found(sim, want) {
+            break;
+        }
+    }
+}
+
+// Locks the buffs of |want| that are shown, and tells whether all of them are.
+fn lock_found<R: Rng>(sim: &mut Simulation<R>, want: BuffSet) -> bool {
+    let found = sim.shown_buffs().intersection(want);
 
-        for b in want.iter() {
-            if sim.has_buff(b) {
-                let pos = sim.position_of(b).unwrap();
-                sim.lock(pos);
-                found.insert(*b);
+    for b in found.iter() {
+        let pos = sim.position_of(&b).unwrap();
+        sim.lock(pos);
+    }
+
+    found == want
+}
+
+// Rerolls without locking until |buff| is shown in the slot at |position|, and returns the custom
+// modules used.
+fn reroll_until_shown_in<R: Rng>(sim: &mut Simulation<R>, buff: Buff, position: usize) -> u32 {
+    loop {
+        sim.reroll();
+        if sim.buffs()[position] == SlotState::Free(buff) {
+            return sim.custom_modules();
+        }
+    }
+}
+
+// Locks |buff|, shown in the slot at |position|, and the buffs of |want| shown with it, then
+// rerolls with locking until all of |want| are shown.
+fn lock_shown_then_reroll<R: Rng>(sim: &mut Simulation<R>, position: usize, want: BuffSet) {
+    sim.lock(position);
+    if !lock_found(sim, want) {
+        reroll_until_all_found_with_locking(sim, want);
+    }
+}
+
+/// A way of rerolling until all the wanted buffs are shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Never lock.
+    NoLocking,
+    /// Lock every wanted buff as soon as it appears.
+    LockImmediately,
+    /// The buff is locked in the slot (0 based) after the first roll, then the other wanted buffs
+    /// are locked as soon as they appear.
+    Locked(Buff, usize),
+    /// Reroll without locking until the buff appears in the slot (0 based) and lock it, then lock
+    /// the other wanted buffs as soon as they appear. Unlike |Locked|, the rolls spent waiting for
+    /// the buff are accounted.
+    Waited(Buff, usize),
+}
+
+impl Strategy {
+    pub fn name(&self) -> String {
+        match self {
+            Strategy::NoLocking => "no locking".to_string(),
+            Strategy::LockImmediately => "lock immediately".to_string(),
+            Strategy::Locked(buff, position) => {
+                format!("{buff:?} locked in slot {}", position + 1)
+            }
+            Strategy::Waited(buff, position) => {
+                format!("{buff:?} waited for in slot {}", position + 1)
             }
         }
+    }
 
-        if want.eq(&found) {
-            break;
+    /// Whether the strategy locks buffs, i.e. pays for locking.
+    pub fn locks(&self) -> bool {
+        *self != Strategy::NoLocking
+    }
+
+    /// Checks that the strategy gets all of |want| with |rules|, instead of rerolling forever. The
+    /// buff of a locked or waited strategy is one of |want|, and is kept only if it can be locked.
+    pub fn check(&self, rules: &Rules, want: &[Buff]) -> Result<(), String> {
+        let want = BuffSet::from_iter(want.iter().copied());
+        match *self {
+            Strategy::NoLocking | Strategy::LockImmediately => rules.check_shown([None; 3], want),
+            Strategy::Locked(buff, position) | Strategy::Waited(buff, position) => {
+                if rules.slot_percent(position) == 0.0 {
+                    return Err(format!("slot {} never gets a buff", position + 1));
+                }
+                let mut locks = [None; 3];
+                if rules.lock_cost(0).is_some() {
+                    locks[position] = Some(buff);
+                }
+                let rest = want.iter().filter(|b| *b != buff).collect();
+                rules.check_shown(locks, rest)
+            }
+        }
+    }
+
+    /// Rerolls |sim| until all of |want| are shown, and returns the custom modules used.
+    pub fn run<R: Rng>(&self, sim: &mut Simulation<R>, want: BuffSet) -> u32 {
+        match *self {
+            Strategy::NoLocking => reroll_until_all_found(sim, want),
+            Strategy::LockImmediately => reroll_until_all_found_with_locking(sim, want),
+            Strategy::Locked(buff, position) => {
+                // Rolling first so that it uses a module.
+                sim.reroll();
+                sim.set_buff(position, &buff);
+                sim.lock(position);
+
+                reroll_until_all_found_with_locking(sim, want);
+            }
+            Strategy::Waited(buff, position) => {
+                reroll_until_shown_in(sim, buff, position);
+                lock_shown_then_reroll(sim, position, want);
+            }
         }
+        sim.custom_modules()
     }
 }
 
+/// The custom modules that |strategy| uses to get |want|, e.g. for buffs picked on the command
+/// line. The buff of a locked strategy is one of |want|.
+pub fn suite_strategy_cost(config: &SuiteConfig, want: &[Buff], strategy: Strategy) -> SuiteReport {
+    let mut report = SuiteReport::new(
+        &format!("custom module usage, {}", strategy.name()),
+        &format!(
+            "The following table shows how many custom modules were used to get the buffs \
+        with the strategy. {} {}",
+            lock_cost_note(&config.rules),
+            config.modules_stop.describe("modules")
+        ),
+        &["buffs"],
+    );
+    let sampler = config.sampler(report.name.as_str());
+    let result = simulate_strategy(strategy, want, &sampler);
+    report.rows.push(ReportRow {
+        labels: vec![buffs_to_string(&config.rules, want.iter())],
+        result: RowResult::Modules(result),
+    });
+    report
+}
+
+/// The custom modules that |strategy| uses to get |want|. The buff of a locked or waited strategy
+/// is one of |want|, and the row is seeded like the rows of the suites that lock it.
+pub fn simulate_strategy(strategy: Strategy, want: &[Buff], sampler: &Sampler) -> SimulationResult {
+    match strategy {
+        Strategy::NoLocking => simulation_num_custom_modules_for_specific_buffs(want, sampler),
+        Strategy::LockImmediately => simulation_num_cus_mods_with_locking(want, sampler),
+        Strategy::Locked(buff, position) => {
+            let rest: Vec<Buff> = want.iter().copied().filter(|b| *b != buff).collect();
+            simulation_with_locked_buff(buff, position, &rest, sampler)
+        }
+        Strategy::Waited(buff, position) => {
+            let rest: Vec<Buff> = want.iter().copied().filter(|b| *b != buff).collect();
+            simulation_with_waited_buff(buff, position, &rest, sampler)
+        }
+    }
+}
+
+/// The custom modules used to get |want| given that |buff|, one of |want|, has just appeared in the
+/// slot at |position| (0 based) on the |roll|th roll, without locking before. The first row counts
+/// the modules from then on, locking |buff| and the other wanted buffs as soon as they appear, and
+/// the second adds the modules of the rolls so far. The third row waits from the start for
+/// comparison.
+pub fn suite_shown_in_slot(
+    config: &SuiteConfig,
+    want: &[Buff],
+    buff: Buff,
+    position: usize,
+    roll: u32,
+) -> SuiteReport {
+    let spent = roll * config.rules.reroll_cost(0).unwrap();
+    let mut report = SuiteReport::new(
+        &format!(
+            "custom module usage, {buff:?} shown in slot {}",
+            position + 1
+        ),
+        &format!(
+            "The following table shows how many custom modules are used to get the buffs given \
+        that {buff:?} has just appeared in slot {} on roll {roll}, without locking before \
+        ({spent} modules spent). It is locked, then the other buffs are locked as soon as they \
+        appear. {} How long it took to appear does not change the modules still needed, the rolls \
+        so far only add up. The last row waits for {buff:?} in the slot from the start instead. \
+        {}",
+            position + 1,
+            lock_cost_note(&config.rules),
+            config.modules_stop.describe("modules")
+        ),
+        &["buffs", "modules counted"],
+    );
+    let sampler = config.sampler(report.name.as_str());
+    let rest: Vec<Buff> = want.iter().copied().filter(|b| *b != buff).collect();
+    for (label, rolls) in [
+        (format!("after roll {roll}"), Some(0)),
+        (format!("from the start, shown on roll {roll}"), Some(roll)),
+        ("waiting from the start".to_string(), None),
+    ] {
+        let result = match rolls {
+            Some(rolls) => simulation_after_shown(buff, position, &rest, rolls, &sampler),
+            None => simulate_strategy(Strategy::Waited(buff, position), want, &sampler),
+        };
+        report.rows.push(ReportRow {
+            labels: vec![buffs_to_string(&config.rules, want.iter()), label],
+            result: RowResult::Modules(result),
+        });
+    }
+    report
+}
+
+// The custom modules used to get |want_rest| after |locked_buff| appeared in the slot at
+// |position|, plus those of the |rolls| before without locking, that one included. The rolls are
+// drawn until |locked_buff| appears in the slot, so that the rest of the roll is drawn given it,
+// and then counted as |rolls| instead.
+fn simulation_after_shown(
+    locked_buff: Buff,
+    position: usize,
+    want_rest: &[Buff],
+    rolls: u32,
+    sampler: &Sampler,
+) -> SimulationResult {
+    let mut result = SimulationResult::new();
+    result.buffs.extend(want_rest.iter());
+    result.locked_buff = Some(locked_buff);
+
+    let want = BuffSet::from_iter(want_rest.iter().copied());
+
+    sampler.run(&mut result, |sim| {
+        reroll_until_shown_in(sim, locked_buff, position);
+        sim.restart_counts(rolls);
+        lock_shown_then_reroll(sim, position, want);
+        sim.custom_modules()
+    });
+
+    result
+}
+
+// The wanted buffs of each case of |size| buffs that |strategy| gets with the rules of |config|,
+// and whether any were left out.
+fn reachable(
+    config: &SuiteConfig,
+    strategy: Strategy,
+    size: usize,
+    coverage: Coverage,
+) -> (Vec<Vec<Buff>>, bool) {
+    let (wants, left_out): (Vec<_>, Vec<_>) = coverage::cases(&config.rules, size, false, coverage)
+        .into_iter()
+        .map(|case| case.want())
+        .partition(|want| strategy.check(&config.rules, want).is_ok());
+    (wants, !left_out.is_empty())
+}
+
 // Simulation to see how many custom modules are required to get all buffs without locking.
 // For example if you want Attack and ChargeSpeed. This simulation checks how many custom modules
 // are used to got you all the buffs that you want.
 // This runs the process multiple times and gets the average number of modules required, without
 // locking.
-fn simulation_num_custom_modules_for_specific_buffs(want: &[Buff]) -> SimulationResult {
+fn simulation_num_custom_modules_for_specific_buffs(
+    want: &[Buff],
+    sampler: &Sampler,
+) -> SimulationResult {
     let mut result = SimulationResult::new();
     result.buffs.extend(want.iter());
 
-    let want: HashSet<Buff> = HashSet::from_iter(want.iter().copied());
+    let want = BuffSet::from_iter(want.iter().copied());
 
-    for _ in 0..DEFAULT_ATTEMPTS {
-        let mut sim = Simulation::new();
-        reroll_until_all_found(&mut sim, &want);
-        result.record(sim.custom_modules);
-    }
+    sampler.run(&mut result, |sim| Strategy::NoLocking.run(sim, want));
 
     result
 }
 
-// See SuitePrint below for description.
-pub fn suite_two_desired_buffs_custom_mod_usage() {
-    let _suite_print = SuitePrint::new(
+// See the report description below.
+pub fn suite_two_desired_buffs_custom_mod_usage(
+    config: &SuiteConfig,
+    coverage: Coverage,
+) -> SuiteReport {
+    let mut report = SuiteReport::new(
         "two desired buffs custom mod usage.",
-        format!(
+        &format!(
             "The following table shows how many custom modules \
         were used to get the listed buffs. The simulations ran until there were rolls that got all
-        the listed buffs for {} times. \
-        None of the buffs are locked during the process.",
-            DEFAULT_ATTEMPTS
-        )
-        .as_str(),
+        the listed buffs. None of the buffs are locked during the process. {}",
+            config.modules_stop.describe("modules")
+        ),
+        &["buff1", "buff2"],
     );
+    let sampler = config.sampler(report.name.as_str());
 
-    let mut data = vec![vec![
-        "buff1".to_string(),
-        "buff2".to_string(),
-        "mean (modules)".to_string(),
-        "std dev (modules)".to_string(),
-    ]];
-
-    let results = [
-        simulation_num_custom_modules_for_specific_buffs(&[Buff::Attack, Buff::Elemental]),
-        simulation_num_custom_modules_for_specific_buffs(&[Buff::Attack, Buff::MaxAmmo]),
-        simulation_num_custom_modules_for_specific_buffs(&[Buff::ChargeDamage, Buff::ChargeSpeed]),
-    ];
+    // Every pattern of chances of two buffs.
+    let (cases, left_out) = reachable(config, Strategy::NoLocking, 2, coverage);
+    let results = cases
+        .iter()
+        .map(|want| simulation_num_custom_modules_for_specific_buffs(want, &sampler));
 
     for result in results {
-        data.push(vec![
-            buff_to_string(result.buffs[0]),
-            buff_to_string(result.buffs[1]),
-            format!("{:.3}", result.mean()),
-            format!("{:.3}", result.stddev()),
-        ]);
+        let labels = vec![
+            buff_to_string(&config.rules, result.buffs[0]),
+            buff_to_string(&config.rules, result.buffs[1]),
+        ];
+        report.rows.push(ReportRow {
+            labels,
+            result: RowResult::Modules(result),
+        });
     }
 
-    let mut table = Builder::from(data).build();
-    table.with(Style::modern());
-    println!("{table}");
+    note_left_out(&mut report, left_out);
+    report
 }
 
 // Simulate to see how many custom modules are required to get a specific set of buffs, with locking.
-pub fn simulation_num_cus_mods_with_locking(want: &[Buff]) -> SimulationResult {
+pub fn simulation_num_cus_mods_with_locking(want: &[Buff], sampler: &Sampler) -> SimulationResult {
     let mut result = SimulationResult::new();
     result.buffs.extend(want.iter());
 
-    let want: HashSet<Buff> = HashSet::from_iter(want.iter().copied());
-    let attempts = 100000;
+    let want = BuffSet::from_iter(want.iter().copied());
 
-    for _ in 0..attempts {
-        let mut sim = Simulation::new();
-        reroll_until_all_found_with_locking(&mut sim, &want);
-        result.record(sim.custom_modules);
-    }
+    sampler.run(&mut result, |sim| Strategy::LockImmediately.run(sim, want));
     result
 }
 
-pub fn suite_two_desired_buffs_custom_mod_usage_with_locking() {
-    let _suite_print = SuitePrint::new(
+pub fn suite_two_desired_buffs_custom_mod_usage_with_locking(
+    config: &SuiteConfig,
+    coverage: Coverage,
+) -> SuiteReport {
+    let mut report = SuiteReport::new(
         "With locking: two desired buffs custom mod usage.",
-        "The following tests report how many custom modules \
+        &format!(
+            "The following tests report how many custom modules \
         were used to get the buffs. When a desired buff appears, they are immediately locked. \
-        The cost of locking a module (2+) is accounted.",
-    );
-
-    let mut data = vec![vec![
-        "buff1".to_string(),
-        "buff2".to_string(),
-        "mean (modules)".to_string(),
-        "std dev (modules)".to_string(),
-    ]];
-
-    let results = [
-        simulation_num_cus_mods_with_locking(&[Buff::Attack, Buff::Elemental]),
-        simulation_num_cus_mods_with_locking(&[Buff::Attack, Buff::MaxAmmo]),
-        simulation_num_cus_mods_with_locking(&[Buff::ChargeDamage, Buff::ChargeSpeed]),
-    ];
-
-    for result in results {
-        data.push(vec![
-            buffs_to_string([result.buffs[0]].iter()),
-            buffs_to_string([result.buffs[1]].iter()),
-            format!("{:.3}", result.mean()),
-            format!("{:.3}", result.stddev()),
-        ]);
-    }
-
-    let mut table = Builder::from(data).build();
-    table.with(Style::modern());
-    println!("{table}");
-}
-
-/// Find custom module usage given that a desired buff is locked on the first slot.
-///
-/// #Arguments
-/// * `locked_buff` - Desired buff. The simulation runs given that this buff is locked.
-/// * `want_rest` - The list wanted buffs. It is safe to exclude the buff specified in
-///                `locked_buff`.
-pub fn simulation_first_desired_buff_locked(
-    locked_buff: Buff,
-    want_rest: &[Buff],
-) -> SimulationResult {
-    simulation_with_locked_buff(locked_buff, 0, want_rest)
-}
-
-pub fn suite_simulation_first_slot_buff_locked() {
-    let _suite_print = SuitePrint::new(
-        "First slot buff locked",
-        format!(
-            "The following table shows how many custom modules \
-        were used to get the desired buffs. \
-        Given that the FIRST slot has been locked after the first roll \
-        (1 for roll + 2 for locking), \
-        whenever a desired buff appears on a reroll, it is immediately locked. \
-        The cost of locking modules (2+) is accounted. \
-        The simluation rerolls until all the preferred buffs are drawn. And each row shows the \
-        statistics on the number of custom modules used until the preferred buffs are drawn \
-        {} times.",
-            DEFAULT_ATTEMPTS
-        )
-        .as_str(),
+        {} {}",
+            lock_cost_note(&config.rules),
+            config.modules_stop.describe("modules")
+        ),
+        &["buff1", "buff2"],
     );
+    let sampler = config.sampler(report.name.as_str());
 
-    let mut data = vec![vec![
-        "locked buff (slot 1)".to_string(),
-        "buff1".to_string(),
-        "buff2".to_string(),
-        "mean (modules)".to_string(),
-        "std dev (modules)".to_string(),
-    ]];
-
-    // Two buffs.
-    let results = [
-        simulation_first_desired_buff_locked(Buff::Attack, &[Buff::Elemental]),
-        simulation_first_desired_buff_locked(Buff::Attack, &[Buff::MaxAmmo]),
-        simulation_first_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack]),
-        simulation_first_desired_buff_locked(Buff::ChargeDamage, &[Buff::ChargeSpeed]),
-        // Three buffs.
-        // 10%, [10%, 10%].
-        simulation_first_desired_buff_locked(Buff::Attack, &[Buff::Elemental, Buff::CritDamage]),
-        // 10%, [10%, 12%].
-        simulation_first_desired_buff_locked(Buff::Attack, &[Buff::Elemental, Buff::MaxAmmo]),
-        // 10%, [12%, 12%].
-        simulation_first_desired_buff_locked(Buff::Attack, &[Buff::MaxAmmo, Buff::CritDamage]),
-        // 12%, [10%, 10%].
-        simulation_first_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack, Buff::Elemental]),
-        // 12%, [10%, 12%].
-        simulation_first_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack, Buff::MaxAmmo]),
-        // 12%, [12%, 12%].
-        simulation_first_desired_buff_locked(
-            Buff::ChargeDamage,
-            &[Buff::MaxAmmo, Buff::ChargeSpeed],
-        ),
-    ];
+    let (cases, left_out) = reachable(config, Strategy::LockImmediately, 2, coverage);
+    let results = cases
+        .iter()
+        .map(|want| simulation_num_cus_mods_with_locking(want, &sampler));
 
     for result in results {
-        let second_buff = if result.buffs.len() < 2 {
-            "N/A".to_string()
-        } else {
-            buff_to_string(result.buffs[1])
-        };
-
-        data.push(vec![
-            buffs_to_string([result.locked_buff.unwrap()].iter()),
-            buffs_to_string([result.buffs[0]].iter()),
-            second_buff,
-            format!("{:.3}", result.mean()),
-            format!("{:.3}", result.stddev()),
-        ]);
+        let labels = vec![
+            buff_to_string(&config.rules, result.buffs[0]),
+            buff_to_string(&config.rules, result.buffs[1]),
+        ];
+        report.rows.push(ReportRow {
+            labels,
+            result: RowResult::Modules(result),
+        });
     }
 
-    let mut table = Builder::from(data).build();
-    table.with(Style::modern());
-    println!("{table}");
+    note_left_out(&mut report, left_out);
+    report
 }
 
-// Find custom module usage given that a desired buff is locked on the second slot.
-pub fn simulation_second_desired_buff_locked(
+pub fn simulation_with_locked_buff(
     locked_buff: Buff,
+    position: usize,
     want_rest: &[Buff],
+    sampler: &Sampler,
 ) -> SimulationResult {
-    simulation_with_locked_buff(locked_buff, 1, want_rest)
-}
-
-pub fn suite_simulation_second_slot_buff_locked() {
-    let _suite_print = SuitePrint::new(
-        "Second slot buff locked",
-        format!(
-            "The following table shows how many custom modules \
-        were used to get the desired buffs. \
-        Given that the SECOND slot has been locked after the first roll \
-        (1 for roll + 2 for locking), \
-        whenever a desired buff appears on a reroll, it is immediately locked. \
-        The cost of locking modules (2+) is accounted. \
-        The simluation rerolls until all the preferred buffs are drawn. And each row shows the \
-        statistics on the number of custom modules used until the preferred buffs are drawn \
-        {} times.",
-            DEFAULT_ATTEMPTS
-        )
-        .as_str(),
-    );
-
-    let mut data = vec![vec![
-        "locked buff (slot 2)".to_string(),
-        "buff1".to_string(),
-        "buff2".to_string(),
-        "mean (modules)".to_string(),
-        "std dev (modules)".to_string(),
-    ]];
-
-    let results = [
-        // Two buffs.
-        simulation_second_desired_buff_locked(Buff::Attack, &[Buff::Elemental]),
-        simulation_second_desired_buff_locked(Buff::Attack, &[Buff::MaxAmmo]),
-        simulation_second_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack]),
-        simulation_second_desired_buff_locked(Buff::ChargeDamage, &[Buff::ChargeSpeed]),
-        // Three buffs.
-        // 10%, [10%, 10%].
-        simulation_second_desired_buff_locked(Buff::Attack, &[Buff::Elemental, Buff::CritDamage]),
-        // 10%, [10%, 12%].
-        simulation_second_desired_buff_locked(Buff::Attack, &[Buff::Elemental, Buff::MaxAmmo]),
-        // 10%, [12%, 12%].
-        simulation_second_desired_buff_locked(Buff::Attack, &[Buff::MaxAmmo, Buff::CritDamage]),
-        // 12%, [10%, 10%].
-        simulation_second_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack, Buff::Elemental]),
-        // 12%, [10%, 12%].
-        simulation_second_desired_buff_locked(Buff::ChargeDamage, &[Buff::Attack, Buff::MaxAmmo]),
-        // 12%, [12%, 12%].
-        simulation_second_desired_buff_locked(
-            Buff::ChargeDamage,
-            &[Buff::MaxAmmo, Buff::ChargeSpeed],
-        ),
-    ];
+    let mut result = SimulationResult::new();
+    result.buffs.extend(want_rest.iter());
+    result.locked_buff = Some(locked_buff);
 
-    for result in results {
-        let second_buff = if result.buffs.len() < 2 {
-            "N/A".to_string()
-        } else {
-            buff_to_string(result.buffs[1])
-        };
+    let want = BuffSet::from_iter(want_rest.iter().copied());
 
-        data.push(vec![
-            buffs_to_string([result.locked_buff.unwrap()].iter()),
-            buffs_to_string([result.buffs[0]].iter()),
-            second_buff,
-            format!("{:.3}", result.mean()),
-            format!("{:.3}", result.stddev()),
-        ]);
-    }
+    let strategy = Strategy::Locked(locked_buff, position);
+    sampler.run(&mut result, |sim| strategy.run(sim, want));
 
-    let mut table = Builder::from(data).build();
-    table.with(Style::modern());
-    println!("{table}");
+    result
 }
 
-pub fn simulation_with_locked_buff(
+// Like |simulation_with_locked_buff|, but the rolls until |locked_buff| appears in the slot at
+// |position| are accounted.
+pub fn simulation_with_waited_buff(
     locked_buff: Buff,
     position: usize,
     want_rest: &[Buff],
+    sampler: &Sampler,
 ) -> SimulationResult {
     let mut result = SimulationResult::new();
     result.buffs.extend(want_rest.iter());
     result.locked_buff = Some(locked_buff);
 
-    let want: HashSet<Buff> = HashSet::from_iter(want_rest.iter().copied());
-    let attempts = DEFAULT_ATTEMPTS;
-
-    for _ in 0..attempts {
-        let mut sim = Simulation::new();
-        // Rolling first so that it uses a module.
-        sim.reroll();
-        sim.set_buff(position, &locked_buff);
-        sim.lock(position);
+    let want = BuffSet::from_iter(want_rest.iter().copied());
 
-        reroll_until_all_found_with_locking(&mut sim, &want);
-        result.record(sim.custom_modules)
-    }
+    let strategy = Strategy::Waited(locked_buff, position);
+    sampler.run(&mut result, |sim| strategy.run(sim, want));
 
     result
 }
 
-pub fn suite_simulation_third_slot_buff_locked() {
-    let _suite_print = SuitePrint::new(
-        "Third slot buff locked",
-        format!(
-            "The following table shows how many custom modules \
-        were used to get the desired buffs. \
-        Given that the THIRD slot has been locked after the first roll \
-        (1 for roll + 2 for locking), \
-        whenever a desired buff appears on a reroll, it is immediately locked. \
-        The cost of locking modules (2+) is accounted. \
-        The simluation rerolls until all the preferred buffs are drawn. And each row shows the \
-        statistics on the number of custom modules used until the preferred buffs are drawn \
-        {} times.",
-            DEFAULT_ATTEMPTS
-        )
-        .as_str(),
-    );
-
-    const SLOT_POSITION: usize = 2;
-
-    let mut data = vec![vec![
-        "locked buff (slot 3)".to_string(),
-        "buff1".to_string(),
-        "buff2".to_string(),
-        "mean (modules)".to_string(),
-        "std dev (modules)".to_string(),
-    ]];
-
-    let results = [
-        // Two buffs.
-        simulation_with_locked_buff(Buff::Attack, SLOT_POSITION, &[Buff::Elemental]),
-        simulation_with_locked_buff(Buff::Attack, SLOT_POSITION, &[Buff::MaxAmmo]),
-        simulation_with_locked_buff(Buff::ChargeDamage, SLOT_POSITION, &[Buff::Attack]),
-        simulation_with_locked_buff(Buff::ChargeDamage, SLOT_POSITION, &[Buff::ChargeSpeed]),
-        // Three buffs.
-        // 10%, [10%, 10%].
-        simulation_with_locked_buff(
-            Buff::Attack,
-            SLOT_POSITION,
-            &[Buff::Elemental, Buff::CritDamage],
-        ),
-        // 10%, [10%, 12%].
-        simulation_with_locked_buff(
-            Buff::Attack,
-            SLOT_POSITION,
-            &[Buff::Elemental, Buff::MaxAmmo],
-        ),
-        // 10%, [12%, 12%].
-        simulation_with_locked_buff(
-            Buff::Attack,
-            SLOT_POSITION,
-            &[Buff::MaxAmmo, Buff::CritDamage],
-        ),
-        // 12%, [10%, 10%].
-        simulation_with_locked_buff(
-            Buff::ChargeDamage,
-            SLOT_POSITION,
-            &[Buff::Attack, Buff::Elemental],
-        ),
-        // 12%, [10%, 12%].
-        simulation_with_locked_buff(
-            Buff::ChargeDamage,
-            SLOT_POSITION,
-            &[Buff::Attack, Buff::MaxAmmo],
-        ),
-        // 12%, [12%, 12%].
-        simulation_with_locked_buff(
-            Buff::ChargeDamage,
-            SLOT_POSITION,
-            &[Buff::MaxAmmo, Buff::ChargeSpeed],
-        ),
-    ];
-
-    for result in results {
-        let second_buff = if result.buffs.len() < 2 {
-            "N/A".to_string()
-        } else {
-            buff_to_string(result.buffs[1])
-        };
-
-        data.push(vec![
-            buffs_to_string([result.locked_buff.unwrap()].iter()),
-            buffs_to_string([result.buffs[0]].iter()),
-            second_buff,
-            format!("{:.3}", result.mean()),
-            format!("{:.3}", result.stddev()),
-        ]);
-    }
-
-    let mut table = Builder::from(data).build();
-    table.with(Style::modern());
-    println!("{table}");
-}
-
 #[cfg(test)]
 mod test {
 
-    use more_asserts::assert_gt;
+    use more_asserts::{assert_gt, assert_lt};
 
     use super::*;
 
@@ -619,8 +1539,8 @@ mod test {
         // Expect it to occur in 10000 attempts.
         for _ in 0..10000 {
             let mut sim = Simulation::new();
-            let want = HashSet::from_iter([Buff::Attack]);
-            reroll_until_all_found(&mut sim, &want);
+            let want = BuffSet::from_iter([Buff::Attack]);
+            reroll_until_all_found(&mut sim, want);
 
             let buffs: Vec<_> = sim
                 .buffs()
@@ -641,7 +1561,7 @@ mod test {
 
             // Make sure that buffs is a superset of want.
             for buff in want.iter() {
-                assert!(buffs.contains(&buff));
+                assert!(buffs.contains(&&buff));
             }
             pass = true;
             break;
@@ -649,4 +1569,278 @@ mod test {
 
         assert!(pass);
     }
+
+    #[test]
+    fn result_percentiles() {
+        let mut result = SimulationResult::new();
+        for modules in 1..=100 {
+            result.record(modules);
+        }
+
+        assert_eq!(result.percentile(50.0), 50);
+        assert_eq!(result.percentile(90.0), 90);
+        assert_eq!(result.percentile(99.0), 99);
+        assert_eq!(result.max(), 100);
+    }
+
+    #[test]
+    fn results_do_not_depend_on_thread_count() {
+        let config = SuiteConfig {
+            modules_stop: StopRule {
+                precision: Some(Precision::Absolute(1.0)),
+                min_samples: 2000,
+                max_samples: 100000,
+                max_duration: None,
+            },
+            seed: 7,
+            ..Default::default()
+        };
+        let run = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                simulation_num_custom_modules_for_specific_buffs(
+                    &[Buff::Attack, Buff::Elemental],
+                    &config.sampler("test"),
+                )
+            })
+        };
+        let (one, four) = (run(1), run(4));
+
+        assert_eq!(one.samples() % sampling::CHUNK_SAMPLES, 0);
+        assert_eq!(one.samples(), four.samples());
+        assert_eq!(one.mean(), four.mean());
+        assert_eq!(one.max(), four.max());
+    }
+
+    // With common random numbers, the suites that want the same buffs use the same generators,
+    // so the same strategy gets the same results in any suite.
+    #[test]
+    fn common_random_numbers_share_seeds_across_suites() {
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(1001),
+            seed: 7,
+            variance_reduction: "both".parse().unwrap(),
+            ..Default::default()
+        };
+        let run = |suite| {
+            simulation_with_locked_buff(Buff::Attack, 2, &[Buff::Elemental], &config.sampler(suite))
+        };
+        let (first, second) = (run("first"), run("second"));
+
+        assert_eq!(first.samples(), 1001);
+        assert_eq!(first.mean(), second.mean());
+        assert_eq!(first.max(), second.max());
+    }
+
+    // Importance sampling needs far fewer rolls than plain sampling for the same precision, so
+    // 20000 rolls are enough to pin down the exact probability of a specific roll.
+    #[test]
+    fn rare_outcome_estimate_covers_exact_probability() {
+        let rule = StopRule::fixed(20000);
+        for outcome in [
+            RareOutcome::Shown([Buff::Attack, Buff::MaxAmmo, Buff::ChargeSpeed]),
+            RareOutcome::InSlots([Buff::Attack, Buff::Elemental, Buff::CritDamage]),
+        ] {
+            let rules = Rules::standard();
+            let exact = importance::exact_probability(&rules, |slots| outcome.happened(slots));
+            let result = sim_rare_outcome(outcome, &rules, &rule, 7);
+            let interval = result.confidence_interval();
+
+            assert_eq!(result.attempts, 20000);
+            assert!(interval.lower <= exact && exact <= interval.upper);
+            assert_lt!(interval.half_width(), exact * 0.1);
+        }
+    }
+
+    // Waiting for the buff in the slot costs the rolls until it appears, which locking it after
+    // the first roll leaves out. Given it appeared, the rolls so far only add up.
+    #[test]
+    fn waiting_for_a_slot_is_accounted() {
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(4000),
+            seed: 7,
+            ..Default::default()
+        };
+        let sampler = config.sampler("test");
+        let want = [Buff::Attack, Buff::Elemental];
+        let locked = simulate_strategy(Strategy::Locked(Buff::Attack, 2), &want, &sampler);
+        let waited = simulate_strategy(Strategy::Waited(Buff::Attack, 2), &want, &sampler);
+        // About 33 rolls for a 3% chance.
+        assert_gt!(waited.mean(), locked.mean() + 20.0);
+
+        let report = suite_shown_in_slot(&config, &want, Buff::Attack, 2, 10);
+        let results: Vec<&SimulationResult> = report
+            .rows
+            .iter()
+            .map(|row| match &row.result {
+                RowResult::Modules(result) => result,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(results[1].mean(), results[0].mean() + 10.0);
+        assert_eq!(results[1].min(), results[0].min() + 10);
+        // The rolls before count as rerolls too.
+        assert_eq!(
+            results[1].outcomes.mean_rerolls().unwrap(),
+            results[0].outcomes.mean_rerolls().unwrap() + 10.0
+        );
+        // Locking Attack and Elemental, when Elemental appeared with it.
+        assert_eq!(results[0].min(), 2 + 3);
+        assert_lt!(results[0].mean(), locked.mean());
+        assert_gt!(results[2].mean(), results[1].mean());
+    }
+
+    #[test]
+    fn strategies_that_never_get_the_buffs_are_left_out() {
+        let rules = Rules::from_toml("test", "third_slot_percent = 0\n").unwrap();
+        let two = [Buff::Attack, Buff::Elemental];
+        let three = [Buff::Attack, Buff::Elemental, Buff::MaxAmmo];
+        assert!(Strategy::NoLocking.check(&rules, &two).is_ok());
+        assert!(Strategy::LockImmediately.check(&rules, &three).is_err());
+        assert!(Strategy::Waited(Buff::Attack, 1)
+            .check(&rules, &two)
+            .is_ok());
+        assert!(Strategy::Waited(Buff::Attack, 2)
+            .check(&rules, &two)
+            .is_err());
+        assert!(Strategy::Locked(Buff::Attack, 1)
+            .check(&rules, &three)
+            .is_err());
+        assert!(Strategy::Locked(Buff::Attack, 0)
+            .check(&Rules::standard(), &three)
+            .is_ok());
+        // Without locks the buff is not kept, so only the others need to be shown.
+        let no_locks = Rules::from_toml("test", "lock_costs = []\nreroll_costs = [1]\n").unwrap();
+        assert!(Strategy::Locked(Buff::Attack, 0)
+            .check(&no_locks, &three)
+            .is_ok());
+
+        let config = SuiteConfig {
+            rules: Arc::new(
+                Rules::from_toml("test", "second_slot_percent = 0\nthird_slot_percent = 0\n")
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let report = suite_two_desired_buffs_custom_mod_usage(&config, Coverage::Representative);
+        assert!(report.rows.is_empty());
+        assert!(report.description.ends_with("are left out."));
+    }
+
+    // The rows stop by the samples alone, so that a seed reproduces the results.
+    #[test]
+    fn default_sampling_has_no_time_budget() {
+        let config = SuiteConfig::default();
+        for rule in [&config.modules_stop, &config.probability_stop] {
+            assert_eq!(rule.max_duration, None);
+            assert!(!rule.describe("").contains("seconds"));
+        }
+    }
+
+    #[test]
+    fn labels_and_descriptions_follow_the_rules() {
+        let rules = Rules::from_toml(
+            "test",
+            "lock_costs = [4]\nreroll_costs = [1, 2]\n[buff_percents]\nAttack = 50\n",
+        )
+        .unwrap();
+        assert_eq!(buff_to_string(&rules, Buff::Attack), "Attack(50%)");
+        assert_eq!(
+            buffs_to_string(&Rules::standard(), [Buff::Attack].iter()),
+            format!("Attack({}%)", Buff::Attack.percent())
+        );
+        assert_eq!(
+            lock_cost_note(&rules),
+            "The cost of locking modules (4) is accounted."
+        );
+        assert_eq!(
+            lock_cost_note(&Rules::standard()),
+            "The cost of locking modules (2, then 3) is accounted."
+        );
+    }
+
+    #[test]
+    fn outcomes_are_recorded() {
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(2000),
+            seed: 7,
+            ..Default::default()
+        };
+        let sampler = config.sampler("test");
+        let want = [Buff::Attack, Buff::Elemental];
+
+        let result = simulate_strategy(Strategy::LockImmediately, &want, &sampler);
+        let outcomes = &result.outcomes;
+        assert_eq!(outcomes.runs, 2000);
+        // Both buffs are always locked, for 2 and 3 modules.
+        assert_eq!(outcomes.mean_locks(), Some(2.0));
+        assert_eq!(outcomes.mean_lock_modules(), Some(5.0));
+        let spent = outcomes.mean_reroll_modules().unwrap() + outcomes.mean_lock_modules().unwrap();
+        assert_lt!((spent - result.mean()).abs(), 0.01 * result.mean());
+        for shares in outcomes.slot_shares().unwrap() {
+            assert_lt!((shares.iter().sum::<f64>() - 1.0).abs(), 1e-9);
+        }
+        assert_eq!(
+            Column::Slots.json(&result)[0].0,
+            format!("slots_{:?}_1", Buff::Attack)
+        );
+
+        let result = simulate_strategy(Strategy::Locked(Buff::Attack, 2), &want, &sampler);
+        assert_eq!(result.outcomes.slot_shares().unwrap()[0], [0.0, 0.0, 1.0]);
+        assert_eq!(result.outcomes.third_slot_share(), Some(1.0));
+        assert_eq!(Column::ThirdSlot.value(&result), "100.00%");
+        assert_eq!(Column::Rerolls.value(&SimulationResult::new()), "-");
+    }
+
+    // Keeps the runs.
+    #[derive(Default)]
+    struct Runs(std::sync::Mutex<Vec<RunOutcome>>);
+
+    impl RunSink for Runs {
+        fn run(&self, _: &str, _: &SimulationResult, run: &RunOutcome) {
+            self.0.lock().unwrap().push(*run);
+        }
+    }
+
+    #[test]
+    fn modules_are_split_between_rerolls_and_locks() {
+        let runs = Arc::new(Runs::default());
+        let config = SuiteConfig {
+            modules_stop: StopRule::fixed(500),
+            seed: 7,
+            runs: Some(runs.clone()),
+            ..Default::default()
+        };
+        let sampler = config.sampler("test");
+        let want = [Buff::Attack, Buff::Elemental];
+        let reroll_cost = config.rules.reroll_cost(0).unwrap();
+        for strategy in [
+            Strategy::NoLocking,
+            Strategy::LockImmediately,
+            Strategy::Locked(Buff::Attack, 2),
+            Strategy::Waited(Buff::Attack, 2),
+        ] {
+            simulate_strategy(strategy, &want, &sampler);
+        }
+        // The rolls before Attack appeared are rerolls too.
+        suite_shown_in_slot(&config, &want, Buff::Attack, 2, 10);
+
+        let runs = runs.0.lock().unwrap();
+        assert_eq!(runs.len(), 4 * 500 + 3 * 500);
+        for run in runs.iter() {
+            assert_eq!(
+                run.reroll_modules + run.lock_modules,
+                run.modules,
+                "{run:?}"
+            );
+            // Rerolls with locks cost more.
+            assert!(run.reroll_modules >= run.rerolls * reroll_cost, "{run:?}");
+            if run.locks == 0 {
+                assert_eq!(run.reroll_modules, run.rerolls * reroll_cost, "{run:?}");
+            }
+        }
+    }
 }
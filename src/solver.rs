@@ -0,0 +1,381 @@
+// An exact solver for getting the wanted buffs. The chances of all the rolls that can follow the
+// locked buffs are known, so the custom modules that rerolling and locking are expected to use can
+// be calculated instead of simulated, for the best choice after every roll. With a limited stock
+// of modules, the solver first makes getting the buffs before the modules run out as likely as
+// possible, then uses as few modules as possible.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::importance;
+use crate::rules::Rules;
+use crate::{Buff, BuffSet, SlotState};
+
+/// The largest stock of custom modules that the solver goes through. Larger stocks are
+/// simulated instead.
+pub const MAX_STOCK: u32 = 2000;
+
+// Chances and module counts closer than this are the same.
+const EPSILON: f64 = 1e-9;
+
+/// How a state turns out when the best choice is made after every roll.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outlook {
+    /// The chance (0 to 1) of getting all the wanted buffs.
+    pub success: f64,
+    /// The custom modules expected to be used from the state, whether or not the buffs are got.
+    pub modules: f64,
+}
+
+impl Outlook {
+    /// Stopping without the wanted buffs.
+    pub const STOP: Outlook = Outlook {
+        success: 0.0,
+        modules: 0.0,
+    };
+    /// Stopping with all the wanted buffs.
+    pub const DONE: Outlook = Outlook {
+        success: 1.0,
+        modules: 0.0,
+    };
+
+    /// Whether this is more likely to get the buffs than |other|, or as likely and cheaper.
+    pub fn is_better_than(&self, other: &Outlook) -> bool {
+        if (self.success - other.success).abs() > EPSILON {
+            self.success > other.success
+        } else {
+            self.modules < other.modules - EPSILON
+        }
+    }
+
+    fn plus(self, modules: u32) -> Outlook {
+        Outlook {
+            success: self.success,
+            modules: self.modules + modules as f64,
+        }
+    }
+}
+
+/// The locked buff of each slot, if any.
+pub(crate) type Locks = [Option<Buff>; 3];
+
+/// The locks of |slots|.
+pub(crate) fn locks(slots: &[SlotState; 3]) -> Locks {
+    slots.map(|slot| match slot {
+        SlotState::Locked(buff) => Some(buff),
+        _ => None,
+    })
+}
+
+pub(crate) fn locked_count(locks: Locks) -> u32 {
+    locks.iter().flatten().count() as u32
+}
+
+// The wanted buffs that a roll shows in the slots that are not locked. The other buffs do not
+// matter for the choices.
+type Roll = [Option<Buff>; 3];
+
+/// Solves how to get the wanted buffs from any state with the rules, with an unlimited or a
+/// limited stock of custom modules. The states that the queries need are solved on the way and
+/// kept for the later queries.
+pub struct Solver {
+    rules: Arc<Rules>,
+    want: BuffSet,
+    stock: Option<u32>,
+    // The outlooks of rerolling after each of the locks that are solved, by the modules left from
+    // 0 to the stock, or the only outlook without a stock.
+    rerolls: HashMap<Locks, Vec<Outlook>>,
+}
+
+impl Solver {
+    /// A solver for getting |want| with a |stock| of modules, or unlimited modules. None if the
+    /// solver does not apply: the stock is above MAX_STOCK, or a reroll is free so that a stock
+    /// would never run out.
+    pub fn new(rules: Arc<Rules>, want: BuffSet, stock: Option<u32>) -> Option<Solver> {
+        if let Some(stock) = stock {
+            if stock > MAX_STOCK || rules.reroll_costs.contains(&0) {
+                return None;
+            }
+        }
+        Some(Solver {
+            rules,
+            want,
+            stock,
+            rerolls: HashMap::new(),
+        })
+    }
+
+    /// The outlook of rerolling |slots| with |stock| modules left, which is None for unlimited
+    /// modules like the solver's stock. A reroll that the stock cannot pay for is stopping.
+    pub fn reroll(&mut self, slots: &[SlotState; 3], stock: Option<u32>) -> Outlook {
+        let (locks, _) = self.split(slots);
+        self.solve(locks);
+        self.reroll_outlook(locks, self.left(stock))
+    }
+
+    /// The outlook of the roll |slots| with |stock| modules left when the best choice is made:
+    /// stopping, rerolling or locking one of its wanted buffs.
+    pub fn best(&mut self, slots: &[SlotState; 3], stock: Option<u32>) -> Outlook {
+        let (locks, roll) = self.split(slots);
+        if self.is_done(locks, roll) {
+            return Outlook::DONE;
+        }
+        self.solve(locks);
+        let left = self.left(stock);
+        let reroll = self.reroll_outlook(locks, left);
+        self.decide(locks, roll, left, Some(reroll))
+    }
+
+    // The index of |stock| in the rerolls.
+    fn left(&self, stock: Option<u32>) -> usize {
+        assert_eq!(stock.is_some(), self.stock.is_some());
+        assert!(stock <= self.stock, "{stock:?} is above the solver's stock");
+        stock.unwrap_or(0) as usize
+    }
+
+    // The locks and the roll of |slots|.
+    fn split(&self, slots: &[SlotState; 3]) -> (Locks, Roll) {
+        let roll = slots.map(|slot| match slot {
+            SlotState::Free(buff) if self.want.contains(buff) => Some(buff),
+            _ => None,
+        });
+        (locks(slots), roll)
+    }
+
+    fn is_done(&self, locks: Locks, roll: Roll) -> bool {
+        let shown: BuffSet = locks.iter().chain(&roll).flatten().copied().collect();
+        shown.is_superset(self.want)
+    }
+
+    fn reroll_outlook(&self, locks: Locks, left: usize) -> Outlook {
+        self.rerolls[&locks][left]
+    }
+
+    // The best outlook of the roll |roll| after |locks| with |left| modules: stopping, the
+    // |reroll| outlook if it is a choice, or locking one of the wanted buffs of the roll. The
+    // locks after locking them must be solved.
+    fn decide(&self, locks: Locks, roll: Roll, left: usize, reroll: Option<Outlook>) -> Outlook {
+        let mut best = Outlook::STOP;
+        if let Some(reroll) = reroll {
+            if reroll.is_better_than(&best) {
+                best = reroll;
+            }
+        }
+        let Some(cost) = self.rules.lock_cost(locked_count(locks)) else {
+            return best;
+        };
+        if self.stock.is_some() && cost as usize > left {
+            return best;
+        }
+        let left = if self.stock.is_some() {
+            left - cost as usize
+        } else {
+            left
+        };
+        for pos in 0..3 {
+            let Some(buff) = roll[pos] else {
+                continue;
+            };
+            let mut next = locks;
+            next[pos] = Some(buff);
+            let mut rest = roll;
+            rest[pos] = None;
+            let reroll = self.reroll_outlook(next, left);
+            let outlook = self.decide(next, rest, left, Some(reroll)).plus(cost);
+            if outlook.is_better_than(&best) {
+                best = outlook;
+            }
+        }
+        best
+    }
+
+    // Solves rerolling after |locks|, and first after the locks that locking more wanted buffs
+    // leads to.
+    fn solve(&mut self, locks: Locks) {
+        if self.rerolls.contains_key(&locks) {
+            return;
+        }
+        let locked: BuffSet = locks.iter().flatten().copied().collect();
+        if self.rules.lock_cost(locked_count(locks)).is_some() {
+            for pos in (0..3).filter(|pos| locks[*pos].is_none()) {
+                for buff in self.want.iter().filter(|buff| !locked.contains(*buff)) {
+                    let mut next = locks;
+                    next[pos] = Some(buff);
+                    self.solve(next);
+                }
+            }
+        }
+
+        // The different rolls that can follow the locks, and their probabilities.
+        let mut rolls: Vec<(Roll, f64)> = vec![];
+        importance::for_each_roll(&self.rules, locks, |slots, probability| {
+            let mut roll = [None; 3];
+            for pos in (0..3).filter(|pos| locks[*pos].is_none()) {
+                roll[pos] = slots[pos].filter(|buff| self.want.contains(*buff));
+            }
+            match rolls.iter_mut().find(|(other, _)| *other == roll) {
+                Some((_, sum)) => *sum += probability,
+                None => rolls.push((roll, probability)),
+            }
+        });
+        let rerolls = match self.stock {
+            Some(stock) => self.rerolls_with_stock(locks, &rolls, stock),
+            None => vec![self.reroll_without_stock(locks, &rolls)],
+        };
+        self.rerolls.insert(locks, rerolls);
+    }
+
+    // Rerolling with each stock from 0 up. A reroll costs at least one module, so the outlooks
+    // after it have the smaller stocks that are solved already.
+    fn rerolls_with_stock(&self, locks: Locks, rolls: &[(Roll, f64)], stock: u32) -> Vec<Outlook> {
+        let cost = self.rules.reroll_cost(locked_count(locks)).unwrap();
+        let mut rerolls: Vec<Outlook> = vec![];
+        for left in 0..=stock {
+            let Some(after) = left.checked_sub(cost) else {
+                rerolls.push(Outlook::STOP);
+                continue;
+            };
+            let mut outlook = Outlook::STOP;
+            for (roll, probability) in rolls {
+                let next = if self.is_done(locks, *roll) {
+                    Outlook::DONE
+                } else {
+                    let reroll = rerolls[after as usize];
+                    self.decide(locks, *roll, after as usize, Some(reroll))
+                };
+                outlook.success += probability * next.success;
+                outlook.modules += probability * next.modules;
+            }
+            // The sum of the chances can be a little above 1.
+            outlook.success = outlook.success.min(1.0);
+            rerolls.push(outlook.plus(cost));
+        }
+        rerolls
+    }
+
+    // Rerolling with unlimited modules, which is rerolling until one of the rolls that are better
+    // to keep than to reroll shows. Those are the rolls whose outlooks are below the expected
+    // modules of rerolling, so the best choice keeps the cheapest rolls up to the count where the
+    // expected modules are the lowest.
+    fn reroll_without_stock(&self, locks: Locks, rolls: &[(Roll, f64)]) -> Outlook {
+        let cost = self.rules.reroll_cost(locked_count(locks)).unwrap() as f64;
+        let mut kept: Vec<(f64, f64)> = rolls
+            .iter()
+            .filter_map(|(roll, probability)| {
+                if self.is_done(locks, *roll) {
+                    return Some((0.0, *probability));
+                }
+                let outlook = self.decide(locks, *roll, 0, None);
+                (outlook.success > 0.5).then_some((outlook.modules, *probability))
+            })
+            .collect();
+        kept.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut best = f64::INFINITY;
+        let (mut probability, mut modules) = (0.0, 0.0);
+        for (outlook, chance) in kept {
+            probability += chance;
+            modules += chance * outlook;
+            if probability > 0.0 {
+                best = best.min((cost + modules) / probability);
+            }
+        }
+        if best.is_finite() {
+            Outlook {
+                success: 1.0,
+                modules: best,
+            }
+        } else {
+            Outlook::STOP
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use more_asserts::assert_lt;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::importance::exact_probability;
+    use crate::simulation::Strategy;
+    use crate::Simulation;
+
+    const EMPTY: [SlotState; 3] = [SlotState::None; 3];
+
+    // A single wanted buff is never worth locking, so it takes a geometric number of rerolls.
+    #[test]
+    fn single_buff_takes_geometric_rerolls() {
+        let rules = Rules::standard();
+        let want = BuffSet::from_iter([Buff::Attack]);
+        let shown = exact_probability(&rules, |slots| slots.contains(&Some(Buff::Attack)));
+        let mut solver = Solver::new(rules.clone(), want, None).unwrap();
+        let outlook = solver.reroll(&EMPTY, None);
+        assert_eq!(outlook.success, 1.0);
+        assert_lt!((outlook.modules - 1.0 / shown).abs(), 1e-9);
+
+        let mut solver = Solver::new(rules, want, Some(3)).unwrap();
+        let outlook = solver.reroll(&EMPTY, Some(3));
+        let failure = (1.0 - shown).powi(3);
+        assert_lt!((outlook.success - (1.0 - failure)).abs(), 1e-9);
+        assert_lt!(
+            (outlook.modules - (1.0 + (1.0 - shown) + (1.0 - shown).powi(2))).abs(),
+            1e-9
+        );
+    }
+
+    // With a large stock the modules almost never run out, so the outlook approaches the one
+    // with unlimited modules.
+    #[test]
+    fn large_stock_approaches_unlimited_modules() {
+        let rules = Rules::standard();
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental]);
+        let unlimited = Solver::new(rules.clone(), want, None)
+            .unwrap()
+            .reroll(&EMPTY, None);
+        let stock = 600;
+        let limited = Solver::new(rules, want, Some(stock))
+            .unwrap()
+            .reroll(&EMPTY, Some(stock));
+        assert_lt!(1.0 - limited.success, 1e-6);
+        assert_lt!((limited.modules - unlimited.modules).abs(), 1e-3);
+        // Stopping with a roll that shows the buffs.
+        let slots = [
+            SlotState::Free(Buff::Elemental),
+            SlotState::Free(Buff::Attack),
+            SlotState::None,
+        ];
+        let mut solver = Solver::new(Rules::standard(), want, None).unwrap();
+        assert_eq!(solver.best(&slots, None), Outlook::DONE);
+    }
+
+    // The simulation rerolls the same way, so it must agree with the solver.
+    #[test]
+    fn simulation_agrees_with_solver() {
+        let want = BuffSet::from_iter([Buff::Attack]);
+        let exact = Solver::new(Rules::standard(), want, None)
+            .unwrap()
+            .reroll(&EMPTY, None);
+        let mut rng = StdRng::seed_from_u64(1);
+        let runs = 20000;
+        let total: u32 = (0..runs)
+            .map(|_| Strategy::NoLocking.run(&mut Simulation::with_rng(&mut rng), want))
+            .sum();
+        let mean = total as f64 / runs as f64;
+        assert_lt!((mean / exact.modules - 1.0).abs(), 0.03);
+    }
+
+    #[test]
+    fn impossible_goal_stops() {
+        let want = BuffSet::from_iter([Buff::Attack, Buff::Elemental, Buff::CritDamage]);
+        let slots = [
+            SlotState::Locked(Buff::Defense),
+            SlotState::None,
+            SlotState::None,
+        ];
+        let mut solver = Solver::new(Rules::standard(), want, None).unwrap();
+        assert_eq!(solver.reroll(&slots, None), Outlook::STOP);
+        assert_eq!(solver.best(&slots, None), Outlook::STOP);
+        assert!(Solver::new(Rules::standard(), want, Some(MAX_STOCK + 1)).is_none());
+    }
+}
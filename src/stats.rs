@@ -0,0 +1,140 @@
+// Statistics helpers for reporting the Monte Carlo error of the simulations.
+
+/// The z-score of a two sided 95% confidence interval.
+pub const Z_95: f64 = 1.959964;
+
+/// A closed interval [lower, upper].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Interval {
+    pub fn half_width(&self) -> f64 {
+        (self.upper - self.lower) / 2.0
+    }
+
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.lower <= other.upper && other.lower <= self.upper
+    }
+}
+
+/// Standard error of a proportion estimated from |hits| out of |trials|.
+pub fn proportion_standard_error(hits: u64, trials: u64) -> f64 {
+    if trials == 0 {
+        return 0.0;
+    }
+    let p = hits as f64 / trials as f64;
+    (p * (1.0 - p) / trials as f64).sqrt()
+}
+
+/// Wilson score interval of a proportion. Unlike the normal approximation, this behaves well
+/// for rare events, e.g. 138 hits out of 100000, and never goes below 0 or above 1.
+pub fn wilson_interval(hits: u64, trials: u64, z: f64) -> Interval {
+    if trials == 0 {
+        return Interval {
+            lower: 0.0,
+            upper: 1.0,
+        };
+    }
+    let n = trials as f64;
+    let p = hits as f64 / n;
+    let z2 = z * z;
+
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    Interval {
+        lower: (center - margin).max(0.0),
+        upper: (center + margin).min(1.0),
+    }
+}
+
+/// Standard error of a sample mean.
+pub fn mean_standard_error(stddev: f64, samples: u64) -> f64 {
+    if samples == 0 {
+        return 0.0;
+    }
+    stddev / (samples as f64).sqrt()
+}
+
+/// Normal approximation interval of a sample mean. The number of samples in the simulations is
+/// large enough for the central limit theorem to apply even though the distribution is skewed.
+pub fn mean_interval(mean: f64, stddev: f64, samples: u64, z: f64) -> Interval {
+    let margin = z * mean_standard_error(stddev, samples);
+    Interval {
+        lower: mean - margin,
+        upper: mean + margin,
+    }
+}
+
+/// For each interval, lists the (1 based) indices of the other intervals that overlap with it.
+/// The estimates of those rows cannot be distinguished from each other.
+pub fn overlapping_rows(intervals: &[Interval]) -> Vec<Vec<usize>> {
+    intervals
+        .iter()
+        .enumerate()
+        .map(|(i, interval)| {
+            intervals
+                .iter()
+                .enumerate()
+                .filter(|(j, other)| i != *j && interval.overlaps(other))
+                .map(|(j, _)| j + 1)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn wilson_interval_contains_estimate() {
+        let interval = wilson_interval(138, 100000, Z_95);
+        let p = 138.0 / 100000.0;
+
+        assert!(interval.lower < p && p < interval.upper);
+        assert!(interval.lower > 0.0);
+        // Roughly 0.116% to 0.163%.
+        assert!((interval.lower - 0.00116).abs() < 0.00002);
+        assert!((interval.upper - 0.00163).abs() < 0.00002);
+    }
+
+    #[test]
+    fn wilson_interval_no_hits() {
+        let interval = wilson_interval(0, 1000, Z_95);
+        assert!(interval.lower < 1e-12);
+        assert!(interval.upper > 0.0);
+    }
+
+    #[test]
+    fn mean_interval_shrinks_with_samples() {
+        let small = mean_interval(24.0, 22.0, 100, Z_95);
+        let large = mean_interval(24.0, 22.0, 10000, Z_95);
+
+        assert!((small.upper - 24.0 - 4.312).abs() < 0.001);
+        assert!((large.upper - 24.0 - 0.4312).abs() < 0.0001);
+    }
+
+    #[test]
+    fn overlapping_rows_flags_indistinguishable() {
+        let intervals = [
+            Interval {
+                lower: 1.0,
+                upper: 2.0,
+            },
+            Interval {
+                lower: 1.5,
+                upper: 2.5,
+            },
+            Interval {
+                lower: 3.0,
+                upper: 4.0,
+            },
+        ];
+
+        assert_eq!(overlapping_rows(&intervals), vec![vec![2], vec![1], vec![]]);
+    }
+}
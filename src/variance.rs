@@ -0,0 +1,222 @@
+// Measures how much the variance reduction options reduce the variance of the difference between
+// two strategies that want the same buffs, which is how the suites compare the strategies.
+
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use tabled::{builder::Builder, settings::style::Style};
+
+use crate::rules::Rules;
+use crate::sampling::{self, SampleRng, VarianceReduction, CHUNK_SAMPLES};
+use crate::simulation::{buffs_to_string, Strategy};
+use crate::{Buff, BuffSet, Simulation};
+
+// The runs of each strategy, for each option.
+const RUNS: u64 = 100000;
+
+// The options that are compared with plain sampling.
+const OPTIONS: [&str; 3] = ["crn", "antithetic", "both"];
+
+/// Two strategies that want the same buffs.
+pub struct Comparison {
+    /// All the wanted buffs, including a locked one.
+    pub buffs: Vec<Buff>,
+    /// The buffs that the strategies reroll for.
+    pub want: BuffSet,
+    pub strategies: [Strategy; 2],
+}
+
+/// The comparisons that the suites make: locking immediately or not, and which slot to lock.
+pub fn comparisons() -> Vec<Comparison> {
+    let slots = |want: &[Buff], first: usize, second: usize| Comparison {
+        buffs: [&[Buff::Attack], want].concat(),
+        want: BuffSet::from_iter(want.iter().copied()),
+        strategies: [
+            Strategy::Locked(Buff::Attack, first),
+            Strategy::Locked(Buff::Attack, second),
+        ],
+    };
+    vec![
+        Comparison {
+            buffs: vec![Buff::Attack, Buff::Elemental],
+            want: BuffSet::from_iter([Buff::Attack, Buff::Elemental]),
+            strategies: [Strategy::NoLocking, Strategy::LockImmediately],
+        },
+        slots(&[Buff::Elemental], 0, 1),
+        slots(&[Buff::Elemental], 0, 2),
+        slots(&[Buff::Elemental], 1, 2),
+        slots(&[Buff::Elemental, Buff::CritDamage], 0, 2),
+    ]
+}
+
+/// The difference between the mean custom module usage of the strategies of a comparison.
+pub struct Measurement {
+    pub comparison: Comparison,
+    /// The first strategy's mean minus the second's, with plain sampling.
+    pub difference: f64,
+    /// The standard error of |difference|.
+    pub std_error: f64,
+    /// The variance of the difference with plain sampling divided by its variance with each of
+    /// OPTIONS, for the same number of runs.
+    pub reductions: Vec<f64>,
+}
+
+// Runs |strategy| once with |rng|.
+fn run(
+    rules: &Arc<Rules>,
+    strategy: Strategy,
+    want: BuffSet,
+    mut rng: SampleRng,
+    synchronized: bool,
+) -> f64 {
+    let mut sim = Simulation::with_rules(rules.clone(), &mut rng);
+    if synchronized {
+        sim.synchronize_draws();
+    }
+    strategy.run(&mut sim, want) as f64
+}
+
+// The mean of the strategy when it runs from |seed|: the mean of the run and its antithetic run
+// with antithetic variates.
+fn strategy_mean(
+    rules: &Arc<Rules>,
+    strategy: Strategy,
+    want: BuffSet,
+    seed: u64,
+    reduction: VarianceReduction,
+) -> f64 {
+    let synchronized = reduction.common_random_numbers;
+    let modules = run(
+        rules,
+        strategy,
+        want,
+        SampleRng::new(StdRng::seed_from_u64(seed)),
+        synchronized,
+    );
+    if !reduction.antithetic {
+        return modules;
+    }
+    let antithetic = run(
+        rules,
+        strategy,
+        want,
+        SampleRng::antithetic(StdRng::seed_from_u64(seed)),
+        synchronized,
+    );
+    (modules + antithetic) / 2.0
+}
+
+// The estimate of the difference between the strategies of |comparison| from RUNS runs of each,
+// and the variance of the estimate.
+fn difference(
+    rules: &Arc<Rules>,
+    comparison: &Comparison,
+    reduction: VarianceReduction,
+    seed: u64,
+) -> (f64, f64) {
+    // An antithetic pair is two runs.
+    let units = if reduction.antithetic { RUNS / 2 } else { RUNS };
+    let [first, second] = comparison.strategies;
+    let differences: Vec<f64> = (0..units.div_ceil(CHUNK_SAMPLES))
+        .into_par_iter()
+        .flat_map_iter(|chunk| {
+            let mut rng = sampling::chunk_rng(seed, chunk);
+            let count = CHUNK_SAMPLES.min(units - chunk * CHUNK_SAMPLES);
+            (0..count)
+                .map(|_| {
+                    let first_seed = rng.next_u64();
+                    let second_seed = if reduction.common_random_numbers {
+                        first_seed
+                    } else {
+                        rng.next_u64()
+                    };
+                    strategy_mean(rules, first, comparison.want, first_seed, reduction)
+                        - strategy_mean(rules, second, comparison.want, second_seed, reduction)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let n = differences.len() as f64;
+    let mean = differences.iter().sum::<f64>() / n;
+    let variance = differences
+        .iter()
+        .map(|difference| (difference - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    (mean, variance / n)
+}
+
+/// Measures every comparison whose strategies get the buffs with |rules|, with plain sampling and
+/// with each option, with generators seeded from |seed|.
+pub fn measure(rules: &Arc<Rules>, seed: u64) -> Vec<Measurement> {
+    comparisons()
+        .into_iter()
+        // The strategies that never get the buffs with the rules would never finish.
+        .filter(|comparison| {
+            let [first, second] = comparison.strategies;
+            first.check(rules, &comparison.buffs).is_ok()
+                && second.check(rules, &comparison.buffs).is_ok()
+        })
+        .map(|comparison| {
+            let label = format!("{:?}/{:?}", comparison.buffs, comparison.strategies);
+            let seed_of = |option: &str| sampling::derive_seed(seed, &format!("{label}/{option}"));
+            let (difference, plain) = self::difference(
+                rules,
+                &comparison,
+                VarianceReduction::default(),
+                seed_of("none"),
+            );
+            let reductions = OPTIONS
+                .iter()
+                .map(|option| {
+                    let reduction = option.parse().unwrap();
+                    plain / self::difference(rules, &comparison, reduction, seed_of(option)).1
+                })
+                .collect();
+            Measurement {
+                comparison,
+                difference,
+                std_error: plain.sqrt(),
+                reductions,
+            }
+        })
+        .collect()
+}
+
+/// The measurements, made with |rules|, as a table.
+pub fn table(rules: &Rules, measurements: &[Measurement]) -> String {
+    let mut builder = Builder::default();
+    builder.push_record([
+        "buffs",
+        "strategies",
+        "difference (modules)",
+        "std error (modules)",
+        "common random numbers",
+        "antithetic variates",
+        "both",
+    ]);
+    for measurement in measurements {
+        let [first, second] = measurement.comparison.strategies;
+        let mut record = vec![
+            buffs_to_string(rules, measurement.comparison.buffs.iter()),
+            format!("{} - {}", first.name(), second.name()),
+            format!("{:.3}", measurement.difference),
+            format!("{:.3}", measurement.std_error),
+        ];
+        record.extend(
+            measurement
+                .reductions
+                .iter()
+                .map(|reduction| format!("{reduction:.2}x")),
+        );
+        builder.push_record(record);
+    }
+    let mut table = builder.build();
+    table.with(Style::modern());
+    format!(
+        "Variance reduction of the difference between two strategies, {RUNS} runs of each \
+        strategy for each option. A reduction of 4x halves the standard error.\n{table}\n"
+    )
+}